@@ -72,8 +72,8 @@ pub trait Action {
 }
 
 pub struct BaseAction {
-    m_name: String,
-    m_parser_payload: String,
+    pub m_name: String,
+    pub m_parser_payload: String,
 }
 
 impl BaseAction {
@@ -87,15 +87,19 @@ impl BaseAction {
         base_action
     }
 
+    /// Split `name:payload`, e.g. `ctl:auditEngine=Off` or
+    /// `msg:'some message'`, removing the quotes around the payload.
+    /// Transformations, e.g. `t:lowercase`, are a name without payload.
     pub fn set_name_and_payload(&mut self, data: &str) {
-        let t = "t:";
-        let dt = data.trim_start_matches(t);
-        match dt.find(":") {
+        let start = if data.starts_with("t:") { 2 } else { 0 };
+        match data[start..].find(':') {
             None => self.m_name = data.to_string(),
             Some(i) => {
-                let (m_name, mut m_payload) = data.split_at(i);
-                m_payload = m_payload.trim_start_matches("'");
-                m_payload = m_payload.get(0..m_payload.len() - 1).unwrap();
+                let (m_name, m_payload) = data.split_at(start + i);
+                let mut m_payload = &m_payload[1..];
+                if m_payload.len() > 2 && m_payload.starts_with('\'') && m_payload.ends_with('\'') {
+                    m_payload = &m_payload[1..m_payload.len() - 1];
+                }
 
                 self.m_name = m_name.to_string();
                 self.m_parser_payload = m_payload.to_string();
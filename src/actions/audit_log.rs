@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::ms_dbg_rule;
 use crate::rules::{RuleMessage, RuleWithActions};
 use crate::transaction::Transaction;
 
@@ -58,6 +59,10 @@ impl Action for AuditLog {
         transaction: &Transaction,
         rm: std::rc::Rc<RuleMessage>,
     ) -> bool {
-        todo!()
+        rm.m_no_audit_log.set(false);
+        ms_dbg_rule!(transaction, 9, rm.m_rule_id, "Saving transaction to logs");
+        rm.m_save_message.set(true);
+
+        true
     }
 }
@@ -1,14 +1,66 @@
 // Copyright 2023 young2j
-// 
+//
 // Licensed under the Apache License, Version 2.0 (the "License");
 // you may not use this file except in compliance with the License.
 // You may obtain a copy of the License at
-// 
+//
 //     http://www.apache.org/licenses/LICENSE-2.0
-// 
+//
 // Unless required by applicable law or agreed to in writing, software
 // distributed under the License is distributed on an "AS IS" BASIS,
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::audit_log::AuditLogStatus;
+use crate::ms_dbg;
+use crate::rules::RuleWithActions;
+use crate::transaction::Transaction;
+
+use crate::actions::action::{Action, BaseAction, Kind};
+
+/// `ctl:auditEngine=On|Off|RelevantOnly`, overrides SecAuditEngine for the
+/// current transaction.
+pub struct AuditEngine {
+    base: BaseAction,
+    _action_kind: Kind,
+    m_audit_engine: AuditLogStatus,
+}
+
+impl AuditEngine {
+    pub fn new(action: &str) -> Result<AuditEngine, String> {
+        let base = BaseAction::new(action);
+        let what = base
+            .m_parser_payload
+            .strip_prefix("auditEngine=")
+            .unwrap_or_default();
+        let m_audit_engine = AuditLogStatus::from_directive(what)
+            .map_err(|_| format!("Invalid argument for ctl:auditEngine: {}", what))?;
+
+        Ok(AuditEngine {
+            base,
+            _action_kind: Kind::RunTimeOnlyIfMatchKind,
+            m_audit_engine,
+        })
+    }
+}
+
+impl Action for AuditEngine {
+    fn evaluate(&self, value: &str, transaction: &Transaction) -> String {
+        self.base.evaluate(value, transaction)
+    }
+
+    fn evaluate_rule_actions(&self, _ra: &RuleWithActions, transaction: &Transaction) -> bool {
+        ms_dbg!(
+            transaction,
+            8,
+            "Setting audit engine to: {}",
+            self.m_audit_engine.as_str()
+        );
+        transaction
+            .m_ctl_audit_engine
+            .set(Some(self.m_audit_engine));
+
+        true
+    }
+}
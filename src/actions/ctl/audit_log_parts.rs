@@ -0,0 +1,72 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::audit_log::parse_parts;
+use crate::rules::RuleWithActions;
+use crate::transaction::Transaction;
+
+use crate::actions::action::{Action, BaseAction, Kind};
+
+/// `ctl:auditLogParts=+E` or `ctl:auditLogParts=-C`, adds or removes parts
+/// of SecAuditLogParts for the current transaction.
+pub struct AuditLogParts {
+    base: BaseAction,
+    _action_kind: Kind,
+    /// true to add the parts, false to remove them.
+    m_parts_action: bool,
+    m_parts: i32,
+}
+
+impl AuditLogParts {
+    pub fn new(action: &str) -> Result<AuditLogParts, String> {
+        let base = BaseAction::new(action);
+        let payload = base
+            .m_parser_payload
+            .strip_prefix("auditLogParts=")
+            .unwrap_or_default();
+        let m_parts_action = match payload.chars().next() {
+            Some('+') => true,
+            Some('-') => false,
+            what => {
+                return Err(format!(
+                    "ctl:auditLogParts modificators expects add or remove (+/-) in front of the modificator. Got: {}",
+                    what.map(String::from).unwrap_or_default()
+                ))
+            }
+        };
+        let m_parts = parse_parts(&payload[1..])?;
+
+        Ok(AuditLogParts {
+            base,
+            _action_kind: Kind::RunTimeOnlyIfMatchKind,
+            m_parts_action,
+            m_parts,
+        })
+    }
+}
+
+impl Action for AuditLogParts {
+    fn evaluate(&self, value: &str, transaction: &Transaction) -> String {
+        self.base.evaluate(value, transaction)
+    }
+
+    fn evaluate_rule_actions(&self, _ra: &RuleWithActions, transaction: &Transaction) -> bool {
+        transaction
+            .m_audit_log_modifier
+            .borrow_mut()
+            .push((self.m_parts_action, self.m_parts));
+
+        true
+    }
+}
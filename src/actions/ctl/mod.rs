@@ -13,3 +13,4 @@
 // limitations under the License.
 
 pub mod audit_engine;
+pub mod audit_log_parts;
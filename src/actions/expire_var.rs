@@ -0,0 +1,71 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::rules::RuleWithActions;
+use crate::transaction::Transaction;
+
+use super::action::{Action, BaseAction, Kind};
+
+/// `expirevar:IP.score=60`, expires a variable of TX or of the record of
+/// the transaction in a persistent collection after the seconds given.
+pub struct ExpireVar {
+    base: BaseAction,
+    _action_kind: Kind,
+    m_collection: String,
+    m_key: String,
+    m_expiry_seconds: i64,
+}
+
+impl ExpireVar {
+    pub fn new(action: &str) -> Result<ExpireVar, String> {
+        let base = BaseAction::new(action);
+        let (m_collection, m_key, m_expiry_seconds) = base
+            .m_parser_payload
+            .split_once('=')
+            .and_then(|(var, seconds)| {
+                let (collection, key) = var.split_once('.')?;
+                Some((
+                    collection.to_string(),
+                    key.to_string(),
+                    seconds.trim().parse().ok()?,
+                ))
+            })
+            .ok_or_else(|| {
+                format!(
+                    "expirevar: expected <collection>.<key>=<seconds>, got: {}",
+                    base.m_parser_payload
+                )
+            })?;
+
+        Ok(ExpireVar {
+            base,
+            _action_kind: Kind::RunTimeOnlyIfMatchKind,
+            m_collection,
+            m_key,
+            m_expiry_seconds,
+        })
+    }
+}
+
+impl Action for ExpireVar {
+    fn evaluate(&self, value: &str, transaction: &Transaction) -> String {
+        self.base.evaluate(value, transaction)
+    }
+
+    fn evaluate_rule_actions(&self, _ra: &RuleWithActions, transaction: &Transaction) -> bool {
+        transaction.expire_var(&self.m_collection, &self.m_key, self.m_expiry_seconds);
+
+        true
+    }
+}
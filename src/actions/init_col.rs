@@ -0,0 +1,68 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::rules::RuleWithActions;
+use crate::transaction::Transaction;
+
+use super::action::{Action, BaseAction, Kind};
+
+/// `initcol:ip=%{REMOTE_ADDR}`, binds the persistent collection ip, global
+/// or resource to the record of the expanded key. SESSION and USER are
+/// bound by setsid and setuid.
+pub struct InitCol {
+    base: BaseAction,
+    _action_kind: Kind,
+    m_collection: String,
+    m_key: String,
+}
+
+impl InitCol {
+    pub fn new(action: &str) -> Result<InitCol, String> {
+        let base = BaseAction::new(action);
+        let (collection, key) = base.m_parser_payload.split_once('=').ok_or_else(|| {
+            format!(
+                "initcol: expected <collection>=<key>, got: {}",
+                base.m_parser_payload
+            )
+        })?;
+        let m_collection = collection.to_lowercase();
+        if !matches!(m_collection.as_str(), "ip" | "global" | "resource") {
+            return Err(format!(
+                "initcol: collection must be ip, global or resource, got: {}",
+                collection
+            ));
+        }
+        let m_key = key.to_string();
+
+        Ok(InitCol {
+            base,
+            _action_kind: Kind::RunTimeOnlyIfMatchKind,
+            m_collection,
+            m_key,
+        })
+    }
+}
+
+impl Action for InitCol {
+    fn evaluate(&self, value: &str, transaction: &Transaction) -> String {
+        self.base.evaluate(value, transaction)
+    }
+
+    fn evaluate_rule_actions(&self, _ra: &RuleWithActions, transaction: &Transaction) -> bool {
+        let key = transaction.expand_macros(&self.m_key);
+        transaction.init_collection(&self.m_collection, &key);
+
+        true
+    }
+}
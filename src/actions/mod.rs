@@ -1,17 +1,252 @@
 // Copyright 2023 young2j
-// 
+//
 // Licensed under the Apache License, Version 2.0 (the "License");
 // you may not use this file except in compliance with the License.
 // You may obtain a copy of the License at
-// 
+//
 //     http://www.apache.org/licenses/LICENSE-2.0
-// 
+//
 // Unless required by applicable law or agreed to in writing, software
 // distributed under the License is distributed on an "AS IS" BASIS,
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-pub mod ctl;
 pub mod action;
-pub mod audit_log;
\ No newline at end of file
+pub mod audit_log;
+pub mod ctl;
+pub mod expire_var;
+pub mod init_col;
+pub mod no_audit_log;
+pub mod sanitise_arg;
+pub mod sanitise_matched;
+pub mod sanitise_matched_bytes;
+pub mod sanitise_request_header;
+pub mod sanitise_response_header;
+pub mod set_sid;
+pub mod set_uid;
+
+#[test]
+fn test_audit_log_actions() {
+    use crate::{
+        actions::{
+            action::Action,
+            ctl::{audit_engine::AuditEngine, audit_log_parts::AuditLogParts},
+            no_audit_log::NoAuditLog,
+        },
+        audit_log::AuditLogStatus,
+        modsecurity::RulesSetProperties,
+        rules::{RuleMessage, RuleWithActions},
+        transaction::Transaction,
+    };
+    use std::{env::temp_dir, fs, rc::Rc, sync::Arc, time::Duration};
+
+    let path = temp_dir().join("modsecurity-rs-relevant-audit.log");
+    _ = fs::remove_file(&path);
+    let mut rules = RulesSetProperties::new();
+    let audit_log = &mut rules.m_audit_log;
+    audit_log.set_status(AuditLogStatus::from_directive("RelevantOnly").unwrap());
+    audit_log.set_relevant_status("^5").unwrap();
+    audit_log.set_parts("ABFHZ").unwrap();
+    audit_log.set_file_path1(path.to_str().unwrap());
+    audit_log.init().unwrap();
+    let rules = Arc::new(rules);
+    let ra = RuleWithActions {};
+    let logged = || {
+        assert!(rules.m_audit_log.flush(Duration::from_secs(5)));
+        fs::read_to_string(&path).unwrap_or_default()
+    };
+
+    // noauditlog message and a status that is not relevant
+    let mut t = Transaction::new_with_id(rules.clone(), "unique-4");
+    t.process_response_headers(403, "HTTP 1.1");
+    let rm = Rc::new(RuleMessage::new(&t));
+    NoAuditLog::new("noauditlog").evaluate_rule_actions_with_message(&ra, &t, rm.clone());
+    t.m_rules_messages.push(rm);
+    t.process_logging().unwrap();
+    assert!(!logged().contains("unique-4"));
+
+    // relevant status
+    let mut t = Transaction::new_with_id(rules.clone(), "unique-5");
+    t.process_response_headers(503, "HTTP 1.1");
+    t.process_logging().unwrap();
+    assert!(logged().contains("unique-5"));
+
+    // forced by ctl, with the parts changed
+    let mut t = Transaction::new_with_id(rules.clone(), "unique-6");
+    t.append_response_body(b"secret");
+    t.process_response_body();
+    AuditEngine::new("ctl:auditEngine=On")
+        .unwrap()
+        .evaluate_rule_actions(&ra, &t);
+    AuditLogParts::new("ctl:auditLogParts=+E")
+        .unwrap()
+        .evaluate_rule_actions(&ra, &t);
+    AuditLogParts::new("ctl:auditLogParts=-B")
+        .unwrap()
+        .evaluate_rule_actions(&ra, &t);
+    t.process_logging().unwrap();
+    let log = logged();
+    let entry = &log[log.find("unique-6").unwrap()..];
+    assert!(entry.contains("-E--\nsecret\n"));
+    assert!(!entry.contains("-B--"));
+
+    // ctl turns it off
+    let mut t = Transaction::new_with_id(rules.clone(), "unique-7");
+    t.process_response_headers(500, "HTTP 1.1");
+    AuditEngine::new("ctl:auditEngine=Off")
+        .unwrap()
+        .evaluate_rule_actions(&ra, &t);
+    t.process_logging().unwrap();
+    assert!(!logged().contains("unique-7"));
+
+    assert!(AuditEngine::new("ctl:auditEngine=Maybe").is_err());
+    assert!(AuditLogParts::new("ctl:auditLogParts=E").is_err());
+}
+
+#[test]
+fn test_sanitise_actions() {
+    use crate::{
+        actions::{
+            action::Action, sanitise_arg::SanitiseArg, sanitise_matched::SanitiseMatched,
+            sanitise_matched_bytes::SanitiseMatchedBytes,
+            sanitise_request_header::SanitiseRequestHeader,
+            sanitise_response_header::SanitiseResponseHeader,
+        },
+        modsecurity::RulesSetProperties,
+        rules::RuleWithActions,
+        transaction::Transaction,
+    };
+    use std::sync::Arc;
+
+    let mut t = Transaction::new_with_id(Arc::new(RulesSetProperties::new()), "unique-9");
+    t.process_uri("/login?user=bob&token=s3cr3t", "POST", "1.1");
+    t.add_request_header("Authorization", "Basic Ym9iOmh1bnRlcjI=");
+    t.add_request_header("Content-Type", "application/x-www-form-urlencoded");
+    t.add_request_header("X-Api-Key", "k3y");
+    t.append_request_body(b"user=bob&password=hunter%32&card=4111111111111111");
+    t.process_request_body();
+    t.add_response_header("Set-Cookie", "session=abc");
+
+    let ra = RuleWithActions {};
+    SanitiseArg::new("sanitiseArg:password").evaluate_rule_actions(&ra, &t);
+    SanitiseArg::new("sanitiseArg:token").evaluate_rule_actions(&ra, &t);
+    SanitiseRequestHeader::new("sanitiseRequestHeader:authorization")
+        .evaluate_rule_actions(&ra, &t);
+    SanitiseResponseHeader::new("sanitiseResponseHeader:Set-Cookie").evaluate_rule_actions(&ra, &t);
+
+    // as the rule engine sets them when ARGS:card or REQUEST_HEADERS:X-Api-Key matched
+    let matched = |t: &mut Transaction, name: &str, value: &str, offset: usize| {
+        let vars = &mut t.m_variables;
+        vars.m_variable_matched_var_name.set(name, 0);
+        vars.m_variable_matched_var.set(value, offset);
+    };
+    let card = t
+        .m_variables
+        .m_variable_args
+        .borrow()
+        .values("card")
+        .next()
+        .map(|v| {
+            let origin = v.get_origin().front().unwrap();
+            (v.get_value().to_string(), origin.m_offset)
+        });
+    let (card, offset) = card.unwrap();
+    matched(&mut t, "ARGS:card", &card, offset);
+    SanitiseMatchedBytes::new("sanitiseMatchedBytes:0/4")
+        .unwrap()
+        .evaluate_rule_actions(&ra, &t);
+    matched(&mut t, "REQUEST_HEADERS:X-Api-Key", "k3y", 0);
+    SanitiseMatched::new("sanitiseMatched").evaluate_rule_actions(&ra, &t);
+
+    let log = String::from_utf8(t.to_old_audit_log_format(0x1fff, "-x--")).unwrap();
+    assert!(
+        log.contains("POST /login?user=bob&token=****** HTTP/1.1\n"),
+        "{}",
+        log
+    );
+    assert!(log.contains("Authorization: **********************\n"));
+    assert!(log.contains("X-Api-Key: ***\n"));
+    assert!(log.contains("\nuser=bob&password=*********&card=************1111\n"));
+    assert!(log.contains("Set-Cookie: ***********\n"));
+    assert_eq!(
+        t.m_request_body,
+        b"user=bob&password=hunter%32&card=4111111111111111"
+    );
+    assert!(SanitiseMatchedBytes::new("sanitiseMatchedBytes:1").is_err());
+
+    // invalid UTF-8 before the argument does not shift its origin
+    let mut t = Transaction::new_with_id(Arc::new(RulesSetProperties::new()), "unique-10");
+    t.process_uri("/login", "POST", "1.1");
+    t.add_request_header("Content-Type", "application/x-www-form-urlencoded");
+    t.append_request_body(b"a=\xff\xff&password=hunter2secret");
+    t.process_request_body();
+    SanitiseArg::new("sanitiseArg:password").evaluate_rule_actions(&ra, &t);
+    let log = t.to_old_audit_log_format(0x1fff, "-x--");
+    let body = b"\na=\xff\xff&password=*************\n";
+    assert!(log.windows(body.len()).any(|w| w == body));
+}
+
+#[test]
+fn test_collection_actions() {
+    use crate::{
+        actions::{action::Action, init_col::InitCol, set_sid::SetSID, set_uid::SetUID},
+        collection::{backend::InMemoryPerProcess, Collection},
+        modsecurity::{modsecurity::ModSecurity, RulesSetProperties},
+        rules::RuleWithActions,
+        transaction::Transaction,
+    };
+    use std::sync::Arc;
+
+    let modsec = ModSecurity::<InMemoryPerProcess>::new();
+    let rules = Arc::new(RulesSetProperties::new());
+    let ra = RuleWithActions {};
+    let transaction = || {
+        let mut t = modsec.new_transaction(rules.clone());
+        t.process_connection("10.0.0.1", 12345, "10.0.0.2", 80);
+        t.process_uri("/?user=bob", "GET", "1.1");
+        t.add_request_header("Cookie", "lang=en; sessionid=abc123");
+        t
+    };
+    let init = |t: &Transaction| {
+        InitCol::new("initcol:ip=%{REMOTE_ADDR}")
+            .unwrap()
+            .evaluate_rule_actions(&ra, t);
+        SetSID::new("setsid:%{REQUEST_COOKIES.sessionid}").evaluate_rule_actions(&ra, t);
+        SetUID::new("setuid:%{ARGS.user}").evaluate_rule_actions(&ra, t);
+    };
+
+    let t = transaction();
+    init(&t);
+    {
+        let collections = t.m_collections.borrow();
+        assert_eq!(collections.m_ip_collection_key, "10.0.0.1");
+        assert_eq!(collections.m_session_collection_key, "abc123");
+        assert_eq!(collections.m_user_collection_key, "bob");
+    }
+    let vars = &t.m_variables;
+    assert_eq!(vars.m_variable_session_id.borrow().evaluate(), "abc123");
+    assert_eq!(vars.m_variable_user_id.borrow().evaluate(), "bob");
+    assert_eq!(t.expand_macros("%{IP.IS_NEW}/%{SESSION.TIMEOUT}"), "1/3600");
+    assert_eq!(
+        t.expand_macros("%{sessionid}-%{REQUEST_COOKIES.lang}"),
+        "abc123-en"
+    );
+    assert_eq!(t.expand_macros("[%{ARGS.missing}]"), "[]");
+
+    // the records are shared by the next transactions
+    let t = transaction();
+    init(&t);
+    assert_eq!(t.expand_macros("%{IP.IS_NEW}"), "0");
+    assert!(modsec
+        .m_ip_collection
+        .resolve_first("10.0.0.1::CREATE_TIME")
+        .is_some());
+
+    // no record for an empty key
+    let t = modsec.new_transaction(rules.clone());
+    SetSID::new("setsid:%{REQUEST_COOKIES.sessionid}").evaluate_rule_actions(&ra, &t);
+    assert!(t.m_collections.borrow().m_session_collection_key.is_empty());
+    assert!(InitCol::new("initcol:session=abc").is_err());
+    assert!(InitCol::new("initcol:ip").is_err());
+}
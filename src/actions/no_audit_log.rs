@@ -0,0 +1,56 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::rules::{RuleMessage, RuleWithActions};
+use crate::transaction::Transaction;
+
+use super::action::{Action, BaseAction, Kind};
+
+/// `noauditlog`, the message of the rule does not make the transaction
+/// relevant for SecAuditEngine RelevantOnly.
+pub struct NoAuditLog {
+    base: BaseAction,
+    _action_kind: Kind,
+}
+
+impl NoAuditLog {
+    pub fn new(action: &str) -> NoAuditLog {
+        NoAuditLog {
+            base: BaseAction::new(action),
+            _action_kind: Kind::RunTimeOnlyIfMatchKind,
+        }
+    }
+}
+
+impl Action for NoAuditLog {
+    fn evaluate(&self, value: &str, transaction: &Transaction) -> String {
+        self.base.evaluate(value, transaction)
+    }
+
+    fn evaluate_rule_actions(&self, ra: &RuleWithActions, transaction: &Transaction) -> bool {
+        self.base.evaluate_rule_actions(ra, transaction)
+    }
+
+    fn evaluate_rule_actions_with_message(
+        &self,
+        _ra: &RuleWithActions,
+        _transaction: &Transaction,
+        rm: std::rc::Rc<RuleMessage>,
+    ) -> bool {
+        rm.m_no_audit_log.set(true);
+        rm.m_save_message.set(false);
+
+        true
+    }
+}
@@ -0,0 +1,46 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::rules::RuleWithActions;
+use crate::transaction::Transaction;
+
+use super::action::{Action, BaseAction, Kind};
+
+/// `sanitiseArg:name`, masks the values of the argument `name`, wherever
+/// they are, in the audit log.
+pub struct SanitiseArg {
+    base: BaseAction,
+    _action_kind: Kind,
+}
+
+impl SanitiseArg {
+    pub fn new(action: &str) -> SanitiseArg {
+        SanitiseArg {
+            base: BaseAction::new(action),
+            _action_kind: Kind::RunTimeOnlyIfMatchKind,
+        }
+    }
+}
+
+impl Action for SanitiseArg {
+    fn evaluate(&self, value: &str, transaction: &Transaction) -> String {
+        self.base.evaluate(value, transaction)
+    }
+
+    fn evaluate_rule_actions(&self, _ra: &RuleWithActions, transaction: &Transaction) -> bool {
+        transaction.sanitise_arg(&self.base.m_parser_payload);
+
+        true
+    }
+}
@@ -0,0 +1,65 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::rules::RuleWithActions;
+use crate::transaction::Transaction;
+
+use super::action::{Action, BaseAction, Kind};
+
+/// `sanitiseMatchedBytes` or `sanitiseMatchedBytes:1/4`, masks the bytes of
+/// the variable that matched, keeping the first and the last ones given.
+pub struct SanitiseMatchedBytes {
+    base: BaseAction,
+    _action_kind: Kind,
+    m_keep_start: usize,
+    m_keep_end: usize,
+}
+
+impl SanitiseMatchedBytes {
+    pub fn new(action: &str) -> Result<SanitiseMatchedBytes, String> {
+        let base = BaseAction::new(action);
+        let (m_keep_start, m_keep_end) = if base.m_parser_payload.is_empty() {
+            (0, 0)
+        } else {
+            base.m_parser_payload
+                .split_once('/')
+                .and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)))
+                .ok_or_else(|| {
+                    format!(
+                        "sanitiseMatchedBytes: expected <start>/<end>, got: {}",
+                        base.m_parser_payload
+                    )
+                })?
+        };
+
+        Ok(SanitiseMatchedBytes {
+            base,
+            _action_kind: Kind::RunTimeOnlyIfMatchKind,
+            m_keep_start,
+            m_keep_end,
+        })
+    }
+}
+
+impl Action for SanitiseMatchedBytes {
+    fn evaluate(&self, value: &str, transaction: &Transaction) -> String {
+        self.base.evaluate(value, transaction)
+    }
+
+    fn evaluate_rule_actions(&self, _ra: &RuleWithActions, transaction: &Transaction) -> bool {
+        transaction.sanitise_matched_bytes(self.m_keep_start, self.m_keep_end);
+
+        true
+    }
+}
@@ -0,0 +1,443 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use regex::Regex;
+
+use crate::{debug_log::DebugLog, ms_dbg, transaction::Transaction};
+
+use super::{
+    shipper::ShipperConfig,
+    sink::DEFAULT_QUEUE_SIZE,
+    writer::{Parallel, Serial, Writer},
+    AuditLogRotation, DiskUsage,
+};
+
+/// Bits of the audit log parts, see SecAuditLogParts.
+pub enum AuditLogParts {
+    AAuditLogPart = 2,
+    BAuditLogPart = 4,
+    CAuditLogPart = 8,
+    DAuditLogPart = 16,
+    EAuditLogPart = 32,
+    FAuditLogPart = 64,
+    GAuditLogPart = 128,
+    HAuditLogPart = 256,
+    IAuditLogPart = 512,
+    JAuditLogPart = 1024,
+    KAuditLogPart = 2048,
+    ZAuditLogPart = 4096,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AuditLogStatus {
+    OnAuditLogStatus,
+    OffAuditLogStatus,
+    /// Only the transactions with a status matching SecAuditLogRelevantStatus,
+    /// or with a message of a rule that did not ask for noauditlog.
+    RelevantOnlyAuditLogStatus,
+}
+
+impl AuditLogStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditLogStatus::OnAuditLogStatus => "On",
+            AuditLogStatus::OffAuditLogStatus => "Off",
+            AuditLogStatus::RelevantOnlyAuditLogStatus => "RelevantOnly",
+        }
+    }
+
+    /// SecAuditEngine On|Off|RelevantOnly, also used by ctl:auditEngine.
+    pub fn from_directive(value: &str) -> Result<AuditLogStatus, String> {
+        match value.to_lowercase().as_str() {
+            "on" => Ok(AuditLogStatus::OnAuditLogStatus),
+            "off" => Ok(AuditLogStatus::OffAuditLogStatus),
+            "relevantonly" => Ok(AuditLogStatus::RelevantOnlyAuditLogStatus),
+            _ => Err(format!("Invalid value for SecAuditEngine: {}", value)),
+        }
+    }
+}
+
+pub enum AuditLogType {
+    SerialAuditLogType,
+    ParallelAuditLogType,
+}
+
+impl AuditLogType {
+    /// SecAuditLogType Serial|Concurrent
+    pub fn from_directive(value: &str) -> Result<AuditLogType, String> {
+        match value.to_lowercase().as_str() {
+            "serial" => Ok(AuditLogType::SerialAuditLogType),
+            "concurrent" => Ok(AuditLogType::ParallelAuditLogType),
+            _ => Err(format!("Invalid value for SecAuditLogType: {}", value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum AuditLogFormat {
+    /// Multipart-like serial format of ModSecurity 2.x.
+    NativeAuditLogFormat,
+    /// One JSON object per transaction.
+    JSONAuditLogFormat,
+}
+
+impl AuditLogFormat {
+    /// SecAuditLogFormat Native|JSON
+    pub fn from_directive(value: &str) -> Result<AuditLogFormat, String> {
+        match value.to_lowercase().as_str() {
+            "native" => Ok(AuditLogFormat::NativeAuditLogFormat),
+            "json" => Ok(AuditLogFormat::JSONAuditLogFormat),
+            _ => Err(format!("Invalid value for SecAuditLogFormat: {}", value)),
+        }
+    }
+}
+
+/// Parse audit log parts, e.g. `ABCFHZ`, into a combination of
+/// `AuditLogParts`, see SecAuditLogParts.
+pub fn parse_parts(value: &str) -> Result<i32, String> {
+    let mut parts = 0;
+    for c in value.trim().chars() {
+        parts |= match c {
+            'A' => AuditLogParts::AAuditLogPart,
+            'B' => AuditLogParts::BAuditLogPart,
+            'C' => AuditLogParts::CAuditLogPart,
+            'D' => AuditLogParts::DAuditLogPart,
+            'E' => AuditLogParts::EAuditLogPart,
+            'F' => AuditLogParts::FAuditLogPart,
+            'G' => AuditLogParts::GAuditLogPart,
+            'H' => AuditLogParts::HAuditLogPart,
+            'I' => AuditLogParts::IAuditLogPart,
+            'J' => AuditLogParts::JAuditLogPart,
+            'K' => AuditLogParts::KAuditLogPart,
+            'Z' => AuditLogParts::ZAuditLogPart,
+            _ => return Err(format!("Invalid audit log part: {}", c)),
+        } as i32;
+    }
+    Ok(parts)
+}
+
+/// Drop the parts carrying bodies and files, C, D, E, G, I and J, keeping
+/// the headers, the messages and the boundaries.
+pub fn headers_only(parts: i32) -> i32 {
+    parts
+        & (AuditLogParts::AAuditLogPart as i32
+            | AuditLogParts::BAuditLogPart as i32
+            | AuditLogParts::FAuditLogPart as i32
+            | AuditLogParts::HAuditLogPart as i32
+            | AuditLogParts::KAuditLogPart as i32
+            | AuditLogParts::ZAuditLogPart as i32)
+}
+
+/// Parse an octal file mode, e.g. `0640`, see SecAuditLogFileMode.
+pub fn parse_file_mode(value: &str) -> Result<u32, String> {
+    u32::from_str_radix(value.trim(), 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| format!("Invalid file mode: {}", value))
+}
+
+/// Audit log configuration and writer, shared by all the transactions of a
+/// rule set.
+pub struct AuditLog {
+    /// SecAuditLog, a file or a socket, pipe or syslog target, see
+    /// `sink::open_sink`
+    pub m_path1: String,
+    /// SecAuditLog2, index of the concurrent audit log
+    pub m_path2: String,
+    /// SecAuditLogStorageDir
+    pub m_storage_dir: String,
+    /// SecAuditLogFileMode
+    pub m_file_permissions: u32,
+    /// SecAuditLogDirMode
+    pub m_directory_permissions: u32,
+    /// SecAuditLogParts
+    pub m_parts: i32,
+    /// SecAuditLogRelevantStatus
+    pub m_relevant: Option<Regex>,
+    /// SecAuditEngine
+    pub m_status: AuditLogStatus,
+    /// SecAuditLogType
+    pub m_type: AuditLogType,
+    /// SecAuditLogFormat
+    pub m_format: AuditLogFormat,
+    /// Number of entries waiting for a slow SecAuditLog target before new
+    /// entries are dropped.
+    pub m_queue_size: usize,
+    /// Rotation of the serial audit log file
+    pub m_rotation: AuditLogRotation,
+    /// Maximum size of the request and response bodies of an entry, parts
+    /// C and E, 0 for no limit
+    pub m_body_limit: usize,
+    /// Disk space of the audit log files beyond which the entries are
+    /// written headers only, 0 for no limit
+    pub m_disk_budget: u64,
+    m_disk_usage: Arc<DiskUsage>,
+    /// Collector the entries of a concurrent audit log are shipped to
+    pub m_shipper: Option<ShipperConfig>,
+    m_debug_log: Arc<DebugLog>,
+    m_writer: Mutex<Option<Arc<dyn Writer>>>,
+}
+
+impl AuditLog {
+    pub fn new() -> AuditLog {
+        AuditLog {
+            m_path1: String::new(),
+            m_path2: String::new(),
+            m_storage_dir: String::new(),
+            m_file_permissions: 0o600,
+            m_directory_permissions: 0o700,
+            m_parts: AuditLogParts::AAuditLogPart as i32
+                | AuditLogParts::BAuditLogPart as i32
+                | AuditLogParts::CAuditLogPart as i32
+                | AuditLogParts::FAuditLogPart as i32
+                | AuditLogParts::HAuditLogPart as i32
+                | AuditLogParts::ZAuditLogPart as i32,
+            m_relevant: None,
+            m_status: AuditLogStatus::OffAuditLogStatus,
+            m_type: AuditLogType::SerialAuditLogType,
+            m_format: AuditLogFormat::NativeAuditLogFormat,
+            m_queue_size: DEFAULT_QUEUE_SIZE,
+            m_rotation: AuditLogRotation::new(),
+            m_body_limit: 0,
+            m_disk_budget: 0,
+            m_disk_usage: Arc::new(DiskUsage::new()),
+            m_shipper: None,
+            m_debug_log: Arc::new(DebugLog::new()),
+            m_writer: Mutex::new(None),
+        }
+    }
+
+    pub fn set_status(&mut self, status: AuditLogStatus) {
+        self.m_status = status;
+    }
+
+    pub fn set_parts(&mut self, parts: &str) -> Result<(), String> {
+        self.m_parts = parse_parts(parts)?;
+        Ok(())
+    }
+
+    pub fn set_relevant_status(&mut self, status: &str) -> Result<(), String> {
+        let re = Regex::new(status)
+            .map_err(|e| format!("Invalid SecAuditLogRelevantStatus {}: {}", status, e))?;
+        self.m_relevant = Some(re);
+        Ok(())
+    }
+
+    /// Whether the response status matches SecAuditLogRelevantStatus.
+    pub fn is_relevant(&self, status: i32) -> bool {
+        match &self.m_relevant {
+            None => false,
+            Some(re) => re.is_match(&status.to_string()),
+        }
+    }
+
+    pub fn set_file_path1(&mut self, path: &str) {
+        self.m_path1 = path.to_string();
+    }
+
+    pub fn set_file_path2(&mut self, path: &str) {
+        self.m_path2 = path.to_string();
+    }
+
+    pub fn set_storage_dir(&mut self, path: &str) {
+        self.m_storage_dir = path.to_string();
+    }
+
+    pub fn set_file_mode(&mut self, permissions: u32) {
+        self.m_file_permissions = permissions;
+    }
+
+    pub fn set_directory_mode(&mut self, permissions: u32) {
+        self.m_directory_permissions = permissions;
+    }
+
+    pub fn set_type(&mut self, audit_type: AuditLogType) {
+        self.m_type = audit_type;
+    }
+
+    pub fn set_format(&mut self, format: AuditLogFormat) {
+        self.m_format = format;
+    }
+
+    pub fn set_queue_size(&mut self, size: usize) {
+        self.m_queue_size = size;
+    }
+
+    pub fn set_rotation(&mut self, rotation: AuditLogRotation) {
+        self.m_rotation = rotation;
+    }
+
+    pub fn set_body_limit(&mut self, limit: usize) {
+        self.m_body_limit = limit;
+    }
+
+    pub fn set_disk_budget(&mut self, budget: u64) {
+        self.m_disk_budget = budget;
+    }
+
+    /// Debug log of the writer workers, which have no transaction to
+    /// report to.
+    pub fn set_debug_log(&mut self, debug_log: Arc<DebugLog>) {
+        self.m_debug_log = debug_log;
+    }
+
+    pub fn set_shipper(&mut self, config: ShipperConfig) {
+        self.m_shipper = Some(config);
+    }
+
+    /// Bytes taken on disk by the audit log files, as accounted by the
+    /// writer.
+    pub fn disk_usage(&self) -> u64 {
+        self.m_disk_usage.get()
+    }
+
+    /// Open the writer, to be called once the configuration is loaded.
+    ///
+    /// The writer is opened even with SecAuditEngine Off, ctl:auditEngine
+    /// may turn the audit log on for a transaction.
+    pub fn init(&self) -> Result<(), String> {
+        let has_target = match self.m_type {
+            AuditLogType::SerialAuditLogType => !self.m_path1.is_empty(),
+            AuditLogType::ParallelAuditLogType => !self.m_storage_dir.is_empty(),
+        };
+        if self.m_status == AuditLogStatus::OffAuditLogStatus && !has_target {
+            return Ok(());
+        }
+
+        // the writer accounts for the files already there
+        self.m_disk_usage.set(0);
+        let mut writer: Box<dyn Writer> = match self.m_type {
+            AuditLogType::SerialAuditLogType if self.m_shipper.is_some() => {
+                return Err(
+                    "Shipping the audit log requires SecAuditLogType Concurrent.".to_string(),
+                );
+            }
+            AuditLogType::SerialAuditLogType => Box::new(Serial::new(
+                &self.m_path1,
+                self.m_file_permissions,
+                self.m_format,
+                self.m_queue_size,
+                self.m_rotation.clone(),
+                self.m_disk_usage.clone(),
+                self.m_debug_log.clone(),
+            )),
+            AuditLogType::ParallelAuditLogType => {
+                let index = if self.m_path2.is_empty() {
+                    &self.m_path1
+                } else {
+                    &self.m_path2
+                };
+                let mut parallel = Parallel::new(
+                    &self.m_storage_dir,
+                    index,
+                    self.m_file_permissions,
+                    self.m_directory_permissions,
+                    self.m_format,
+                    self.m_disk_usage.clone(),
+                );
+                if let Some(config) = &self.m_shipper {
+                    parallel.set_shipper(config.clone(), self.m_debug_log.clone());
+                }
+                Box::new(parallel)
+            }
+        };
+        writer.init()?;
+
+        let mut guard = self.m_writer.lock().unwrap_or_else(|e| e.into_inner());
+        *guard = Some(Arc::from(writer));
+
+        Ok(())
+    }
+
+    pub fn save_if_relevant(&self, transaction: &Transaction) -> Result<(), String> {
+        self.save_if_relevant_with_parts(transaction, self.m_parts)
+    }
+
+    /// Write the entry of `transaction`, unless the audit engine is off for
+    /// it or, with RelevantOnly, the transaction is not relevant: its status
+    /// does not match SecAuditLogRelevantStatus and all its messages come
+    /// from rules with noauditlog. Once the disk budget is exceeded, only
+    /// the headers and the messages are written.
+    pub fn save_if_relevant_with_parts(
+        &self,
+        transaction: &Transaction,
+        parts: i32,
+    ) -> Result<(), String> {
+        // ctl:auditEngine takes precedence over SecAuditEngine
+        let status = transaction
+            .m_ctl_audit_engine
+            .get()
+            .unwrap_or(self.m_status);
+        match status {
+            AuditLogStatus::OffAuditLogStatus => return Ok(()),
+            AuditLogStatus::OnAuditLogStatus => {}
+            AuditLogStatus::RelevantOnlyAuditLogStatus => {
+                let save_anyway = transaction
+                    .m_rules_messages
+                    .iter()
+                    .any(|rm| !rm.m_no_audit_log.get());
+                if !save_anyway && !self.is_relevant(transaction.m_http_code_returned) {
+                    ms_dbg!(
+                        transaction,
+                        5,
+                        "Return code `{}' is not interesting to audit logs, relevant code(s): `{}'.",
+                        transaction.m_http_code_returned,
+                        self.m_relevant.as_ref().map_or("", |re| re.as_str())
+                    );
+                    return Ok(());
+                }
+            }
+        }
+
+        let mut parts = parts;
+        if self.m_disk_budget > 0 && self.disk_usage() >= self.m_disk_budget {
+            ms_dbg!(
+                transaction,
+                3,
+                "Audit log disk budget of {} bytes exceeded, logging the headers only.",
+                self.m_disk_budget
+            );
+            parts = headers_only(parts);
+        }
+
+        match self.writer() {
+            None => Err("Audit log writer is not initialized.".to_string()),
+            Some(writer) => writer.write(transaction, parts),
+        }
+    }
+
+    /// Wait up to `timeout` for the queued entries to be written, and
+    /// shipped, returns whether they all were.
+    pub fn flush(&self, timeout: Duration) -> bool {
+        self.writer().is_none_or(|writer| writer.flush(timeout))
+    }
+
+    /// The writer is only locked to be cloned, the entries are written
+    /// concurrently, see `Writer`.
+    fn writer(&self) -> Option<Arc<dyn Writer>> {
+        let guard = self.m_writer.lock().unwrap_or_else(|e| e.into_inner());
+        guard.clone()
+    }
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        AuditLog::new()
+    }
+}
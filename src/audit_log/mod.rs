@@ -1,14 +1,574 @@
 // Copyright 2023 young2j
-// 
+//
 // Licensed under the Apache License, Version 2.0 (the "License");
 // you may not use this file except in compliance with the License.
 // You may obtain a copy of the License at
-// 
+//
 //     http://www.apache.org/licenses/LICENSE-2.0
-// 
+//
 // Unless required by applicable law or agreed to in writing, software
 // distributed under the License is distributed on an "AS IS" BASIS,
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod audit_log;
+pub mod reader;
+pub mod rotation;
+pub mod shipper;
+pub mod sink;
+pub mod writer;
+
+pub use audit_log::*;
+pub use rotation::*;
+
+#[test]
+fn test_serial_audit_log() {
+    use crate::{modsecurity::RulesSetProperties, rules::RuleMessage, transaction::Transaction};
+    use std::{env::temp_dir, fs, rc::Rc, sync::Arc, time::Duration};
+
+    let path = temp_dir().join("modsecurity-rs-serial-audit.log");
+    _ = fs::remove_file(&path);
+    let mut rules = RulesSetProperties::new();
+    rules
+        .m_audit_log
+        .set_status(AuditLogStatus::OnAuditLogStatus);
+    rules.m_audit_log.set_file_path1(path.to_str().unwrap());
+    rules.m_audit_log.init().unwrap();
+
+    let mut t = Transaction::new_with_id(Arc::new(rules), "unique-1");
+    t.process_connection("10.0.0.1", 12345, "10.0.0.2", 80);
+    t.process_uri("/a%20b?x=1", "POST", "1.1");
+    t.add_request_header("Host", "example.com");
+    t.append_request_body(b"x=2");
+    t.process_request_body();
+    t.process_response_headers(403, "HTTP 1.1");
+    t.add_response_header("Content-Length", "0");
+    let mut rm = RuleMessage::new(&t);
+    rm.m_rule_id = 1234;
+    rm.m_message = "Test".to_string();
+    t.m_rules_messages.push(Rc::new(rm));
+    t.process_logging().unwrap();
+    assert!(t.m_rules.m_audit_log.flush(Duration::from_secs(5)));
+
+    let log = fs::read_to_string(&path).unwrap();
+    let boundary = &log[3..11];
+    let expected_tail = format!(
+        " unique-1 10.0.0.1 12345 10.0.0.2 80\n\
+---{b}---B--\nPOST /a%20b?x=1 HTTP/1.1\nHost: example.com\n\n\
+---{b}---C--\nx=2\n\n\
+---{b}---F--\nHTTP/1.1 403\nContent-Length: 0\n\n\
+---{b}---H--\nModSecurity: Warning.  [file \"\"] [line \"0\"] [id \"1234\"] [rev \"\"] [msg \"Test\"] \
+[data \"\"] [severity \"0\"] [ver \"\"] [maturity \"0\"] [accuracy \"0\"] [hostname \"10.0.0.2\"] \
+[uri \"/a b\"] [unique_id \"unique-1\"] [ref \"\"]\n\n\
+---{b}---Z--\n\n",
+        b = boundary
+    );
+    assert!(log.starts_with(&format!("---{}---A--\n[", boundary)));
+    assert!(log.ends_with(&expected_tail), "{}", log);
+}
+
+#[test]
+fn test_concurrent_audit_log() {
+    use crate::{modsecurity::RulesSetProperties, transaction::Transaction};
+    use std::{env::temp_dir, fs, sync::Arc};
+
+    let dir = temp_dir().join("modsecurity-rs-concurrent-audit");
+    _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let index = dir.join("index.log");
+
+    let mut rules = RulesSetProperties::new();
+    let audit_log = &mut rules.m_audit_log;
+    audit_log.set_status(AuditLogStatus::OnAuditLogStatus);
+    audit_log.set_type(AuditLogType::from_directive("Concurrent").unwrap());
+    audit_log.set_file_path1(index.to_str().unwrap());
+    audit_log.set_storage_dir(dir.to_str().unwrap());
+    audit_log.set_file_mode(parse_file_mode("0640").unwrap());
+    audit_log.init().unwrap();
+
+    let mut t = Transaction::new_with_id(Arc::new(rules), "unique-2");
+    t.process_connection("10.0.0.1", 12345, "10.0.0.2", 80);
+    t.process_uri("/", "GET", "1.1");
+    t.add_request_header("Host", "example.com");
+    t.process_logging().unwrap();
+
+    let line = fs::read_to_string(&index).unwrap();
+    assert!(line.starts_with("example.com 10.0.0.1 - - ["), "{}", line);
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let (file, size) = (fields[fields.len() - 4], fields[fields.len() - 2]);
+    assert!(file.ends_with("-unique-2"));
+    assert_eq!(file.matches('/').count(), 3);
+
+    let entry = dir.join(file.trim_start_matches('/'));
+    assert_eq!(fs::metadata(&entry).unwrap().len().to_string(), size);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&entry).unwrap().permissions().mode();
+        // the umask may only remove permissions
+        assert_eq!(mode & 0o777 & !0o640, 0);
+    }
+}
+
+#[test]
+fn test_json_audit_log() {
+    use crate::{modsecurity::RulesSetProperties, rules::RuleMessage, transaction::Transaction};
+    use std::{env::temp_dir, fs, rc::Rc, sync::Arc, time::Duration};
+
+    let path = temp_dir().join("modsecurity-rs-json-audit.log");
+    _ = fs::remove_file(&path);
+    let mut rules = RulesSetProperties::new();
+    rules.m_components.push("OWASP_CRS/3.3.2".to_string());
+    let audit_log = &mut rules.m_audit_log;
+    audit_log.set_status(AuditLogStatus::OnAuditLogStatus);
+    audit_log.set_format(AuditLogFormat::from_directive("JSON").unwrap());
+    audit_log.set_file_path1(path.to_str().unwrap());
+    audit_log.init().unwrap();
+
+    let mut t = Transaction::new_with_id(Arc::new(rules), "unique-3");
+    t.m_connector = "test-connector v1".to_string();
+    t.process_connection("10.0.0.1", 12345, "10.0.0.2", 80);
+    t.process_uri("/?q=\"x\"", "POST", "1.1");
+    t.add_request_header("Host", "example.com");
+    t.append_request_body(b"x=2");
+    t.process_request_body();
+    t.process_response_headers(403, "HTTP 1.1");
+    let mut rm = RuleMessage::new(&t);
+    rm.m_rule_id = 1234;
+    rm.m_message = "Test".to_string();
+    rm.m_tags.push("attack-xss".to_string());
+    t.m_rules_messages.push(Rc::new(rm));
+    t.process_logging().unwrap();
+    assert!(t.m_rules.m_audit_log.flush(Duration::from_secs(5)));
+
+    let log = fs::read_to_string(&path).unwrap();
+    assert_eq!(log.lines().count(), 1);
+    let json: serde_json::Value = serde_json::from_str(&log).unwrap();
+    let transaction = &json["transaction"];
+    assert_eq!(transaction["unique_id"], "unique-3");
+    assert_eq!(transaction["client_port"], 12345);
+    assert_eq!(transaction["request"]["uri"], "/?q=\"x\"");
+    assert_eq!(transaction["request"]["http_version"], 1.1);
+    assert_eq!(transaction["request"]["body"], "x=2");
+    assert_eq!(transaction["request"]["headers"]["Host"], "example.com");
+    assert_eq!(transaction["response"]["http_code"], 403);
+    assert!(transaction["response"].get("body").is_none());
+    let producer = &transaction["producer"];
+    assert_eq!(producer["connector"], "test-connector v1");
+    assert_eq!(producer["secrules_engine"], "Enabled");
+    assert_eq!(producer["components"][0], "OWASP_CRS/3.3.2");
+    let message = &transaction["messages"][0];
+    assert_eq!(message["message"], "Test");
+    assert_eq!(message["details"]["ruleId"], "1234");
+    assert_eq!(message["details"]["tags"][0], "attack-xss");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_audit_log_sinks() {
+    use sink::{open_sink, Sink, SinkQueue};
+    use std::{
+        env::temp_dir,
+        fs,
+        io::Read,
+        net::UdpSocket,
+        os::unix::net::{UnixDatagram, UnixListener},
+        sync::mpsc,
+        time::Duration,
+    };
+
+    let dir = temp_dir().join("modsecurity-rs-sinks");
+    _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let mut buf = [0u8; 1024];
+
+    let path = dir.join("dgram.sock");
+    let server = UnixDatagram::bind(&path).unwrap();
+    let mut sink = open_sink(&format!("unixgram:{}", path.display()), 0o600).unwrap();
+    sink.write_entry(b"entry 1\n").unwrap();
+    let n = server.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"entry 1\n");
+
+    let mut sink = open_sink(&format!("syslog:unix:{}", path.display()), 0o600).unwrap();
+    sink.write_entry(b"{\"transaction\":{}}\n").unwrap();
+    let n = server.recv(&mut buf).unwrap();
+    let msg = std::str::from_utf8(&buf[..n]).unwrap();
+    assert!(msg.starts_with("<134>1 "), "{}", msg);
+    assert!(msg.contains(" modsecurity "));
+    assert!(msg.ends_with(" - - {\"transaction\":{}}"), "{}", msg);
+
+    // entries too large for a UDP datagram are truncated
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap();
+    let mut sink = open_sink(&format!("syslog:udp://{}", address), 0o600).unwrap();
+    sink.write_entry(&vec![b'a'; 100_000]).unwrap();
+    let mut datagram = vec![0u8; 100_000];
+    let n = server.recv(&mut datagram).unwrap();
+    assert_eq!(n, sink::SYSLOG_UDP_MAX_SIZE);
+    assert!(datagram[..n].ends_with(b"a [truncated]"));
+
+    let path = dir.join("stream.sock");
+    let listener = UnixListener::bind(&path).unwrap();
+    let sink = open_sink(&format!("unix:{}", path.display()), 0o600).unwrap();
+    let queue = SinkQueue::new(sink, 4).unwrap();
+    queue.push(b"a\n".to_vec()).unwrap();
+    queue.push(b"b\n".to_vec()).unwrap();
+    let (mut stream, _) = listener.accept().unwrap();
+    assert!(queue.flush(Duration::from_secs(5)));
+    // the worker stops and closes the connection
+    drop(queue);
+    let mut received = String::new();
+    stream.read_to_string(&mut received).unwrap();
+    // each entry preceded by its length
+    assert_eq!(received, "\0\0\0\u{2}a\n\0\0\0\u{2}b\n");
+
+    // a stalled sink does not block the pushes
+    struct Stalled(mpsc::Receiver<()>);
+    impl Sink for Stalled {
+        fn write_entry(&mut self, _entry: &[u8]) -> Result<(), String> {
+            _ = self.0.recv();
+            Ok(())
+        }
+    }
+    let (release, stalled) = mpsc::channel();
+    let queue = SinkQueue::new(Box::new(Stalled(stalled)), 2).unwrap();
+    for i in 0..10 {
+        _ = queue.push(vec![i]);
+    }
+    assert!(queue.dropped() >= 7);
+    for _ in 0..10 {
+        _ = release.send(());
+    }
+    assert!(queue.flush(Duration::from_secs(5)));
+}
+
+#[test]
+fn test_audit_log_reader() {
+    use crate::{modsecurity::RulesSetProperties, rules::RuleMessage, transaction::Transaction};
+    use reader::{read_audit_log, ConcurrentAuditLogReader};
+    use std::{env::temp_dir, fs, io::Cursor, rc::Rc, sync::Arc};
+
+    let dir = temp_dir().join("modsecurity-rs-reader");
+    _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let index = dir.join("index.log");
+    let mut rules = RulesSetProperties::new();
+    let audit_log = &mut rules.m_audit_log;
+    audit_log.set_status(AuditLogStatus::OnAuditLogStatus);
+    audit_log.set_type(AuditLogType::ParallelAuditLogType);
+    audit_log.set_parts("ABCEFHZ").unwrap();
+    audit_log.set_file_path1(index.to_str().unwrap());
+    audit_log.set_storage_dir(dir.to_str().unwrap());
+    audit_log.init().unwrap();
+
+    let mut t = Transaction::new_with_id(Arc::new(rules), "unique-8");
+    t.process_connection("10.0.0.1", 12345, "10.0.0.2", 80);
+    t.process_uri("/a%20b?x=1", "POST", "1.1");
+    t.add_request_header("Host", "example.com");
+    t.add_request_header("User-Agent", "curl");
+    t.append_request_body(b"x=2\n--not-a-boundary--");
+    t.process_request_body();
+    t.process_response_headers(403, "HTTP 1.1");
+    t.add_response_header("Content-Type", "text/plain");
+    t.append_response_body(b"denied\x01\"");
+    let mut rm = RuleMessage::new(&t);
+    rm.m_rule_id = 942100;
+    rm.m_is_disruptive = true;
+    rm.m_phase = 2;
+    rm.m_match = "Matched \"Operator `Rx'\" against ARGS:x.".to_string();
+    rm.m_message = "SQL Injection".to_string();
+    rm.m_data = "Matched \"x\"".to_string();
+    rm.m_severity = 2;
+    rm.m_tags = vec!["attack-sqli".to_string(), "paranoia-level/1".to_string()];
+    t.m_rules_messages.push(Rc::new(rm));
+
+    // the uri is written as received in every format
+    fn check(entry: &reader::AuditLogEntry) {
+        assert_eq!(entry.m_id, "unique-8");
+        assert_eq!(entry.m_request.m_method, "POST");
+        assert_eq!(entry.m_request.m_uri, "/a%20b?x=1");
+        let body = entry.m_request.m_body.as_deref();
+        assert_eq!(body, Some(&b"x=2\n--not-a-boundary--"[..]));
+        assert_eq!(entry.m_response.m_http_code, 403);
+        assert_eq!(entry.m_messages.len(), 1);
+    }
+
+    // native, two entries in a row
+    let mut log = t.to_old_audit_log_format(t.m_rules.m_audit_log.m_parts, "-abcdefgh--");
+    log.extend(t.to_old_audit_log_format(t.m_rules.m_audit_log.m_parts, "-ijklmnop--"));
+    let entries: Vec<_> = read_audit_log(Cursor::new(log)).collect();
+    assert_eq!(entries.len(), 2);
+    let entry = entries.into_iter().next().unwrap().unwrap();
+    assert_eq!(entry.m_client_port, 12345);
+    assert_eq!(
+        entry.m_request.m_headers[1],
+        ("User-Agent".to_string(), "curl".to_string())
+    );
+    assert_eq!(
+        entry.m_response.m_body.as_deref(),
+        Some(&b"denied\x01\""[..])
+    );
+    assert_eq!(entry.m_response.m_headers.len(), 1);
+    let rm = &entry.m_messages[0];
+    assert!(rm.m_is_disruptive);
+    assert_eq!(rm.m_phase, 2);
+    assert_eq!(rm.m_rule_id, 942100);
+    assert_eq!(rm.m_match, "Matched \"Operator `Rx'\" against ARGS:x.");
+    assert_eq!(rm.m_data, "Matched \"x\"");
+    assert_eq!(rm.m_tags.len(), 2);
+    assert_eq!(rm.m_uri_no_query_string_decoded, "/a b");
+    check(&entry);
+
+    // JSON
+    let log = t.to_json(t.m_rules.m_audit_log.m_parts);
+    let entry = read_audit_log(Cursor::new(log)).next().unwrap().unwrap();
+    assert_eq!(entry.m_request.m_http_version, "1.1");
+    assert_eq!(entry.m_messages[0].m_tags[1], "paranoia-level/1");
+    assert!(entry.m_producer.is_some());
+    check(&entry);
+
+    // concurrent
+    t.process_logging().unwrap();
+    let mut entries =
+        ConcurrentAuditLogReader::open(dir.to_str().unwrap(), index.to_str().unwrap()).unwrap();
+    let (index, entry) = entries.next().unwrap().unwrap();
+    assert_eq!(index.m_request_line, "POST /a%20b?x=1 HTTP/1.1");
+    assert_eq!(index.m_user_agent, "curl");
+    assert_eq!(index.m_session_id, "");
+    check(&entry);
+    assert!(entries.next().is_none());
+
+    // quotes, backslashes and control bytes of the request survive the index
+    let mut t = Transaction::new_with_id(t.m_rules.clone(), "unique-11");
+    t.process_uri("/\"a\"", "GET", "1.1");
+    t.add_request_header("User-Agent", "sqlmap \"1\" \\ é\t");
+    let line = t.to_old_audit_log_format_index("/f", 1, "00");
+    let index = reader::AuditLogIndexEntry::parse(&line).unwrap();
+    assert_eq!(index.m_request_line, "GET /\"a\" HTTP/1.1");
+    assert_eq!(index.m_user_agent, "sqlmap \"1\" \\ é\t");
+    assert_eq!(index.m_id, "unique-11");
+    assert_eq!(index.m_md5, "00");
+}
+
+#[test]
+fn test_audit_log_rotation() {
+    use crate::{modsecurity::RulesSetProperties, transaction::Transaction};
+    use std::{env::temp_dir, fs, path::Path, sync::Arc, time::Duration};
+
+    assert_eq!(parse_size("512").unwrap(), 512);
+    assert_eq!(parse_size("10M").unwrap(), 10 << 20);
+    assert_eq!(parse_interval("1d").unwrap(), Duration::from_secs(86400));
+    assert!(parse_size("10X").is_err());
+
+    let dir = temp_dir().join("modsecurity-rs-rotation");
+    _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("audit.log");
+    let path = path.to_str().unwrap();
+
+    let mut rules = RulesSetProperties::new();
+    let audit_log = &mut rules.m_audit_log;
+    audit_log.set_status(AuditLogStatus::OnAuditLogStatus);
+    audit_log.set_format(AuditLogFormat::JSONAuditLogFormat);
+    audit_log.set_file_path1(path);
+    // every entry goes to a file of its own
+    let mut rotation = AuditLogRotation::new();
+    rotation.m_max_size = 1;
+    rotation.m_retention = 2;
+    audit_log.set_rotation(rotation);
+    audit_log.set_body_limit(16);
+    audit_log.set_disk_budget(1);
+    audit_log.init().unwrap();
+    let rules = Arc::new(rules);
+
+    let request_body = |path: &str| {
+        let log = fs::read_to_string(path).unwrap();
+        assert_eq!(log.lines().count(), 1);
+        let json: serde_json::Value = serde_json::from_str(&log).unwrap();
+        json["transaction"]["request"]["body"]
+            .as_str()
+            .map(String::from)
+    };
+    for i in 1..=4 {
+        let mut t = Transaction::new_with_id(rules.clone(), &format!("unique-{}", i));
+        t.process_connection("10.0.0.1", 12345, "10.0.0.2", 80);
+        t.process_uri("/", "POST", "1.1");
+        t.append_request_body(&[b'a'; 100]);
+        t.process_request_body();
+        t.process_logging().unwrap();
+        assert!(rules.m_audit_log.flush(Duration::from_secs(5)));
+
+        if i == 1 {
+            let expected = format!("{}[...truncated 84 bytes]", "a".repeat(16));
+            assert_eq!(request_body(path), Some(expected));
+        }
+    }
+
+    // past the disk budget, the entries have no body
+    assert_eq!(request_body(path), None);
+    assert_eq!(request_body(&rotated_path(path, 2)), None);
+    assert!(!Path::new(&rotated_path(path, 3)).exists());
+    assert_eq!(
+        rules.m_audit_log.disk_usage(),
+        file_usage(path, 2),
+        "the rotated out entry is no longer accounted"
+    );
+}
+
+#[test]
+fn test_audit_log_shipper() {
+    use crate::{modsecurity::RulesSetProperties, transaction::Transaction};
+    use md5::{Digest, Md5};
+    use reader::AuditLogIndexEntry;
+    use shipper::{AuditLogShipper, DiskQueue, ShipperConfig};
+    use std::{
+        env::temp_dir,
+        fs,
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        path::Path,
+        sync::{mpsc, Arc},
+        thread,
+        time::Duration,
+    };
+
+    let dir = temp_dir().join("modsecurity-rs-shipper");
+    _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let queue_path = dir.join("queue");
+    let queue_path = queue_path.to_str().unwrap();
+
+    // stand-in collector, failing once before accepting the entry
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!(
+        "http://{}/rpc/auditLogReceiver",
+        listener.local_addr().unwrap()
+    );
+    let (sender, requests) = mpsc::channel();
+    let collector = thread::spawn(move || {
+        for status in ["503 Service Unavailable", "200 OK"] {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim_end().is_empty() {
+                    break;
+                }
+                head.push(line.trim_end().to_string());
+            }
+            let length = head
+                .iter()
+                .find_map(|h| {
+                    h.to_lowercase()
+                        .strip_prefix("content-length:")
+                        .map(|v| v.trim().parse().unwrap())
+                })
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let mut stream = reader.into_inner();
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            )
+            .unwrap();
+            sender.send((head, body)).unwrap();
+        }
+    });
+
+    let mut rules = RulesSetProperties::new();
+    let audit_log = &mut rules.m_audit_log;
+    audit_log.set_status(AuditLogStatus::OnAuditLogStatus);
+    audit_log.set_type(AuditLogType::ParallelAuditLogType);
+    audit_log.set_file_path1(dir.join("index.log").to_str().unwrap());
+    audit_log.set_storage_dir(dir.to_str().unwrap());
+    let mut config = ShipperConfig::new(&url, queue_path);
+    config.m_username = "user".to_string();
+    config.m_password = "pass".to_string();
+    audit_log.set_shipper(config);
+    audit_log.init().unwrap();
+
+    let mut t = Transaction::new_with_id(Arc::new(rules), "unique-5");
+    t.process_connection("10.0.0.1", 12345, "10.0.0.2", 80);
+    t.process_uri("/", "GET", "1.1");
+    t.add_request_header("Host", "example.com");
+    t.process_logging().unwrap();
+    assert!(t.m_rules.m_audit_log.flush(Duration::from_secs(5)));
+    collector.join().unwrap();
+
+    let (failed, _) = requests.recv().unwrap();
+    let (head, body) = requests.recv().unwrap();
+    assert_eq!(failed, head);
+    let header = |name: &str| {
+        head.iter()
+            .find_map(|h| {
+                let (key, value) = h.split_once(':')?;
+                key.eq_ignore_ascii_case(name)
+                    .then(|| value.trim().to_string())
+            })
+            .unwrap()
+    };
+    assert_eq!(head[0], "PUT /rpc/auditLogReceiver HTTP/1.1");
+    assert_eq!(header("Authorization"), "Basic dXNlcjpwYXNz");
+    let summary = header("X-ForensicLog-Summary");
+    assert!(
+        summary.starts_with("example.com 10.0.0.1 - - ["),
+        "{}",
+        summary
+    );
+    let md5 = Md5::digest(&body)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    assert_eq!(header("Content-MD5"), md5);
+    let index = AuditLogIndexEntry::parse(&summary).unwrap();
+    assert_eq!(index.m_id, "unique-5");
+    // delivered entries are removed
+    assert!(!dir.join(index.m_file.trim_start_matches('/')).exists());
+    assert!(DiskQueue::open(queue_path).unwrap().is_empty());
+
+    // the entries not yet delivered are kept for the next run
+    fs::write(dir.join(index.m_file.trim_start_matches('/')), &body).unwrap();
+    let queue_path = dir.join("queue2");
+    let config = ShipperConfig::new(&url, queue_path.to_str().unwrap());
+    let shipper = AuditLogShipper::new(&config, dir.to_str().unwrap(), Default::default()).unwrap();
+    shipper.ship(&summary).unwrap();
+    assert!(!shipper.flush(Duration::from_millis(50)));
+    drop(shipper);
+    assert!(Path::new(&queue_path).exists());
+    assert_eq!(
+        DiskQueue::open(queue_path.to_str().unwrap()).unwrap().len(),
+        1
+    );
+
+    // popped by moving the cursor, truncated once empty
+    let queue_path = dir.join("queue3");
+    let queue_path = queue_path.to_str().unwrap();
+    let mut queue = DiskQueue::open(queue_path).unwrap();
+    for line in ["a", "b", "c"] {
+        queue.push(line).unwrap();
+    }
+    assert_eq!(queue.pop().unwrap().as_deref(), Some("a"));
+    assert_eq!(fs::read_to_string(queue_path).unwrap(), "a\nb\nc\n");
+    let mut queue = DiskQueue::open(queue_path).unwrap();
+    assert_eq!(queue.len(), 2);
+    assert_eq!(queue.pop().unwrap().as_deref(), Some("b"));
+    // a line cut off by a crash is dropped
+    fs::OpenOptions::new()
+        .append(true)
+        .open(queue_path)
+        .unwrap()
+        .write_all(b"d")
+        .unwrap();
+    let mut queue = DiskQueue::open(queue_path).unwrap();
+    assert_eq!(queue.front().map(String::as_str), Some("c"));
+    queue.push("e").unwrap();
+    assert_eq!(queue.pop().unwrap().as_deref(), Some("c"));
+    assert_eq!(queue.pop().unwrap().as_deref(), Some("e"));
+    assert!(fs::read(queue_path).unwrap().is_empty());
+    assert!(DiskQueue::open(queue_path).unwrap().is_empty());
+}
@@ -0,0 +1,67 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::rules::RuleMessage;
+
+#[derive(Debug, Clone, Default)]
+pub struct AuditLogRequest {
+    pub m_method: String,
+    /// As received, in every format.
+    pub m_uri: String,
+    /// e.g. `1.1`
+    pub m_http_version: String,
+    /// Part B, in the logged order.
+    pub m_headers: Vec<(String, String)>,
+    /// Part C, None when it was not logged.
+    pub m_body: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuditLogResponse {
+    /// Only written in the native format.
+    pub m_http_version: String,
+    pub m_http_code: i32,
+    /// Part F, in the logged order.
+    pub m_headers: Vec<(String, String)>,
+    /// Part E, None when it was not logged.
+    pub m_body: Option<Vec<u8>>,
+}
+
+/// `producer` of the JSON format.
+#[derive(Debug, Clone, Default)]
+pub struct AuditLogProducer {
+    pub m_modsecurity: String,
+    pub m_connector: String,
+    pub m_secrules_engine: String,
+    pub m_components: Vec<String>,
+}
+
+/// One transaction read back from an audit log.
+#[derive(Debug, Clone, Default)]
+pub struct AuditLogEntry {
+    pub m_id: String,
+    /// As written: `[31/Dec/2023:23:59:59 +0000]` in the native format,
+    /// `Sun Dec 31 23:59:59 2023` in JSON.
+    pub m_timestamp: String,
+    pub m_client_ip_address: String,
+    pub m_client_port: u16,
+    pub m_server_ip_address: String,
+    pub m_server_port: u16,
+    pub m_request: AuditLogRequest,
+    pub m_response: AuditLogResponse,
+    /// Only written in the JSON format.
+    pub m_producer: Option<AuditLogProducer>,
+    /// Part H.
+    pub m_messages: Vec<RuleMessage>,
+}
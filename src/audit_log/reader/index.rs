@@ -0,0 +1,174 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    borrow::Cow,
+    fs::{self, File},
+    io::{BufRead, BufReader, Cursor, Lines},
+    path::PathBuf,
+};
+
+use md5::{Digest, Md5};
+
+use crate::utils::string::log_unescape;
+
+use super::{read_audit_log, AuditLogEntry};
+
+/// One line of the index of a concurrent audit log, see
+/// `Transaction::to_old_audit_log_format_index`. Fields logged as `-` are
+/// empty.
+#[derive(Debug, Clone, Default)]
+pub struct AuditLogIndexEntry {
+    pub m_host: String,
+    pub m_client_ip_address: String,
+    pub m_timestamp: String,
+    pub m_request_line: String,
+    pub m_http_code: i32,
+    pub m_bytes_sent: usize,
+    pub m_referer: String,
+    pub m_user_agent: String,
+    pub m_id: String,
+    pub m_session_id: String,
+    /// Path of the entry, relative to SecAuditLogStorageDir.
+    pub m_file: String,
+    pub m_offset: usize,
+    pub m_size: usize,
+    pub m_md5: String,
+}
+
+/// Split on spaces, keeping `"quoted strings"` and `[dates]` whole. The
+/// quoted strings are unescaped, see `log_escape`.
+fn tokens(line: &str) -> Vec<Cow<'_, str>> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        let (token, next) = if let Some(quoted) = rest.strip_prefix('"') {
+            // the closing quote is the first one not escaped
+            let mut escaped = false;
+            let end = quoted.bytes().position(|c| {
+                let close = c == b'"' && !escaped;
+                escaped = c == b'\\' && !escaped;
+                close
+            });
+            let (token, next) = match end {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            };
+            (Cow::Owned(log_unescape(token)), next)
+        } else if rest.starts_with('[') {
+            match rest.find(']') {
+                Some(end) => (Cow::Borrowed(&rest[..end + 1]), &rest[end + 1..]),
+                None => (Cow::Borrowed(rest), ""),
+            }
+        } else {
+            let (token, next) = rest.split_once(' ').unwrap_or((rest, ""));
+            (Cow::Borrowed(token), next)
+        };
+        tokens.push(token);
+        rest = next.trim_start();
+    }
+    tokens
+}
+
+impl AuditLogIndexEntry {
+    pub fn parse(line: &str) -> Result<AuditLogIndexEntry, String> {
+        let tokens = tokens(line);
+        if tokens.len() < 16 {
+            return Err(format!("Invalid audit log index line: {}", line));
+        }
+        let field = |i: usize| match tokens[i].as_ref() {
+            "-" => String::new(),
+            token => token.to_string(),
+        };
+
+        Ok(AuditLogIndexEntry {
+            m_host: field(0),
+            m_client_ip_address: field(1),
+            m_timestamp: field(4),
+            m_request_line: field(5),
+            m_http_code: tokens[6].parse().unwrap_or_default(),
+            m_bytes_sent: tokens[7].parse().unwrap_or_default(),
+            m_referer: field(8),
+            m_user_agent: field(9),
+            m_id: field(10),
+            m_session_id: field(11),
+            m_file: field(12),
+            m_offset: tokens[13].parse().unwrap_or_default(),
+            m_size: tokens[14].parse().unwrap_or_default(),
+            m_md5: tokens[15].trim_start_matches("md5:").to_string(),
+        })
+    }
+}
+
+/// Entries of a concurrent audit log, following its index (SecAuditLog2,
+/// or SecAuditLog) and reading each entry from SecAuditLogStorageDir. The
+/// md5 of the index is checked before parsing the entry.
+pub struct ConcurrentAuditLogReader {
+    m_storage_dir: PathBuf,
+    m_index: Lines<BufReader<File>>,
+}
+
+impl ConcurrentAuditLogReader {
+    pub fn open(storage_dir: &str, index_path: &str) -> Result<ConcurrentAuditLogReader, String> {
+        let index = File::open(index_path)
+            .map_err(|e| format!("Failed to open the audit log index {}: {}", index_path, e))?;
+        Ok(ConcurrentAuditLogReader {
+            m_storage_dir: PathBuf::from(storage_dir),
+            m_index: BufReader::new(index).lines(),
+        })
+    }
+
+    fn read_entry(&self, index: &AuditLogIndexEntry) -> Result<AuditLogEntry, String> {
+        let path = self
+            .m_storage_dir
+            .join(index.m_file.trim_start_matches('/'));
+        let log = fs::read(&path)
+            .map_err(|e| format!("Failed to read the audit log {}: {}", path.display(), e))?;
+
+        let md5 = Md5::digest(&log)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        if md5 != index.m_md5 {
+            return Err(format!(
+                "Audit log {} does not match its md5 {}",
+                path.display(),
+                index.m_md5
+            ));
+        }
+
+        read_audit_log(Cursor::new(log))
+            .next()
+            .unwrap_or_else(|| Err(format!("Empty audit log {}", path.display())))
+    }
+}
+
+impl Iterator for ConcurrentAuditLogReader {
+    type Item = Result<(AuditLogIndexEntry, AuditLogEntry), String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = loop {
+            match self.m_index.next()? {
+                Err(e) => return Some(Err(format!("Failed to read the audit log index: {}", e))),
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => break line,
+            }
+        };
+
+        Some(AuditLogIndexEntry::parse(&line).and_then(|index| {
+            let entry = self.read_entry(&index)?;
+            Ok((index, entry))
+        }))
+    }
+}
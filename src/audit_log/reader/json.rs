@@ -0,0 +1,153 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::Read;
+
+use serde_json::{de::IoRead, Deserializer, StreamDeserializer, Value};
+
+use crate::rules::RuleMessage;
+
+use super::{AuditLogEntry, AuditLogProducer};
+
+/// Parser of the JSON audit log format, one entry per JSON object, see
+/// `Transaction::to_json`.
+pub struct JSONAuditLogReader<R: Read> {
+    m_stream: StreamDeserializer<'static, IoRead<R>, Value>,
+    m_done: bool,
+}
+
+impl<R: Read> JSONAuditLogReader<R> {
+    pub fn new(reader: R) -> JSONAuditLogReader<R> {
+        JSONAuditLogReader {
+            m_stream: Deserializer::from_reader(reader).into_iter(),
+            m_done: false,
+        }
+    }
+}
+
+/// Strings as they are, numbers as written.
+fn string(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn number<T: std::str::FromStr + Default>(value: &Value) -> T {
+    string(value).parse().unwrap_or_default()
+}
+
+fn headers(value: &Value) -> Vec<(String, String)> {
+    value
+        .as_object()
+        .map(|headers| {
+            headers
+                .iter()
+                .map(|(key, value)| (key.clone(), string(value)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|values| values.iter().map(string).collect())
+        .unwrap_or_default()
+}
+
+fn rule_message(entry: &AuditLogEntry, value: &Value) -> RuleMessage {
+    let details = &value["details"];
+    let mut rm = RuleMessage::default();
+    rm.m_save_message.set(true);
+    rm.m_id = entry.m_id.clone();
+    rm.m_client_ip_address = entry.m_client_ip_address.clone();
+    rm.m_server_ip_address = entry.m_server_ip_address.clone();
+    rm.m_message = string(&value["message"]);
+    rm.m_match = string(&details["match"]);
+    rm.m_reference = string(&details["reference"]);
+    rm.m_rule_id = number(&details["ruleId"]);
+    rm.m_rule_file = string(&details["file"]);
+    rm.m_rule_line = number(&details["lineNumber"]);
+    rm.m_data = string(&details["data"]);
+    rm.m_severity = number(&details["severity"]);
+    rm.m_ver = string(&details["ver"]);
+    rm.m_rev = string(&details["rev"]);
+    rm.m_tags = strings(&details["tags"]);
+    rm.m_maturity = number(&details["maturity"]);
+    rm.m_accuracy = number(&details["accuracy"]);
+    rm
+}
+
+/// Map the `transaction` object to an entry.
+pub fn entry_from_json(value: &Value) -> Result<AuditLogEntry, String> {
+    let t = value
+        .get("transaction")
+        .filter(|t| t.is_object())
+        .ok_or_else(|| "Invalid JSON audit log entry: no transaction".to_string())?;
+
+    let mut entry = AuditLogEntry {
+        m_id: string(&t["unique_id"]),
+        m_timestamp: string(&t["time_stamp"]),
+        m_client_ip_address: string(&t["client_ip"]),
+        m_client_port: number(&t["client_port"]),
+        m_server_ip_address: string(&t["host_ip"]),
+        m_server_port: number(&t["host_port"]),
+        ..Default::default()
+    };
+
+    let request = &t["request"];
+    entry.m_request.m_method = string(&request["method"]);
+    entry.m_request.m_uri = string(&request["uri"]);
+    entry.m_request.m_http_version = string(&request["http_version"]);
+    entry.m_request.m_headers = headers(&request["headers"]);
+    entry.m_request.m_body = request.get("body").map(|b| string(b).into_bytes());
+
+    let response = &t["response"];
+    entry.m_response.m_http_code = number(&response["http_code"]);
+    entry.m_response.m_headers = headers(&response["headers"]);
+    entry.m_response.m_body = response.get("body").map(|b| string(b).into_bytes());
+
+    entry.m_producer = t.get("producer").map(|producer| AuditLogProducer {
+        m_modsecurity: string(&producer["modsecurity"]),
+        m_connector: string(&producer["connector"]),
+        m_secrules_engine: string(&producer["secrules_engine"]),
+        m_components: strings(&producer["components"]),
+    });
+    if let Some(messages) = t["messages"].as_array() {
+        let messages = messages.iter().map(|m| rule_message(&entry, m)).collect();
+        entry.m_messages = messages;
+    }
+
+    Ok(entry)
+}
+
+impl<R: Read> Iterator for JSONAuditLogReader<R> {
+    type Item = Result<AuditLogEntry, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.m_done {
+            return None;
+        }
+        match self.m_stream.next()? {
+            Ok(value) => Some(entry_from_json(&value)),
+            Err(e) => {
+                // the position in the stream is lost after a syntax error
+                self.m_done = true;
+                Some(Err(format!("Invalid JSON audit log entry: {}", e)))
+            }
+        }
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::BufRead;
+
+use crate::{rules::RuleMessage, utils::string::from_hex_if_needed};
+
+use super::AuditLogEntry;
+
+/// Streaming parser of the native (serial) audit log format, one entry per
+/// `next`, see `Transaction::to_old_audit_log_format`.
+///
+/// Lines before the first part A are skipped, so reading may start in the
+/// middle of a file that is still being written.
+pub struct NativeAuditLogReader<R: BufRead> {
+    m_reader: R,
+    m_done: bool,
+}
+
+/// `--<trailer>-<part>--` into its trailer and part.
+fn parse_boundary(line: &[u8]) -> Option<(&[u8], u8)> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    if line.len() < 7 || !line.starts_with(b"--") || !line.ends_with(b"--") {
+        return None;
+    }
+    let part = line[line.len() - 3];
+    if line[line.len() - 4] != b'-' || !part.is_ascii_uppercase() {
+        return None;
+    }
+    Some((&line[2..line.len() - 4], part))
+}
+
+fn parse_header(line: &str) -> (String, String) {
+    match line.split_once(':') {
+        Some((key, value)) => (key.to_string(), value.trim_start().to_string()),
+        None => (line.to_string(), String::new()),
+    }
+}
+
+/// Bodies are followed by two new lines.
+fn parse_body(content: &[u8]) -> &[u8] {
+    content.strip_suffix(b"\n\n").unwrap_or(content)
+}
+
+fn parse_part(entry: &mut AuditLogEntry, part: u8, content: &[u8]) -> Result<(), String> {
+    let text = String::from_utf8_lossy(content);
+    let mut lines = text.lines().filter(|line| !line.is_empty());
+
+    match part {
+        b'A' => {
+            let line = lines.next().unwrap_or_default();
+            let (timestamp, rest) = match line.find("] ") {
+                Some(end) if line.starts_with('[') => (&line[..end + 1], &line[end + 2..]),
+                _ => ("", line),
+            };
+            let fields: Vec<&str> = rest.split_whitespace().collect();
+            if fields.len() < 5 {
+                return Err(format!("Invalid audit log part A: {}", line));
+            }
+            entry.m_timestamp = timestamp.to_string();
+            entry.m_id = fields[0].to_string();
+            entry.m_client_ip_address = fields[1].to_string();
+            entry.m_client_port = fields[2].parse().unwrap_or_default();
+            entry.m_server_ip_address = fields[3].to_string();
+            entry.m_server_port = fields[4].parse().unwrap_or_default();
+        }
+        b'B' => {
+            // the uri is written as received, older logs decoded it and it
+            // may contain spaces
+            let line = lines.next().unwrap_or_default();
+            let (method, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let (uri, version) = rest.rsplit_once(' ').unwrap_or((rest, ""));
+            let request = &mut entry.m_request;
+            request.m_method = method.to_string();
+            request.m_uri = uri.to_string();
+            request.m_http_version = version.trim_start_matches("HTTP/").to_string();
+            request.m_headers = lines.map(parse_header).collect();
+        }
+        b'C' => entry.m_request.m_body = Some(parse_body(content).to_vec()),
+        b'E' => {
+            let body = String::from_utf8_lossy(parse_body(content));
+            entry.m_response.m_body = Some(from_hex_if_needed(&body));
+        }
+        b'F' => {
+            let line = lines.next().unwrap_or_default();
+            let (version, code) = line.split_once(' ').unwrap_or((line, ""));
+            let response = &mut entry.m_response;
+            response.m_http_version = version.trim_start_matches("HTTP/").to_string();
+            response.m_http_code = code.trim().parse().unwrap_or_default();
+            response.m_headers = lines.map(parse_header).collect();
+        }
+        b'H' => {
+            for line in lines {
+                entry.m_messages.push(RuleMessage::from_log(line)?);
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+impl<R: BufRead> NativeAuditLogReader<R> {
+    pub fn new(reader: R) -> NativeAuditLogReader<R> {
+        NativeAuditLogReader {
+            m_reader: reader,
+            m_done: false,
+        }
+    }
+
+    /// Read a line, false at the end of the input.
+    fn read_line(&mut self, line: &mut Vec<u8>) -> Result<bool, String> {
+        line.clear();
+        match self.m_reader.read_until(b'\n', line) {
+            Ok(n) => Ok(n > 0),
+            Err(e) => {
+                self.m_done = true;
+                Err(format!("Failed to read the audit log: {}", e))
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for NativeAuditLogReader<R> {
+    type Item = Result<AuditLogEntry, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.m_done {
+            return None;
+        }
+
+        let mut line = Vec::new();
+        let trailer = loop {
+            match self.read_line(&mut line) {
+                Err(e) => return Some(Err(e)),
+                Ok(false) => {
+                    self.m_done = true;
+                    return None;
+                }
+                Ok(true) => {
+                    if let Some((trailer, b'A')) = parse_boundary(&line) {
+                        break trailer.to_vec();
+                    }
+                }
+            }
+        };
+
+        let mut entry = AuditLogEntry::default();
+        let mut part = b'A';
+        let mut content = Vec::new();
+        loop {
+            match self.read_line(&mut line) {
+                Err(e) => return Some(Err(e)),
+                Ok(false) => {
+                    self.m_done = true;
+                    return Some(Err(format!(
+                        "Truncated audit log entry {}",
+                        String::from_utf8_lossy(&trailer)
+                    )));
+                }
+                Ok(true) => {}
+            }
+
+            match parse_boundary(&line) {
+                Some((t, next)) if t == trailer.as_slice() => {
+                    if let Err(e) = parse_part(&mut entry, part, &content) {
+                        return Some(Err(e));
+                    }
+                    if next == b'Z' {
+                        return Some(Ok(entry));
+                    }
+                    part = next;
+                    content.clear();
+                }
+                _ => content.extend_from_slice(&line),
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+};
+
+use super::{AuditLogEntry, JSONAuditLogReader, NativeAuditLogReader};
+
+/// Entries of a serial audit log, the format (native or JSON) is told by
+/// the first byte that is not a space.
+pub fn read_audit_log<'r, R: BufRead + 'r>(
+    mut reader: R,
+) -> Box<dyn Iterator<Item = Result<AuditLogEntry, String>> + 'r> {
+    let is_json = loop {
+        let (skip, first) = match reader.fill_buf() {
+            Ok(buf) => match buf.iter().position(|c| !c.is_ascii_whitespace()) {
+                Some(i) => (i, Some(buf[i])),
+                None => (buf.len(), None),
+            },
+            // reported by the native reader
+            Err(_) => break false,
+        };
+        if skip == 0 && first.is_none() {
+            // end of the input
+            break false;
+        }
+        reader.consume(skip);
+        if let Some(first) = first {
+            break first == b'{';
+        }
+    };
+
+    if is_json {
+        Box::new(JSONAuditLogReader::new(reader))
+    } else {
+        Box::new(NativeAuditLogReader::new(reader))
+    }
+}
+
+/// Open a serial audit log file, see `read_audit_log`.
+pub fn open_audit_log(
+    path: &str,
+) -> Result<Box<dyn Iterator<Item = Result<AuditLogEntry, String>>>, String> {
+    let file =
+        File::open(path).map_err(|e| format!("Failed to open the audit log {}: {}", path, e))?;
+    Ok(read_audit_log(BufReader::new(file)))
+}
@@ -0,0 +1,215 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fs,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+
+/// Default number of rotated files kept, `<file>.1` being the most recent.
+pub const DEFAULT_RETENTION: usize = 5;
+
+/// Rotation of a serial audit log file.
+///
+/// The file is renamed to `<file>.1`, the older ones shifted up to
+/// `<file>.<retention>`, once it would grow past `m_max_size` or was opened
+/// more than `m_interval` ago; a zero size or no interval disables the
+/// corresponding trigger.
+#[derive(Clone)]
+pub struct AuditLogRotation {
+    pub m_max_size: u64,
+    pub m_interval: Option<Duration>,
+    pub m_retention: usize,
+}
+
+impl AuditLogRotation {
+    pub fn new() -> AuditLogRotation {
+        AuditLogRotation {
+            m_max_size: 0,
+            m_interval: None,
+            m_retention: DEFAULT_RETENTION,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.m_max_size > 0 || self.m_interval.is_some()
+    }
+
+    /// Whether a file of `size` bytes, opened at `opened`, must be rotated
+    /// before `entry_len` more bytes are appended. An empty file is never
+    /// rotated, an entry larger than `m_max_size` goes to a file of its own.
+    pub fn should_rotate(&self, size: u64, entry_len: u64, opened: SystemTime) -> bool {
+        if size == 0 {
+            return false;
+        }
+        if self.m_max_size > 0 && size + entry_len > self.m_max_size {
+            return true;
+        }
+        match self.m_interval {
+            None => false,
+            Some(interval) => opened.elapsed().is_ok_and(|elapsed| elapsed >= interval),
+        }
+    }
+}
+
+impl Default for AuditLogRotation {
+    fn default() -> Self {
+        AuditLogRotation::new()
+    }
+}
+
+/// Path of the `n`th rotated file of `path`.
+pub fn rotated_path(path: &str, n: usize) -> String {
+    format!("{}.{}", path, n)
+}
+
+fn file_size(path: &str) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or_default()
+}
+
+/// Rotate `path`, keeping `retention` rotated files. Returns the number of
+/// bytes freed by the files deleted.
+pub fn rotate(path: &str, retention: usize) -> Result<u64, String> {
+    let rename = |from: &str, to: &str| {
+        fs::rename(from, to)
+            .map_err(|e| format!("Failed to rotate the audit log {} to {}: {}", from, to, e))
+    };
+
+    if retention == 0 {
+        let freed = file_size(path);
+        fs::remove_file(path)
+            .map_err(|e| format!("Failed to remove the audit log {}: {}", path, e))?;
+        return Ok(freed);
+    }
+
+    let mut freed = 0;
+    let oldest = rotated_path(path, retention);
+    if Path::new(&oldest).exists() {
+        freed = file_size(&oldest);
+        fs::remove_file(&oldest)
+            .map_err(|e| format!("Failed to remove the audit log {}: {}", oldest, e))?;
+    }
+    for n in (1..retention).rev() {
+        let from = rotated_path(path, n);
+        if Path::new(&from).exists() {
+            rename(&from, &rotated_path(path, n + 1))?;
+        }
+    }
+    rename(path, &rotated_path(path, 1))?;
+
+    Ok(freed)
+}
+
+/// Size of `path` and of its rotated files.
+pub fn file_usage(path: &str, retention: usize) -> u64 {
+    (1..=retention).fold(file_size(path), |total, n| {
+        total + file_size(&rotated_path(path, n))
+    })
+}
+
+/// Size of the files under `path`, recursively.
+pub fn dir_usage(path: &Path) -> u64 {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(m) if m.is_dir() => dir_usage(&entry.path()),
+            Ok(m) => m.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Bytes taken on disk by the audit log, kept up to date by the writers
+/// and checked against the disk budget of `AuditLog`.
+#[derive(Default)]
+pub struct DiskUsage {
+    m_bytes: AtomicU64,
+}
+
+impl DiskUsage {
+    pub fn new() -> DiskUsage {
+        DiskUsage::default()
+    }
+
+    pub fn get(&self) -> u64 {
+        self.m_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, bytes: u64) {
+        self.m_bytes.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn add(&self, bytes: u64) {
+        self.m_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn remove(&self, bytes: u64) {
+        _ = self
+            .m_bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |b| {
+                Some(b.saturating_sub(bytes))
+            });
+    }
+}
+
+/// Parse a size, in bytes or with a `K`, `M` or `G` suffix, e.g. `100M`.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (digits, unit) = match value.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&value[..i], c.to_ascii_uppercase()),
+        _ => (value, 'B'),
+    };
+    let multiplier: u64 = match unit {
+        'B' => 1,
+        'K' => 1 << 10,
+        'M' => 1 << 20,
+        'G' => 1 << 30,
+        _ => return Err(format!("Invalid size: {}", value)),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("Invalid size: {}", value))
+}
+
+/// Parse an interval, in seconds or with a `s`, `m`, `h` or `d` suffix,
+/// e.g. `1d`.
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (digits, unit) = match value.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&value[..i], c.to_ascii_lowercase()),
+        _ => (value, 's'),
+    };
+    let multiplier: u64 = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        _ => return Err(format!("Invalid interval: {}", value)),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .filter(|n| *n > 0)
+        .and_then(|n| n.checked_mul(multiplier))
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("Invalid interval: {}", value))
+}
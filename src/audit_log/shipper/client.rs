@@ -0,0 +1,90 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::audit_log::reader::AuditLogIndexEntry;
+
+/// Outcome of a delivery the collector answered.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    Delivered,
+    /// Refused by the collector, e.g. 400, retrying would not help.
+    Rejected(String),
+}
+
+/// Client of an HTTP(S) audit log collector, speaking the protocol of
+/// mlogc: every entry of a concurrent audit log is PUT to the collector
+/// URL with its index line in `X-ForensicLog-Summary` and the md5 of the
+/// entry, as found on the index line, in `Content-MD5`.
+pub struct CollectorClient {
+    m_url: String,
+    m_authorization: Option<String>,
+    m_agent: ureq::Agent,
+}
+
+impl CollectorClient {
+    pub fn new(url: &str, timeout: Duration) -> CollectorClient {
+        CollectorClient {
+            m_url: url.to_string(),
+            m_authorization: None,
+            m_agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+        }
+    }
+
+    /// Basic authentication to the collector.
+    pub fn set_credentials(&mut self, username: &str, password: &str) {
+        let credentials = STANDARD.encode(format!("{}:{}", username, password));
+        self.m_authorization = Some(format!("Basic {}", credentials));
+    }
+
+    /// PUT the `entry` described by `index`, the index line `summary`.
+    ///
+    /// 2xx and 409, the entry being already stored, are deliveries; other
+    /// 4xx but 408 and 429 are rejections. Errors are worth a retry: the
+    /// collector is unreachable, timed out or failed with a 5xx.
+    pub fn put(
+        &self,
+        summary: &str,
+        index: &AuditLogIndexEntry,
+        entry: &[u8],
+    ) -> Result<DeliveryStatus, String> {
+        let mut request = self
+            .m_agent
+            .put(&self.m_url)
+            .set("Content-Type", "application/octet-stream")
+            .set("X-ForensicLog-Summary", summary)
+            .set("Content-MD5", &index.m_md5);
+        if let Some(authorization) = &self.m_authorization {
+            request = request.set("Authorization", authorization);
+        }
+
+        match request.send_bytes(entry) {
+            Ok(_) | Err(ureq::Error::Status(409, _)) => Ok(DeliveryStatus::Delivered),
+            Err(ureq::Error::Status(code, _)) if code == 408 || code == 429 || code >= 500 => {
+                Err(format!("Audit log collector failed with status {}", code))
+            }
+            Err(ureq::Error::Status(code, _)) => Ok(DeliveryStatus::Rejected(format!(
+                "Audit log collector refused entry {} with status {}",
+                index.m_id, code
+            ))),
+            Err(ureq::Error::Transport(e)) => Err(format!(
+                "Failed to reach the audit log collector {}: {}",
+                self.m_url, e
+            )),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+};
+
+/// The consumed lines are dropped from the file once they take this many
+/// bytes and more than the lines left, see `DiskQueue::pop`.
+const COMPACTION_SIZE: u64 = 1024 * 1024;
+
+/// Index lines waiting to be shipped, one per line of a file so that the
+/// entries not yet delivered are resumed after a restart.
+///
+/// Lines are appended to the file and popped by moving a cursor, the offset
+/// of the first line left, kept in `path.cursor`. Both are synced to disk
+/// before returning. The file is truncated once empty and compacted once
+/// mostly consumed: a crash while compacting ships the consumed lines again.
+pub struct DiskQueue {
+    m_path: String,
+    m_cursor_path: String,
+    /// The lines left and the offsets of their ends in the file.
+    m_lines: VecDeque<(String, u64)>,
+    m_offset: u64,
+    m_size: u64,
+}
+
+impl DiskQueue {
+    /// Open the queue stored at `path`, created when missing.
+    pub fn open(path: &str) -> Result<DiskQueue, String> {
+        let read_error = |e| format!("Failed to read the shipper queue {}: {}", path, e);
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(read_error(e)),
+        };
+        let cursor_path = format!("{}.cursor", path);
+        let offset = match fs::read_to_string(&cursor_path) {
+            // an unreadable cursor ships the whole file again
+            Ok(cursor) => cursor.trim().parse::<usize>().unwrap_or(0),
+            Err(e) if e.kind() == ErrorKind::NotFound => 0,
+            Err(e) => return Err(read_error(e)),
+        };
+
+        // a line without its newline is a write interrupted by a crash, and
+        // a cursor past the end a truncation
+        let size = content
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map_or(0, |i| i + 1);
+        if size < content.len() {
+            OpenOptions::new()
+                .write(true)
+                .open(path)
+                .and_then(|file| file.set_len(size as u64))
+                .map_err(|e| format!("Failed to write the shipper queue {}: {}", path, e))?;
+        }
+        let offset = if offset > size { 0 } else { offset };
+
+        let mut lines = VecDeque::new();
+        let mut end = offset;
+        for line in content[offset..size].split_inclusive(|b| *b == b'\n') {
+            end += line.len();
+            let line = String::from_utf8_lossy(line);
+            if !line.trim().is_empty() {
+                lines.push_back((line.trim_end().to_string(), end as u64));
+            }
+        }
+
+        Ok(DiskQueue {
+            m_path: path.to_string(),
+            m_cursor_path: cursor_path,
+            m_lines: lines,
+            m_offset: offset as u64,
+            m_size: size as u64,
+        })
+    }
+
+    /// Append `line`, synced to disk before returning.
+    pub fn push(&mut self, line: &str) -> Result<(), String> {
+        let line = format!("{}\n", line);
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.m_path)
+            .and_then(|mut file| {
+                // a partial write is cut off, for the next line to be whole
+                file.write_all(line.as_bytes())
+                    .and_then(|_| file.sync_data())
+                    .inspect_err(|_| _ = file.set_len(self.m_size))
+            })
+            .map_err(|e| self.write_error(e))?;
+        self.m_size += line.len() as u64;
+        self.m_lines
+            .push_back((line.trim_end().to_string(), self.m_size));
+        Ok(())
+    }
+
+    pub fn front(&self) -> Option<&String> {
+        self.m_lines.front().map(|(line, _)| line)
+    }
+
+    /// Remove the first line, moving the cursor past it.
+    pub fn pop(&mut self) -> Result<Option<String>, String> {
+        let (line, end) = match self.m_lines.pop_front() {
+            None => return Ok(None),
+            Some(line) => line,
+        };
+
+        if self.m_lines.is_empty() {
+            // the cursor, left past the end by a crash, is reset by `open`
+            self.truncate()?;
+        } else if end >= COMPACTION_SIZE && end * 2 >= self.m_size {
+            self.compact()?;
+        } else {
+            self.m_offset = end;
+        }
+        self.write_cursor()?;
+
+        Ok(Some(line))
+    }
+
+    pub fn len(&self) -> usize {
+        self.m_lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.m_lines.is_empty()
+    }
+
+    fn truncate(&mut self) -> Result<(), String> {
+        OpenOptions::new()
+            .write(true)
+            .open(&self.m_path)
+            .and_then(|file| {
+                file.set_len(0)?;
+                file.sync_data()
+            })
+            .map_err(|e| self.write_error(e))?;
+        self.m_offset = 0;
+        self.m_size = 0;
+        Ok(())
+    }
+
+    /// Replace the file by the lines left, the cursor being reset first.
+    fn compact(&mut self) -> Result<(), String> {
+        let tmp = format!("{}.tmp", self.m_path);
+        let mut size = 0;
+        for (line, end) in self.m_lines.iter_mut() {
+            size += line.len() as u64 + 1;
+            *end = size;
+        }
+        let content = self
+            .m_lines
+            .iter()
+            .map(|(line, _)| format!("{}\n", line))
+            .collect::<String>();
+        File::create(&tmp)
+            .and_then(|mut file| {
+                file.write_all(content.as_bytes())?;
+                file.sync_data()
+            })
+            .map_err(|e| self.write_error(e))?;
+        self.m_offset = 0;
+        self.m_size = size;
+        self.write_cursor()?;
+        fs::rename(&tmp, &self.m_path).map_err(|e| self.write_error(e))
+    }
+
+    /// The cursor is overwritten in place, with a fixed width.
+    fn write_cursor(&self) -> Result<(), String> {
+        OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&self.m_cursor_path)
+            .and_then(|mut file| {
+                file.write_all(format!("{:020}\n", self.m_offset).as_bytes())?;
+                file.sync_data()
+            })
+            .map_err(|e| self.write_error(e))
+    }
+
+    fn write_error(&self, e: std::io::Error) -> String {
+        format!("Failed to write the shipper queue {}: {}", self.m_path, e)
+    }
+}
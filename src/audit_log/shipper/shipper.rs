@@ -0,0 +1,298 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{audit_log::reader::AuditLogIndexEntry, debug_log::DebugLog};
+
+use super::{CollectorClient, DeliveryStatus, DiskQueue};
+
+/// Delays between the attempts to deliver an entry while the collector is
+/// failing, the last one repeated until it recovers.
+const RETRY_DELAYS: [Duration; 4] = [
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+    Duration::from_secs(60),
+];
+
+/// Configuration of the shipping of a concurrent audit log to a collector.
+#[derive(Clone)]
+pub struct ShipperConfig {
+    /// Collector URL, e.g. `https://collector:8888/rpc/auditLogReceiver`
+    pub m_url: String,
+    /// Basic authentication, no authentication when the username is empty
+    pub m_username: String,
+    pub m_password: String,
+    /// File of the index lines not yet delivered
+    pub m_queue_path: String,
+    /// Keep the entry files once delivered
+    pub m_keep_entries: bool,
+    /// Timeout of a request to the collector
+    pub m_timeout: Duration,
+}
+
+impl ShipperConfig {
+    pub fn new(url: &str, queue_path: &str) -> ShipperConfig {
+        ShipperConfig {
+            m_url: url.to_string(),
+            m_username: String::new(),
+            m_password: String::new(),
+            m_queue_path: queue_path.to_string(),
+            m_keep_entries: false,
+            m_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+struct ShipperState {
+    /// Entries shipped and not yet delivered, queued or not.
+    m_pending: AtomicUsize,
+    m_shipped: AtomicUsize,
+    m_rejected: AtomicUsize,
+}
+
+/// Ships the entries of a concurrent audit log to a collector, the
+/// built-in equivalent of mlogc.
+///
+/// The index lines are handed over to a worker thread, which queues them on
+/// disk and PUTs the entries they point to, in order, retrying while the
+/// collector fails. The queue left by a previous run is shipped first.
+pub struct AuditLogShipper {
+    m_state: Arc<ShipperState>,
+    /// Dropped to stop the worker.
+    m_sender: Option<Sender<String>>,
+    m_worker: Option<JoinHandle<()>>,
+}
+
+impl AuditLogShipper {
+    pub fn new(
+        config: &ShipperConfig,
+        storage_dir: &str,
+        debug_log: Arc<DebugLog>,
+    ) -> Result<AuditLogShipper, String> {
+        let mut client = CollectorClient::new(&config.m_url, config.m_timeout);
+        if !config.m_username.is_empty() {
+            client.set_credentials(&config.m_username, &config.m_password);
+        }
+        let queue = DiskQueue::open(&config.m_queue_path)?;
+        let state = Arc::new(ShipperState {
+            m_pending: AtomicUsize::new(queue.len()),
+            m_shipped: AtomicUsize::new(0),
+            m_rejected: AtomicUsize::new(0),
+        });
+
+        let (sender, receiver) = mpsc::channel();
+        let worker_state = state.clone();
+        let storage_dir = PathBuf::from(storage_dir);
+        let keep_entries = config.m_keep_entries;
+        let worker = thread::Builder::new()
+            .name("modsecurity-audit-log-shipper".to_string())
+            .spawn(move || {
+                let worker = ShipperWorker {
+                    m_state: worker_state,
+                    m_queue: queue,
+                    m_receiver: receiver,
+                    m_debug_log: debug_log,
+                };
+                worker.run(client, storage_dir, keep_entries)
+            })
+            .map_err(|e| format!("Failed to start the audit log shipper: {}", e))?;
+
+        Ok(AuditLogShipper {
+            m_state: state,
+            m_sender: Some(sender),
+            m_worker: Some(worker),
+        })
+    }
+
+    /// Queue the entry of the index line `line` for shipping, without
+    /// waiting for the disk: the worker thread queues it.
+    pub fn ship(&self, line: &str) -> Result<(), String> {
+        self.m_state.m_pending.fetch_add(1, Ordering::Relaxed);
+        self.m_sender
+            .as_ref()
+            .and_then(|sender| sender.send(line.trim_end().to_string()).ok())
+            .ok_or_else(|| {
+                self.m_state.m_pending.fetch_sub(1, Ordering::Relaxed);
+                "The audit log shipper is stopped".to_string()
+            })
+    }
+
+    /// Wait up to `timeout` for the queue to be shipped, returns whether it
+    /// is empty.
+    pub fn flush(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.pending() > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        true
+    }
+
+    /// Number of entries waiting to be shipped.
+    pub fn pending(&self) -> usize {
+        self.m_state.m_pending.load(Ordering::Relaxed)
+    }
+
+    pub fn shipped(&self) -> usize {
+        self.m_state.m_shipped.load(Ordering::Relaxed)
+    }
+
+    /// Number of entries the collector refused, or that could not be read
+    /// or queued.
+    pub fn rejected(&self) -> usize {
+        self.m_state.m_rejected.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for AuditLogShipper {
+    /// Stop the worker, once its current request is answered or timed out;
+    /// the entries not yet shipped stay in the queue.
+    fn drop(&mut self) {
+        // the worker queues the lines received before stopping
+        self.m_sender.take();
+        if let Some(worker) = self.m_worker.take() {
+            _ = worker.join();
+        }
+    }
+}
+
+/// The worker thread of an `AuditLogShipper`, which owns the queue.
+struct ShipperWorker {
+    m_state: Arc<ShipperState>,
+    m_queue: DiskQueue,
+    m_receiver: Receiver<String>,
+    m_debug_log: Arc<DebugLog>,
+}
+
+impl ShipperWorker {
+    /// Worker loop, returns once the shipper is dropped.
+    fn run(mut self, client: CollectorClient, storage_dir: PathBuf, keep_entries: bool) {
+        let mut failures = 0;
+        loop {
+            loop {
+                match self.m_receiver.try_recv() {
+                    Ok(line) => self.push(&line),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+            let line = match self.m_queue.front() {
+                Some(line) => line.clone(),
+                None => match self.m_receiver.recv() {
+                    Ok(line) => {
+                        self.push(&line);
+                        continue;
+                    }
+                    Err(_) => return,
+                },
+            };
+
+            match ShipperWorker::deliver(&client, &storage_dir, &line, keep_entries) {
+                Err(e) => {
+                    let delay = RETRY_DELAYS[failures.min(RETRY_DELAYS.len() - 1)];
+                    failures += 1;
+                    self.m_debug_log.write(
+                        3,
+                        &format!(
+                            "Failed to ship the audit log entry, retrying in {:?}: {}",
+                            delay, e
+                        ),
+                    );
+                    // queuing what is shipped meanwhile, stopped early when
+                    // the shipper is dropped
+                    let deadline = Instant::now() + delay;
+                    loop {
+                        let timeout = deadline.saturating_duration_since(Instant::now());
+                        match self.m_receiver.recv_timeout(timeout) {
+                            Ok(line) => self.push(&line),
+                            Err(RecvTimeoutError::Timeout) => break,
+                            Err(RecvTimeoutError::Disconnected) => return,
+                        }
+                    }
+                    continue;
+                }
+                Ok(DeliveryStatus::Delivered) => {
+                    self.m_state.m_shipped.fetch_add(1, Ordering::Relaxed);
+                }
+                Ok(DeliveryStatus::Rejected(reason)) => {
+                    self.m_debug_log
+                        .write(1, &format!("Audit log entry dropped: {}", reason));
+                    self.m_state.m_rejected.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+
+            failures = 0;
+            if let Err(e) = self.m_queue.pop() {
+                self.m_debug_log.write(1, &e);
+            }
+            self.m_state.m_pending.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Queue `line` on disk, the entry is dropped when it cannot be.
+    fn push(&mut self, line: &str) {
+        if let Err(e) = self.m_queue.push(line) {
+            self.m_debug_log
+                .write(1, &format!("Audit log entry dropped: {}", e));
+            self.m_state.m_rejected.fetch_add(1, Ordering::Relaxed);
+            self.m_state.m_pending.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// PUT the entry of the index line `line`, removing its file once
+    /// delivered unless `keep_entries`. An index line that cannot be parsed
+    /// or points to a missing file is rejected.
+    fn deliver(
+        client: &CollectorClient,
+        storage_dir: &Path,
+        line: &str,
+        keep_entries: bool,
+    ) -> Result<DeliveryStatus, String> {
+        let index = match AuditLogIndexEntry::parse(line) {
+            Ok(index) => index,
+            Err(e) => return Ok(DeliveryStatus::Rejected(e)),
+        };
+        let path = storage_dir.join(index.m_file.trim_start_matches('/'));
+        let entry = match fs::read(&path) {
+            Ok(entry) => entry,
+            Err(e) => {
+                return Ok(DeliveryStatus::Rejected(format!(
+                    "Failed to read the audit log {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+
+        let status = client.put(line, &index, &entry)?;
+        if status == DeliveryStatus::Delivered && !keep_entries {
+            _ = fs::remove_file(&path);
+        }
+        Ok(status)
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{fs::File, io::Write, sync::Arc, time::SystemTime};
+
+use crate::audit_log::{file_usage, rotate, writer::open_append, AuditLogRotation, DiskUsage};
+
+use super::Sink;
+
+/// Entries appended to a file, the SecAuditLogType Serial target, rotated
+/// according to `m_rotation`.
+pub struct FileSink {
+    m_path: String,
+    m_file_permissions: u32,
+    m_file: File,
+    m_size: u64,
+    m_opened: SystemTime,
+    m_rotation: AuditLogRotation,
+    m_usage: Arc<DiskUsage>,
+}
+
+impl FileSink {
+    pub fn new(path: &str, file_permissions: u32) -> Result<FileSink, String> {
+        FileSink::with_rotation(
+            path,
+            file_permissions,
+            AuditLogRotation::new(),
+            Arc::new(DiskUsage::new()),
+        )
+    }
+
+    pub fn with_rotation(
+        path: &str,
+        file_permissions: u32,
+        rotation: AuditLogRotation,
+        usage: Arc<DiskUsage>,
+    ) -> Result<FileSink, String> {
+        let file = open_append(path, file_permissions)?;
+        let metadata = file
+            .metadata()
+            .map_err(|e| format!("Failed to open the audit log file {}: {}", path, e))?;
+        // the age of an existing file counts for the rotation interval
+        let opened = metadata
+            .created()
+            .or_else(|_| metadata.modified())
+            .unwrap_or_else(|_| SystemTime::now());
+
+        usage.add(file_usage(path, rotation.m_retention));
+
+        Ok(FileSink {
+            m_path: path.to_string(),
+            m_file_permissions: file_permissions,
+            m_file: file,
+            m_size: metadata.len(),
+            m_opened: opened,
+            m_rotation: rotation,
+            m_usage: usage,
+        })
+    }
+
+    fn rotate(&mut self) -> Result<(), String> {
+        let freed = rotate(&self.m_path, self.m_rotation.m_retention)?;
+        self.m_usage.remove(freed);
+
+        self.m_file = open_append(&self.m_path, self.m_file_permissions)?;
+        self.m_size = 0;
+        self.m_opened = SystemTime::now();
+        Ok(())
+    }
+}
+
+impl Sink for FileSink {
+    fn write_entry(&mut self, entry: &[u8]) -> Result<(), String> {
+        let len = entry.len() as u64;
+        if self
+            .m_rotation
+            .should_rotate(self.m_size, len, self.m_opened)
+        {
+            self.rotate()?;
+        }
+
+        self.m_file
+            .write_all(entry)
+            .map_err(|e| format!("Failed to write the audit log file {}: {}", self.m_path, e))?;
+        self.m_size += len;
+        self.m_usage.add(len);
+        Ok(())
+    }
+}
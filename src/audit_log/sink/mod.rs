@@ -0,0 +1,31 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod file;
+#[cfg(unix)]
+pub mod pipe;
+pub mod queue;
+pub mod sink;
+#[cfg(unix)]
+pub mod socket;
+pub mod syslog;
+
+pub use file::*;
+#[cfg(unix)]
+pub use pipe::*;
+pub use queue::*;
+pub use sink::*;
+#[cfg(unix)]
+pub use socket::*;
+pub use syslog::*;
@@ -0,0 +1,55 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+};
+
+use super::Sink;
+
+/// Entries written to a named pipe created by the reader, e.g. with
+/// `mkfifo`. Opening the pipe waits for the reader; when the reader goes
+/// away the pipe is opened again for the next entry.
+pub struct NamedPipeSink {
+    m_path: String,
+    m_pipe: Option<File>,
+}
+
+impl NamedPipeSink {
+    pub fn new(path: &str) -> NamedPipeSink {
+        NamedPipeSink {
+            m_path: path.to_string(),
+            m_pipe: None,
+        }
+    }
+}
+
+impl Sink for NamedPipeSink {
+    fn write_entry(&mut self, entry: &[u8]) -> Result<(), String> {
+        if self.m_pipe.is_none() {
+            let pipe = OpenOptions::new()
+                .write(true)
+                .open(&self.m_path)
+                .map_err(|e| format!("Failed to open the pipe {}: {}", self.m_path, e))?;
+            self.m_pipe = Some(pipe);
+        }
+
+        let pipe = self.m_pipe.as_mut().unwrap();
+        pipe.write_all(entry).map_err(|e| {
+            self.m_pipe = None;
+            format!("Failed to write to the pipe {}: {}", self.m_path, e)
+        })
+    }
+}
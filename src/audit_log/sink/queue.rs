@@ -0,0 +1,141 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::debug_log::DebugLog;
+
+use super::Sink;
+
+/// Default number of entries waiting for the sink.
+pub const DEFAULT_QUEUE_SIZE: usize = 1024;
+
+/// Delays between the attempts to deliver an entry to a failing sink.
+const RETRY_DELAYS: [Duration; 3] = [
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_millis(1000),
+];
+
+/// Bounded queue in front of a sink, drained by a worker thread.
+///
+/// Pushing never blocks: when the sink is stalled or failing and the queue
+/// is full, the entry is dropped and counted instead.
+pub struct SinkQueue {
+    m_sender: SyncSender<Vec<u8>>,
+    /// Entries queued and not yet delivered or dropped.
+    m_pending: Arc<AtomicUsize>,
+    m_dropped: Arc<AtomicUsize>,
+}
+
+impl SinkQueue {
+    pub fn new(sink: Box<dyn Sink>, capacity: usize) -> Result<SinkQueue, String> {
+        SinkQueue::with_debug_log(sink, capacity, Arc::new(DebugLog::new()))
+    }
+
+    /// Same as `new`, the entries the sink failed to take being reported to
+    /// `debug_log`.
+    pub fn with_debug_log(
+        sink: Box<dyn Sink>,
+        capacity: usize,
+        debug_log: Arc<DebugLog>,
+    ) -> Result<SinkQueue, String> {
+        let (sender, receiver) = sync_channel(capacity);
+        let m_pending = Arc::new(AtomicUsize::new(0));
+        let m_dropped = Arc::new(AtomicUsize::new(0));
+
+        let (pending, dropped) = (m_pending.clone(), m_dropped.clone());
+        thread::Builder::new()
+            .name("modsecurity-audit-log".to_string())
+            .spawn(move || SinkQueue::drain(sink, receiver, pending, dropped, debug_log))
+            .map_err(|e| format!("Failed to start the audit log worker: {}", e))?;
+
+        Ok(SinkQueue {
+            m_sender: sender,
+            m_pending,
+            m_dropped,
+        })
+    }
+
+    /// Worker loop, returns once the queue is dropped and emptied.
+    fn drain(
+        mut sink: Box<dyn Sink>,
+        receiver: Receiver<Vec<u8>>,
+        pending: Arc<AtomicUsize>,
+        dropped: Arc<AtomicUsize>,
+        debug_log: Arc<DebugLog>,
+    ) {
+        for entry in receiver {
+            let mut res = sink.write_entry(&entry);
+            for delay in RETRY_DELAYS {
+                if res.is_ok() {
+                    break;
+                }
+                thread::sleep(delay);
+                res = sink.write_entry(&entry);
+            }
+            if let Err(e) = res {
+                debug_log.write(1, &format!("Failed to deliver the audit log entry: {}", e));
+                dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            pending.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+
+    pub fn push(&self, entry: Vec<u8>) -> Result<(), String> {
+        self.m_pending.fetch_add(1, Ordering::AcqRel);
+        match self.m_sender.try_send(entry) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                self.m_pending.fetch_sub(1, Ordering::AcqRel);
+                self.m_dropped.fetch_add(1, Ordering::Relaxed);
+                match e {
+                    TrySendError::Full(_) => {
+                        Err("Audit log queue is full, entry dropped.".to_string())
+                    }
+                    TrySendError::Disconnected(_) => {
+                        Err("Audit log worker stopped, entry dropped.".to_string())
+                    }
+                }
+            }
+        }
+    }
+
+    /// Wait up to `timeout` for the queued entries to be delivered, returns
+    /// whether the queue is empty.
+    pub fn flush(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.m_pending.load(Ordering::Acquire) > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        true
+    }
+
+    /// Number of entries dropped because the queue was full or the sink
+    /// kept failing.
+    pub fn dropped(&self) -> usize {
+        self.m_dropped.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use crate::audit_log::{AuditLogRotation, DiskUsage};
+
+use super::{FileSink, SyslogSink, SyslogTransport};
+
+/// Destination of the serialized audit log entries.
+///
+/// Sinks are driven by the worker thread of a `SinkQueue`, they may block
+/// without stalling the transactions.
+pub trait Sink: Send {
+    /// Deliver one entry; on error the entry may be retried.
+    fn write_entry(&mut self, entry: &[u8]) -> Result<(), String>;
+}
+
+/// Open the sink described by SecAuditLog:
+///
+/// - `/path/to/file`, appended to, created with `file_permissions`
+/// - `unix:/path/to/socket`, Unix stream socket, entries prefixed by their
+///   length
+/// - `unixgram:/path/to/socket`, Unix datagram socket
+/// - `pipe:/path/to/fifo`, named pipe
+/// - `syslog:udp://host:port` or `syslog:unix:/dev/log`, RFC 5424 syslog
+pub fn open_sink(target: &str, file_permissions: u32) -> Result<Box<dyn Sink>, String> {
+    open_sink_with_rotation(
+        target,
+        file_permissions,
+        AuditLogRotation::new(),
+        Arc::new(DiskUsage::new()),
+    )
+}
+
+/// Same as `open_sink`, a file target being rotated according to
+/// `rotation` and its size accounted in `usage`.
+pub fn open_sink_with_rotation(
+    target: &str,
+    file_permissions: u32,
+    rotation: AuditLogRotation,
+    usage: Arc<DiskUsage>,
+) -> Result<Box<dyn Sink>, String> {
+    if let Some(address) = target.strip_prefix("syslog:") {
+        let transport = if let Some(address) = address.strip_prefix("udp://") {
+            SyslogTransport::Udp(address.to_string())
+        } else if let Some(path) = address.strip_prefix("unix:") {
+            SyslogTransport::Unix(path.to_string())
+        } else {
+            return Err(format!("Invalid syslog audit log target: {}", target));
+        };
+        return Ok(Box::new(SyslogSink::new(transport)?));
+    }
+
+    #[cfg(unix)]
+    {
+        use super::{NamedPipeSink, UnixDatagramSink, UnixStreamSink};
+
+        if let Some(path) = target.strip_prefix("unix:") {
+            return Ok(Box::new(UnixStreamSink::new(path)));
+        }
+        if let Some(path) = target.strip_prefix("unixgram:") {
+            return Ok(Box::new(UnixDatagramSink::new(path)?));
+        }
+        if let Some(path) = target.strip_prefix("pipe:") {
+            return Ok(Box::new(NamedPipeSink::new(path)));
+        }
+    }
+
+    Ok(Box::new(FileSink::with_rotation(
+        target,
+        file_permissions,
+        rotation,
+        usage,
+    )?))
+}
@@ -0,0 +1,88 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    io::Write,
+    os::unix::net::{UnixDatagram, UnixStream},
+};
+
+use super::Sink;
+
+/// Entries written to a Unix stream socket, one after the other, each one
+/// preceded by its length as a 4 bytes big endian integer. The connection
+/// is opened on the first entry and opened again after an error: an entry
+/// left incomplete by a connection is sent again, in full, on the next one,
+/// so that readers discard the incomplete frame a connection ends with.
+pub struct UnixStreamSink {
+    m_path: String,
+    m_stream: Option<UnixStream>,
+}
+
+impl UnixStreamSink {
+    pub fn new(path: &str) -> UnixStreamSink {
+        UnixStreamSink {
+            m_path: path.to_string(),
+            m_stream: None,
+        }
+    }
+}
+
+impl Sink for UnixStreamSink {
+    fn write_entry(&mut self, entry: &[u8]) -> Result<(), String> {
+        if self.m_stream.is_none() {
+            let stream = UnixStream::connect(&self.m_path)
+                .map_err(|e| format!("Failed to connect to {}: {}", self.m_path, e))?;
+            self.m_stream = Some(stream);
+        }
+
+        let length = u32::try_from(entry.len())
+            .map_err(|_| format!("Audit log entry too large for {}", self.m_path))?;
+        let mut frame = Vec::with_capacity(4 + entry.len());
+        frame.extend_from_slice(&length.to_be_bytes());
+        frame.extend_from_slice(entry);
+
+        let stream = self.m_stream.as_mut().unwrap();
+        stream.write_all(&frame).map_err(|e| {
+            self.m_stream = None;
+            format!("Failed to write to {}: {}", self.m_path, e)
+        })
+    }
+}
+
+/// One datagram per entry, sent to a Unix datagram socket; entries larger
+/// than the socket buffer are rejected by the kernel.
+pub struct UnixDatagramSink {
+    m_path: String,
+    m_socket: UnixDatagram,
+}
+
+impl UnixDatagramSink {
+    pub fn new(path: &str) -> Result<UnixDatagramSink, String> {
+        let socket =
+            UnixDatagram::unbound().map_err(|e| format!("Failed to create a socket: {}", e))?;
+        Ok(UnixDatagramSink {
+            m_path: path.to_string(),
+            m_socket: socket,
+        })
+    }
+}
+
+impl Sink for UnixDatagramSink {
+    fn write_entry(&mut self, entry: &[u8]) -> Result<(), String> {
+        self.m_socket
+            .send_to(entry, &self.m_path)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to send to {}: {}", self.m_path, e))
+    }
+}
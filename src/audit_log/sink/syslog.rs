@@ -0,0 +1,157 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::{
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    process,
+};
+
+use crate::utils::{system::hostname, time::UtcTime};
+
+use super::Sink;
+
+/// local0
+pub const SYSLOG_FACILITY: u8 = 16;
+/// informational
+pub const SYSLOG_SEVERITY: u8 = 6;
+/// Largest message sent over UDP, the largest UDP payload over IPv4.
+pub const SYSLOG_UDP_MAX_SIZE: usize = 65507;
+/// Ends the messages truncated to `SYSLOG_UDP_MAX_SIZE`.
+pub const SYSLOG_TRUNCATED_MARKER: &str = " [truncated]";
+
+pub enum SyslogTransport {
+    /// `host:port` of a syslog server.
+    Udp(String),
+    /// Path of the local syslog socket, e.g. `/dev/log`.
+    Unix(String),
+}
+
+enum SyslogSocket {
+    Udp(UdpSocket),
+    #[cfg(unix)]
+    Unix(UnixDatagram, String),
+}
+
+/// Entries sent as RFC 5424 syslog messages, one message per entry. Over
+/// UDP, the messages larger than `SYSLOG_UDP_MAX_SIZE` are truncated and
+/// end with `SYSLOG_TRUNCATED_MARKER`.
+pub struct SyslogSink {
+    m_socket: SyslogSocket,
+    m_hostname: String,
+    m_app_name: String,
+    m_facility: u8,
+}
+
+impl SyslogSink {
+    pub fn new(transport: SyslogTransport) -> Result<SyslogSink, String> {
+        let m_socket = match transport {
+            SyslogTransport::Udp(address) => {
+                let remote: SocketAddr = address
+                    .to_socket_addrs()
+                    .ok()
+                    .and_then(|mut addrs| addrs.next())
+                    .ok_or_else(|| format!("Invalid syslog address: {}", address))?;
+                let local = if remote.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                let socket = UdpSocket::bind(local)
+                    .and_then(|socket| socket.connect(remote).map(|_| socket))
+                    .map_err(|e| format!("Failed to open the syslog socket: {}", e))?;
+                SyslogSocket::Udp(socket)
+            }
+            #[cfg(unix)]
+            SyslogTransport::Unix(path) => {
+                let socket = UnixDatagram::unbound()
+                    .map_err(|e| format!("Failed to open the syslog socket: {}", e))?;
+                SyslogSocket::Unix(socket, path)
+            }
+            #[cfg(not(unix))]
+            SyslogTransport::Unix(path) => {
+                return Err(format!("Unix sockets are not supported: {}", path))
+            }
+        };
+
+        let m_hostname = hostname();
+        Ok(SyslogSink {
+            m_socket,
+            m_hostname: if m_hostname.is_empty() {
+                "-".to_string()
+            } else {
+                m_hostname
+            },
+            m_app_name: "modsecurity".to_string(),
+            m_facility: SYSLOG_FACILITY,
+        })
+    }
+
+    pub fn set_facility(&mut self, facility: u8) {
+        self.m_facility = facility;
+    }
+
+    /// `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID SD MSG`, without
+    /// structured data; the trailing new lines of the entry are removed.
+    pub fn format(&self, entry: &[u8]) -> Vec<u8> {
+        let t = UtcTime::now();
+        let mut msg = format!(
+            "<{}>1 {:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z {} {} {} - - ",
+            self.m_facility as u32 * 8 + SYSLOG_SEVERITY as u32,
+            t.year,
+            t.month,
+            t.day,
+            t.hour,
+            t.minute,
+            t.second,
+            self.m_hostname,
+            self.m_app_name,
+            process::id()
+        )
+        .into_bytes();
+        let end = entry.iter().rposition(|&c| c != b'\n').map_or(0, |i| i + 1);
+        msg.extend_from_slice(&entry[..end]);
+        msg
+    }
+}
+
+impl Sink for SyslogSink {
+    fn write_entry(&mut self, entry: &[u8]) -> Result<(), String> {
+        let mut msg = self.format(entry);
+        let sent = match &self.m_socket {
+            SyslogSocket::Udp(socket) => {
+                truncate(&mut msg, SYSLOG_UDP_MAX_SIZE);
+                socket.send(&msg)
+            }
+            #[cfg(unix)]
+            SyslogSocket::Unix(socket, path) => socket.send_to(&msg, path),
+        };
+        sent.map(|_| ())
+            .map_err(|e| format!("Failed to send the syslog message: {}", e))
+    }
+}
+
+/// Truncate `msg` to `max` bytes, marker included, on a character boundary.
+fn truncate(msg: &mut Vec<u8>, max: usize) {
+    if msg.len() <= max {
+        return;
+    }
+    let mut end = max - SYSLOG_TRUNCATED_MARKER.len();
+    while end > 0 && msg[end] & 0xc0 == 0x80 {
+        end -= 1;
+    }
+    msg.truncate(end);
+    msg.extend_from_slice(SYSLOG_TRUNCATED_MARKER.as_bytes());
+}
@@ -0,0 +1,209 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use md5::{Digest, Md5};
+
+use crate::{
+    audit_log::{
+        dir_usage,
+        shipper::{AuditLogShipper, ShipperConfig},
+        AuditLogFormat, DiskUsage,
+    },
+    debug_log::DebugLog,
+    transaction::Transaction,
+    utils::time::UtcTime,
+};
+
+use super::{audit_log_entry, open_append, Writer};
+
+/// SecAuditLogType Concurrent: every entry is written to its own file,
+/// `<SecAuditLogStorageDir>/%Y%m%d/%Y%m%d-%H%M/%Y%m%d-%H%M%S-<unique id>`,
+/// and an index line pointing to it is appended to SecAuditLog2, or to
+/// SecAuditLog when SecAuditLog2 is not set. With a shipper, the entries
+/// are also sent to a collector.
+pub struct Parallel {
+    m_storage_dir: String,
+    m_index_path: String,
+    m_file_permissions: u32,
+    m_directory_permissions: u32,
+    m_format: AuditLogFormat,
+    m_usage: Arc<DiskUsage>,
+    m_index: Mutex<Option<File>>,
+    m_shipper_config: Option<(ShipperConfig, Arc<DebugLog>)>,
+    m_shipper: Option<AuditLogShipper>,
+}
+
+impl Parallel {
+    pub fn new(
+        storage_dir: &str,
+        index_path: &str,
+        file_permissions: u32,
+        directory_permissions: u32,
+        format: AuditLogFormat,
+        usage: Arc<DiskUsage>,
+    ) -> Parallel {
+        Parallel {
+            m_storage_dir: storage_dir.trim_end_matches('/').to_string(),
+            m_index_path: index_path.to_string(),
+            m_file_permissions: file_permissions,
+            m_directory_permissions: directory_permissions,
+            m_format: format,
+            m_usage: usage,
+            m_index: Mutex::new(None),
+            m_shipper_config: None,
+            m_shipper: None,
+        }
+    }
+
+    /// Ship the entries to the collector of `config`, from `init`; the
+    /// shipping failures are reported to `debug_log`.
+    pub fn set_shipper(&mut self, config: ShipperConfig, debug_log: Arc<DebugLog>) {
+        self.m_shipper_config = Some((config, debug_log));
+    }
+
+    /// Relative path of the entry of `transaction`, e.g.
+    /// `/20231231/20231231-2359/20231231-235959-<unique id>`.
+    pub fn log_file_path(transaction: &Transaction) -> String {
+        let secs = transaction
+            .m_timestamp
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let t = UtcTime::from_unix(secs);
+
+        format!(
+            "/{}/{}-{:02}{:02}/{}-{}",
+            t.ymd(),
+            t.ymd(),
+            t.hour,
+            t.minute,
+            t.ymd_hms(),
+            transaction.m_id
+        )
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), String> {
+        if path.is_dir() {
+            return Ok(());
+        }
+
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(self.m_directory_permissions);
+        }
+        match builder.create(path) {
+            Ok(_) => Ok(()),
+            // created meanwhile by another transaction
+            Err(_) if path.is_dir() => Ok(()),
+            Err(e) => Err(format!(
+                "Failed to create the directory {}: {}",
+                path.display(),
+                e
+            )),
+        }
+    }
+}
+
+impl Writer for Parallel {
+    fn init(&mut self) -> Result<(), String> {
+        if self.m_storage_dir.is_empty() {
+            return Err("SecAuditLogStorageDir is not set.".to_string());
+        }
+        if !Path::new(&self.m_storage_dir).is_dir() {
+            return Err(format!(
+                "SecAuditLogStorageDir {} is not a directory.",
+                self.m_storage_dir
+            ));
+        }
+        self.m_usage.add(dir_usage(Path::new(&self.m_storage_dir)));
+        if !self.m_index_path.is_empty() {
+            let index = open_append(&self.m_index_path, self.m_file_permissions)?;
+            if !self.m_index_path.starts_with(&self.m_storage_dir) {
+                self.m_usage
+                    .add(index.metadata().map(|m| m.len()).unwrap_or_default());
+            }
+            *self.m_index.get_mut().unwrap_or_else(|e| e.into_inner()) = Some(index);
+        }
+        if let Some((config, debug_log)) = &self.m_shipper_config {
+            self.m_shipper = Some(AuditLogShipper::new(
+                config,
+                &self.m_storage_dir,
+                debug_log.clone(),
+            )?);
+        }
+
+        Ok(())
+    }
+
+    fn write(&self, transaction: &Transaction, parts: i32) -> Result<(), String> {
+        let log = audit_log_entry(transaction, parts, self.m_format);
+
+        let relative = Parallel::log_file_path(transaction);
+        let file_name = format!("{}{}", self.m_storage_dir, relative);
+        let path = Path::new(&file_name);
+        if let Some(time_dir) = path.parent() {
+            if let Some(day_dir) = time_dir.parent() {
+                self.create_dir(day_dir)?;
+            }
+            self.create_dir(time_dir)?;
+        }
+
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(self.m_file_permissions);
+        }
+        options
+            .open(path)
+            .and_then(|mut file| file.write_all(&log))
+            .map_err(|e| format!("Failed to write the audit log file {}: {}", file_name, e))?;
+        self.m_usage.add(log.len() as u64);
+
+        let md5 = Md5::digest(&log)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        let index = transaction.to_old_audit_log_format_index(&relative, log.len(), &md5);
+
+        let mut guard = self.m_index.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(file) = guard.as_mut() {
+            file.write_all(index.as_bytes())
+                .map_err(|e| format!("Failed to write the audit log index: {}", e))?;
+            self.m_usage.add(index.len() as u64);
+        }
+
+        match &self.m_shipper {
+            None => Ok(()),
+            Some(shipper) => shipper.ship(&index),
+        }
+    }
+
+    fn flush(&self, timeout: Duration) -> bool {
+        self.m_shipper
+            .as_ref()
+            .is_none_or(|shipper| shipper.flush(timeout))
+    }
+}
@@ -0,0 +1,120 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fs::{File, OpenOptions},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    audit_log::{
+        sink::{open_sink_with_rotation, SinkQueue},
+        AuditLogFormat, AuditLogRotation, DiskUsage,
+    },
+    debug_log::DebugLog,
+    transaction::Transaction,
+};
+
+use super::{audit_log_entry, Writer};
+
+/// SecAuditLogType Serial: every entry is sent to SecAuditLog, a file or
+/// one of the targets of `open_sink`, through a bounded queue. A file is
+/// rotated according to `m_rotation`.
+pub struct Serial {
+    m_path: String,
+    m_file_permissions: u32,
+    m_format: AuditLogFormat,
+    m_queue_size: usize,
+    m_rotation: AuditLogRotation,
+    m_usage: Arc<DiskUsage>,
+    m_debug_log: Arc<DebugLog>,
+    m_queue: Option<SinkQueue>,
+}
+
+impl Serial {
+    pub fn new(
+        path: &str,
+        file_permissions: u32,
+        format: AuditLogFormat,
+        queue_size: usize,
+        rotation: AuditLogRotation,
+        usage: Arc<DiskUsage>,
+        debug_log: Arc<DebugLog>,
+    ) -> Serial {
+        Serial {
+            m_path: path.to_string(),
+            m_file_permissions: file_permissions,
+            m_format: format,
+            m_queue_size: queue_size,
+            m_rotation: rotation,
+            m_usage: usage,
+            m_debug_log: debug_log,
+            m_queue: None,
+        }
+    }
+}
+
+/// Open `path` for appending, created with `permissions` when missing.
+pub fn open_append(path: &str, permissions: u32) -> Result<File, String> {
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(permissions);
+    }
+
+    options
+        .open(path)
+        .map_err(|e| format!("Failed to open the audit log file {}: {}", path, e))
+}
+
+impl Writer for Serial {
+    fn init(&mut self) -> Result<(), String> {
+        if self.m_path.is_empty() {
+            return Err("SecAuditLog is not set.".to_string());
+        }
+        let sink = open_sink_with_rotation(
+            &self.m_path,
+            self.m_file_permissions,
+            self.m_rotation.clone(),
+            self.m_usage.clone(),
+        )?;
+        self.m_queue = Some(SinkQueue::with_debug_log(
+            sink,
+            self.m_queue_size,
+            self.m_debug_log.clone(),
+        )?);
+
+        Ok(())
+    }
+
+    fn write(&self, transaction: &Transaction, parts: i32) -> Result<(), String> {
+        let msg = audit_log_entry(transaction, parts, self.m_format);
+
+        // one entry at a time, so that entries of concurrent transactions
+        // are not interleaved
+        match self.m_queue.as_ref() {
+            None => Err("Audit log sink is not open.".to_string()),
+            Some(queue) => queue.push(msg),
+        }
+    }
+
+    fn flush(&self, timeout: Duration) -> bool {
+        self.m_queue
+            .as_ref()
+            .is_none_or(|queue| queue.flush(timeout))
+    }
+}
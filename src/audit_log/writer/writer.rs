@@ -0,0 +1,50 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use rand::{distributions::Alphanumeric, Rng};
+
+use crate::{audit_log::AuditLogFormat, transaction::Transaction};
+
+/// Shared by the transactions, `write` is called concurrently.
+pub trait Writer: Send + Sync {
+    fn init(&mut self) -> Result<(), String>;
+    fn write(&self, transaction: &Transaction, parts: i32) -> Result<(), String>;
+
+    /// Wait up to `timeout` for the queued entries, if any, to be written.
+    fn flush(&self, _timeout: Duration) -> bool {
+        true
+    }
+}
+
+/// Random boundary of an audit log entry, 8 alphanumeric characters.
+pub fn generate_boundary() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(8)
+        .map(char::from)
+        .collect()
+}
+
+/// Serialize the audit log entry of `transaction` in `format`.
+pub fn audit_log_entry(transaction: &Transaction, parts: i32, format: AuditLogFormat) -> Vec<u8> {
+    match format {
+        AuditLogFormat::NativeAuditLogFormat => {
+            let trailer = format!("-{}--", generate_boundary());
+            transaction.to_old_audit_log_format(parts, &trailer)
+        }
+        AuditLogFormat::JSONAuditLogFormat => transaction.to_json(parts).into_bytes(),
+    }
+}
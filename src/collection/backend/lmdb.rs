@@ -12,252 +12,592 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{env::temp_dir, path::Path, sync::Arc};
+use std::{
+    env::temp_dir,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
 use lmdb_zero::{
-    open::Flags as OpenFlags,
-    put::Flags as PutFlags,
-    traits::{LmdbOrdKey, LmdbRaw},
-    Database, DatabaseOptions, EnvBuilder, Environment, ReadTransaction, WriteTransaction,
+    db, open::Flags as OpenFlags, put::Flags as PutFlags, ConstAccessor, Database, DatabaseOptions,
+    EnvBuilder, Environment, LmdbResultExt, ReadTransaction, WriteAccessor, WriteTransaction,
 };
 
 use crate::{
-    collection::Collection,
+    collection::{expiry_scopes, now_seconds, Collection},
+    modsecurity::Metrics,
+    utils::regex_cache::cached_regex,
     variables::{KeyExclusions, VariableValue},
 };
 
+/// The LMDB default map size, 10MiB.
+pub const DEFAULT_LMDB_MAP_SIZE: usize = 10 * 1024 * 1024;
+
+/// Where and how large the LMDB environments are.
+#[derive(Debug, Clone)]
+pub struct LmdbConfig {
+    /// Each collection is an environment in its own subdirectory, e.g.
+    /// `m_path/IP`.
+    pub m_path: PathBuf,
+    /// Maximum size of the data of a collection, in bytes.
+    pub m_map_size: usize,
+}
+
+impl LmdbConfig {
+    pub fn new(path: impl Into<PathBuf>, map_size: usize) -> LmdbConfig {
+        LmdbConfig {
+            m_path: path.into(),
+            m_map_size: map_size,
+        }
+    }
+}
+
+impl Default for LmdbConfig {
+    fn default() -> Self {
+        LmdbConfig::new(temp_dir().join("modsecurity"), DEFAULT_LMDB_MAP_SIZE)
+    }
+}
+
+/// Persistent collection stored in an LMDB environment, shared by all the
+/// processes opening the same path.
+///
+/// Every operation runs in its own transaction, writes are committed before
+/// returning and so visible to the readers of every process. Keys and
+/// values are limited to the LMDB maximum key size, 511 bytes by default,
+/// as values are sorted duplicates of their key. The expiry times of the
+/// keys and records are kept in a database of their own.
+///
+/// The `Collection` methods can not fail: their errors are kept and
+/// available through `last_error`, the inherent methods return them.
 pub struct LMDB {
     m_name: String,
-    db: Database<'static>,
-    w_txn: WriteTransaction<'static>,
-    r_txn: ReadTransaction<'static>,
-    env: Arc<Environment>,
+    /// The environment and its database, or why they could not be opened.
+    m_handle: Result<LmdbHandle, String>,
+    m_last_error: Mutex<Option<String>>,
+    m_metrics: Option<Arc<Metrics>>,
 }
 
-impl LMDB {
-    pub fn into_env(&self) -> &Environment {
-        self.env.as_ref()
+struct LmdbHandle {
+    m_db: Database<'static>,
+    m_expiry: Database<'static>,
+    m_env: Arc<Environment>,
+}
+
+impl LmdbHandle {
+    /// `key` itself or the records it belongs to, when expired.
+    fn expired_scopes<'k>(
+        &self,
+        access: &ConstAccessor,
+        key: &'k str,
+    ) -> Result<Vec<&'k str>, String> {
+        let now = now_seconds();
+        let mut expired = Vec::new();
+        for scope in expiry_scopes(key) {
+            let at = access
+                .get::<str, str>(&self.m_expiry, scope)
+                .to_opt()
+                .map_err(|e| format!("LMDB: failed to read the expiry of {scope}: {e}"))?;
+            if at
+                .and_then(|at| at.parse::<i64>().ok())
+                .is_some_and(|at| at <= now)
+            {
+                expired.push(scope);
+            }
+        }
+        Ok(expired)
+    }
+
+    fn is_expired(&self, access: &ConstAccessor, key: &str) -> Result<bool, String> {
+        Ok(!self.expired_scopes(access, key)?.is_empty())
+    }
+
+    /// Remove what expired of `key` before writing it.
+    fn purge_scopes(
+        &self,
+        txn: &WriteTransaction,
+        access: &mut WriteAccessor,
+        key: &str,
+    ) -> Result<(), String> {
+        for scope in self.expired_scopes(access, key)? {
+            self.purge(txn, access, scope)?;
+        }
+        Ok(())
+    }
+
+    fn first(&self, access: &ConstAccessor, key: &str) -> Result<Option<String>, String> {
+        Ok(access
+            .get::<str, str>(&self.m_db, key)
+            .to_opt()
+            .map_err(|e| format!("LMDB: failed to read {key}: {e}"))?
+            .map(str::to_string))
+    }
+
+    /// See `LMDB::put_first`.
+    fn put_first(
+        &self,
+        txn: &WriteTransaction,
+        access: &mut WriteAccessor,
+        key: &str,
+        value: &str,
+        insert: bool,
+    ) -> Result<bool, String> {
+        self.purge_scopes(txn, access, key)?;
+        match self.first(access, key)? {
+            Some(first) => access
+                .del_item(&self.m_db, key, first.as_str())
+                .map_err(|e| format!("LMDB: failed to delete {key}: {e}"))?,
+            None if !insert => return Ok(false),
+            None => {}
+        }
+        access
+            .put(&self.m_db, key, value, PutFlags::empty())
+            .map_err(|e| format!("LMDB: failed to store {key}: {e}"))?;
+        Ok(true)
+    }
+
+    /// Remove the expired key, or the keys of the expired record, `scope`.
+    fn purge(
+        &self,
+        txn: &WriteTransaction,
+        access: &mut WriteAccessor,
+        scope: &str,
+    ) -> Result<(), String> {
+        let read_error = |e| format!("LMDB: failed to read {scope}: {e}");
+        let prefix = format!("{scope}::");
+        let mut keys = vec![scope.to_string()];
+        {
+            let mut cursor = txn
+                .cursor(&self.m_db)
+                .map_err(|e| format!("LMDB: failed to open a cursor: {e}"))?;
+            let mut item = cursor
+                .seek_range_k::<str, str>(access, prefix.as_str())
+                .to_opt()
+                .map_err(read_error)?;
+            while let Some((k, _)) = item {
+                if !k.starts_with(&prefix) {
+                    break;
+                }
+                keys.push(k.to_string());
+                item = cursor
+                    .next_nodup::<str, str>(access)
+                    .to_opt()
+                    .map_err(read_error)?;
+            }
+        }
+
+        for key in keys.iter() {
+            let delete_error = |e| format!("LMDB: failed to delete {key}: {e}");
+            access
+                .del_key(&self.m_db, key.as_str())
+                .to_opt()
+                .map_err(delete_error)?;
+            access
+                .del_key(&self.m_expiry, key.as_str())
+                .to_opt()
+                .map_err(delete_error)?;
+        }
+        Ok(())
     }
 }
 
-impl Collection for LMDB {
-    fn new(name: &str) -> LMDB {
-        let path_buf = temp_dir().join(Path::new(name));
-        let db_path = path_buf.to_str().unwrap();
+impl LMDB {
+    /// Open, creating it if needed, the environment of the collection
+    /// `name` under `config.m_path`.
+    pub fn open(name: &str, config: &LmdbConfig) -> Result<LMDB, String> {
+        let path = config.m_path.join(name);
+        fs::create_dir_all(&path).map_err(|e| {
+            format!(
+                "LMDB: failed to create the directory {}: {}",
+                path.display(),
+                e
+            )
+        })?;
+        let db_path = path
+            .to_str()
+            .ok_or_else(|| format!("LMDB: invalid path {}", path.display()))?;
+
         let env = unsafe {
-            let env = EnvBuilder::new()
-                .unwrap()
+            let mut builder = EnvBuilder::new()
+                .map_err(|e| format!("LMDB: failed to create the environment: {e}"))?;
+            builder
+                .set_mapsize(config.m_map_size)
+                .map_err(|e| format!("LMDB: failed to set the map size: {e}"))?;
+            // values and expiry
+            builder
+                .set_maxdbs(2)
+                .map_err(|e| format!("LMDB: failed to set the number of databases: {e}"))?;
+            builder
                 .open(db_path, OpenFlags::empty(), 0o600)
-                .unwrap();
-
-            Arc::new(env)
+                .map_err(|e| format!("LMDB: failed to open {db_path}: {e}"))?
         };
+        let env = Arc::new(env);
 
-        let opts = DatabaseOptions::create_multimap::<LmdbKV, LmdbKV>();
-        let db = Database::open(env.clone(), Some(name), &opts).unwrap();
-        let w_txn = WriteTransaction::new(env.clone()).unwrap();
-        let r_txn = ReadTransaction::new(env.clone()).unwrap();
+        let opts = DatabaseOptions::new(db::CREATE | db::DUPSORT);
+        let db = Database::open(env.clone(), Some("values"), &opts)
+            .map_err(|e| format!("LMDB: failed to open the values of {name}: {e}"))?;
+        let opts = DatabaseOptions::new(db::CREATE);
+        let expiry = Database::open(env.clone(), Some("expiry"), &opts)
+            .map_err(|e| format!("LMDB: failed to open the expiry of {name}: {e}"))?;
 
+        Ok(LMDB::with_handle(
+            name,
+            Ok(LmdbHandle {
+                m_db: db,
+                m_expiry: expiry,
+                m_env: env,
+            }),
+        ))
+    }
+
+    fn with_handle(name: &str, handle: Result<LmdbHandle, String>) -> LMDB {
         LMDB {
             m_name: name.to_string(),
-            db,
-            w_txn,
-            r_txn,
-            env,
+            m_handle: handle,
+            m_last_error: Mutex::new(None),
+            m_metrics: None,
         }
     }
 
-    fn store(&mut self, key: String, value: String) {
-        let mut access = self.w_txn.access();
-        _ = access.put(
-            &self.db,
-            &LmdbKV::new(key.as_str()),
-            &LmdbKV::new(value.as_str()),
-            PutFlags::empty(),
-        );
-    }
-
-    fn update_first(&mut self, key: &str, value: &str) -> bool {
-        let mut ok = false;
-        let k = LmdbKV::from(key);
-        let v = LmdbKV::from(value);
-
-        let r_access = self.r_txn.access();
-        let first_item = r_access.get::<LmdbKV, LmdbKV>(&self.db, &k);
-        if let Ok(val) = first_item {
-            let mut w_access = self.w_txn.access();
-            _ = w_access.del_item(&self.db, &k, val);
-            _ = w_access.put(&self.db, &k, &v, PutFlags::empty());
-            ok = true;
-        };
-
-        ok
+    pub fn env(&self) -> Option<&Environment> {
+        self.m_handle.as_ref().ok().map(|h| h.m_env.as_ref())
     }
 
-    fn store_or_update_first(&mut self, key: &str, value: &str) -> bool {
-        if !self.update_first(key, value) {
-            self.store(key.to_string(), value.to_string());
-        }
-
-        return true;
+    fn handle(&self) -> Result<&LmdbHandle, String> {
+        self.m_handle.as_ref().map_err(|err| err.clone())
     }
 
-    fn del(&mut self, key: &str) {
-        let mut access = self.w_txn.access();
-        _ = access.del_key(&self.db, &LmdbKV::from(key));
+    /// The last error of a `Collection` method, cleared when returned.
+    pub fn last_error(&self) -> Option<String> {
+        self.m_last_error
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
     }
 
-    fn resolve_first(&self, key: &str) -> Option<&str> {
-        let r_access = self.r_txn.access();
-        let first_item = r_access.get::<LmdbKV, LmdbKV>(&self.db, &LmdbKV::from(key));
-        match first_item {
-            Ok(val) => Some(val.data),
-            _ => None,
-        }
+    /// Add `value` to the values of `key`.
+    pub fn put(&self, key: &str, value: &str) -> Result<(), String> {
+        self.timed("store", || {
+            self.write(|h, txn| {
+                let mut access = txn.access();
+                h.purge_scopes(txn, &mut access, key)?;
+                access
+                    .put(&h.m_db, key, value, PutFlags::empty())
+                    .map_err(|e| format!("LMDB: failed to store {key}: {e}"))
+            })
+        })
     }
 
-    fn resolve_single_match(&self, key: &str, mut l: Vec<VariableValue>) {
-        let k = LmdbKV::from(key);
-        let r_access = self.r_txn.access();
-        let mut cursor = self.r_txn.cursor(&self.db).unwrap();
-        _ = cursor.seek_k::<LmdbKV, LmdbKV>(&r_access, &k);
-        loop {
-            let item = cursor.get_multiple::<LmdbKV>(&r_access);
-            match item {
-                Ok(val) => l.push(VariableValue::new_with_collection(
-                    &self.m_name,
-                    key,
-                    val.data,
-                )),
-                _ => break,
-            }
-        }
+    /// Replace the first value of `key`, adding it when `key` has no value
+    /// and `insert` is set. Returns whether a value was written.
+    pub fn put_first(&self, key: &str, value: &str, insert: bool) -> Result<bool, String> {
+        self.timed("update", || {
+            self.write(|h, txn| h.put_first(txn, &mut txn.access(), key, value, insert))
+        })
     }
 
-    fn resolve_multi_matches(&self, key: &str, mut l: Vec<VariableValue>, ke: KeyExclusions) {
-        if key.len() == 0 {
-            let r_access = self.r_txn.access();
-            let mut cursor = self.r_txn.cursor(&self.db).unwrap();
-            let first_item = cursor.first::<LmdbKV, LmdbKV>(&r_access);
-            match first_item {
-                Err(_) => return,
-                Ok((k, v)) => {
-                    if !ke.to_omit(k.data) {
-                        l.insert(
-                            0,
-                            VariableValue::new_with_collection(&self.m_name, k.data, v.data),
-                        );
+    /// Create the record `compartment`, expiring at `at`, unless the first
+    /// of its `values` is set, in a single write transaction. Returns
+    /// whether it was created.
+    pub fn create(
+        &self,
+        compartment: &str,
+        values: &[(&str, &str)],
+        at: i64,
+    ) -> Result<bool, String> {
+        self.timed("create", || {
+            self.write(|h, txn| {
+                let mut access = txn.access();
+                for (i, (name, value)) in values.iter().enumerate() {
+                    let key = format!("{compartment}::{name}");
+                    h.purge_scopes(txn, &mut access, &key)?;
+                    if i == 0 && h.first(&access, &key)?.is_some() {
+                        return Ok(false);
                     }
+                    h.put_first(txn, &mut access, &key, value, true)?;
                 }
-            }
-            loop {
-                let next_item = cursor.next::<LmdbKV, LmdbKV>(&r_access);
-                match next_item {
-                    Err(_) => break,
-                    Ok((k, v)) => {
-                        if !ke.to_omit(k.data) {
-                            l.insert(
-                                0,
-                                VariableValue::new_with_collection(&self.m_name, k.data, v.data),
-                            );
+                access
+                    .put(
+                        &h.m_expiry,
+                        compartment,
+                        at.to_string().as_str(),
+                        PutFlags::empty(),
+                    )
+                    .map_err(|e| {
+                        format!("LMDB: failed to store the expiry of {compartment}: {e}")
+                    })?;
+                Ok(true)
+            })
+        })
+    }
+
+    /// Remove all the values of `key`, and its expiry.
+    pub fn delete(&self, key: &str) -> Result<(), String> {
+        self.timed("delete", || {
+            self.write(|h, txn| {
+                let mut access = txn.access();
+                let delete_error = |e| format!("LMDB: failed to delete {key}: {e}");
+                access
+                    .del_key(&h.m_db, key)
+                    .to_opt()
+                    .map_err(delete_error)?;
+                access
+                    .del_key(&h.m_expiry, key)
+                    .to_opt()
+                    .map_err(delete_error)?;
+                Ok(())
+            })
+        })
+    }
+
+    /// Expire the key, or record, `key` at `at`, in seconds since the
+    /// epoch.
+    pub fn expire(&self, key: &str, at: i64) -> Result<(), String> {
+        self.timed("expire", || {
+            self.write(|h, txn| {
+                txn.access()
+                    .put(&h.m_expiry, key, at.to_string().as_str(), PutFlags::empty())
+                    .map_err(|e| format!("LMDB: failed to store the expiry of {key}: {e}"))
+            })
+        })
+    }
+
+    /// Remove the expired keys and records, returns how many expired.
+    pub fn purge(&self) -> Result<usize, String> {
+        self.timed("purge", || {
+            self.write(|h, txn| {
+                let mut access = txn.access();
+                let now = now_seconds();
+                let read_error =
+                    |e| format!("LMDB: failed to read the expiry of {}: {e}", self.m_name);
+                let mut expired = Vec::new();
+                {
+                    let mut cursor = txn
+                        .cursor(&h.m_expiry)
+                        .map_err(|e| format!("LMDB: failed to open a cursor: {e}"))?;
+                    let mut item = cursor
+                        .first::<str, str>(&access)
+                        .to_opt()
+                        .map_err(read_error)?;
+                    while let Some((scope, at)) = item {
+                        if at.parse::<i64>().is_ok_and(|at| at <= now) {
+                            expired.push(scope.to_string());
                         }
+                        item = cursor
+                            .next::<str, str>(&access)
+                            .to_opt()
+                            .map_err(read_error)?;
                     }
                 }
+                for scope in expired.iter() {
+                    h.purge(txn, &mut access, scope)?;
+                }
+                Ok(expired.len())
+            })
+        })
+    }
+
+    /// First value of `key`, the smallest one.
+    pub fn get(&self, key: &str) -> Result<Option<String>, String> {
+        self.timed("get", || {
+            let h = self.handle()?;
+            let txn = ReadTransaction::new(h.m_env.clone())
+                .map_err(|e| format!("LMDB: failed to begin a read transaction: {e}"))?;
+            let access = txn.access();
+            if h.is_expired(&access, key)? {
+                return Ok(None);
             }
-        } else {
-            let r_access = self.r_txn.access();
-            let mut cursor = self.r_txn.cursor(&self.db).unwrap();
-            _ = cursor.seek_k::<LmdbKV, LmdbKV>(&r_access, &LmdbKV::from(key));
-            let first_item = cursor.first_dup::<LmdbKV>(&r_access);
-            match first_item {
-                Err(_) => return,
-                Ok(val) => {
-                    if !ke.to_omit(key) {
-                        l.insert(
-                            0,
-                            VariableValue::new_with_collection(&self.m_name, key, val.data),
-                        );
-                    }
+            let value = access
+                .get::<str, str>(&h.m_db, key)
+                .to_opt()
+                .map_err(|e| format!("LMDB: failed to read {key}: {e}"))?;
+            Ok(value.map(str::to_string))
+        })
+    }
+
+    /// All the values of `key`.
+    pub fn get_all(&self, key: &str) -> Result<Vec<String>, String> {
+        self.timed("get", || {
+            let h = self.handle()?;
+            let txn = ReadTransaction::new(h.m_env.clone())
+                .map_err(|e| format!("LMDB: failed to begin a read transaction: {e}"))?;
+            let access = txn.access();
+            let mut cursor = txn
+                .cursor(&h.m_db)
+                .map_err(|e| format!("LMDB: failed to open a cursor: {e}"))?;
+            let read_error = |e| format!("LMDB: failed to read {key}: {e}");
+
+            let mut values = Vec::new();
+            if h.is_expired(&access, key)? {
+                return Ok(values);
+            }
+            let mut item = cursor
+                .seek_k::<str, str>(&access, key)
+                .to_opt()
+                .map_err(read_error)?;
+            while let Some(value) = item {
+                values.push(value.to_string());
+                item = cursor
+                    .next_dup::<str, str>(&access)
+                    .to_opt()
+                    .map_err(read_error)?
+                    .map(|(_, v)| v);
+            }
+            Ok(values)
+        })
+    }
+
+    /// All the keys and values, sorted by key, but the expired ones.
+    pub fn entries(&self) -> Result<Vec<(String, String)>, String> {
+        self.timed("scan", || {
+            let h = self.handle()?;
+            let txn = ReadTransaction::new(h.m_env.clone())
+                .map_err(|e| format!("LMDB: failed to begin a read transaction: {e}"))?;
+            let access = txn.access();
+            let mut cursor = txn
+                .cursor(&h.m_db)
+                .map_err(|e| format!("LMDB: failed to open a cursor: {e}"))?;
+            let read_error = |e| format!("LMDB: failed to read {}: {e}", self.m_name);
+
+            let mut entries = Vec::new();
+            let mut item = cursor
+                .first::<str, str>(&access)
+                .to_opt()
+                .map_err(read_error)?;
+            while let Some((k, v)) = item {
+                if !h.is_expired(&access, k)? {
+                    entries.push((k.to_string(), v.to_string()));
                 }
+                item = cursor
+                    .next::<str, str>(&access)
+                    .to_opt()
+                    .map_err(read_error)?;
             }
-            loop {
-                let next_item = cursor.next_dup::<LmdbKV, LmdbKV>(&r_access);
-                match next_item {
-                    Err(_) => break,
-                    Ok((k, v)) => {
-                        if !ke.to_omit(k.data) {
-                            l.insert(
-                                0,
-                                VariableValue::new_with_collection(&self.m_name, k.data, v.data),
-                            );
-                        }
-                    }
+            Ok(entries)
+        })
+    }
+
+    /// Run `f` in a write transaction, committed when `f` succeeds and
+    /// aborted otherwise.
+    fn write<T>(
+        &self,
+        f: impl FnOnce(&LmdbHandle, &WriteTransaction<'static>) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let h = self.handle()?;
+        let txn = WriteTransaction::new(h.m_env.clone())
+            .map_err(|e| format!("LMDB: failed to begin a write transaction: {e}"))?;
+        let result = f(h, &txn)?;
+        txn.commit()
+            .map_err(|e| format!("LMDB: failed to commit {}: {e}", self.m_name))?;
+        Ok(result)
+    }
+
+    fn timed<T>(&self, operation: &str, f: impl FnOnce() -> T) -> T {
+        match &self.m_metrics {
+            Some(metrics) => metrics.time_collection("lmdb", operation, f),
+            None => f(),
+        }
+    }
+
+    /// Keep the error of a `Collection` method, see `last_error`.
+    fn check<T: Default>(&self, result: Result<T, String>) -> T {
+        result.unwrap_or_else(|err| {
+            *self.m_last_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(err);
+            T::default()
+        })
+    }
+}
+
+impl Collection for LMDB {
+    /// Open the collection with the default `LmdbConfig`. A collection that
+    /// could not be opened is a collection whose every operation fails, see
+    /// `LMDB::open` to handle the error.
+    fn new(name: &str) -> LMDB {
+        LMDB::open(name, &LmdbConfig::default())
+            .unwrap_or_else(|err| LMDB::with_handle(name, Err(err)))
+    }
+
+    fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.m_metrics = Some(metrics);
+    }
+
+    fn store(&self, key: String, value: String) {
+        let result = self.put(&key, &value);
+        self.check(result);
+    }
+
+    fn update_first(&self, key: &str, value: &str) -> bool {
+        let result = self.put_first(key, value, false);
+        self.check(result)
+    }
+
+    fn store_or_update_first(&self, key: &str, value: &str) -> bool {
+        let result = self.put_first(key, value, true);
+        self.check(result)
+    }
+
+    fn del(&self, key: &str) {
+        let result = self.delete(key);
+        self.check(result);
+    }
+
+    fn resolve_first(&self, key: &str) -> Option<String> {
+        self.check(self.get(key))
+    }
+
+    fn resolve_single_match(&self, key: &str, l: &mut Vec<VariableValue>) {
+        for v in self.check(self.get_all(key)) {
+            l.push(VariableValue::new_with_collection(&self.m_name, key, &v));
+        }
+    }
+
+    fn resolve_multi_matches(&self, key: &str, l: &mut Vec<VariableValue>, ke: &KeyExclusions) {
+        if key.is_empty() {
+            for (k, v) in self.check(self.entries()) {
+                if !ke.to_omit(&k) {
+                    l.push(VariableValue::new_with_collection(&self.m_name, &k, &v));
                 }
             }
+        } else if !ke.to_omit(key) {
+            for v in self.check(self.get_all(key)) {
+                l.push(VariableValue::new_with_collection(&self.m_name, key, &v));
+            }
         }
     }
 
     fn resolve_regular_expression(
         &self,
         re_key: &str,
-        mut l: Vec<VariableValue>,
-        ke: KeyExclusions,
+        l: &mut Vec<VariableValue>,
+        ke: &KeyExclusions,
     ) {
-        let r_access = self.r_txn.access();
-        let mut cursor = self.r_txn.cursor(&self.db).unwrap();
-        let first_item = cursor.first::<LmdbKV, LmdbKV>(&r_access);
-        match first_item {
-            Err(_) => return,
-            Ok((k, v)) => {
-                if !ke.to_omit(k.data) {
-                    // && regex_search(re_key)
-                    l.insert(
-                        0,
-                        VariableValue::new_with_collection(&self.m_name, k.data, v.data),
-                    );
-                }
+        let Some(re) = self.check(cached_regex(re_key).map(Some)) else {
+            return;
+        };
+        for (k, v) in self.check(self.entries()) {
+            if re.is_match(&k) && !ke.to_omit(&k) {
+                l.push(VariableValue::new_with_collection(&self.m_name, &k, &v));
             }
         }
+    }
 
-        loop {
-            let next_item = cursor.next::<LmdbKV, LmdbKV>(&r_access);
-            match next_item {
-                Err(_) => break,
-                Ok((k, v)) => {
-                    if !ke.to_omit(k.data) {
-                        // && regex_search(re_key)
-                        l.insert(
-                            0,
-                            VariableValue::new_with_collection(&self.m_name, k.data, v.data),
-                        );
-                    }
-                }
-            }
-        }
+    fn create_record(
+        &self,
+        compartment: &str,
+        values: &[(&str, &str)],
+        expiry_seconds: i64,
+    ) -> bool {
+        let result = self.create(compartment, values, now_seconds() + expiry_seconds);
+        self.check(result)
     }
-}
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
-struct LmdbKV<'a> {
-    size: usize,
-    data: &'a str,
-}
-unsafe impl LmdbOrdKey for LmdbKV<'_> {}
-unsafe impl LmdbRaw for LmdbKV<'_> {}
-impl<'a> From<&'a str> for LmdbKV<'a> {
-    fn from(value: &'a str) -> Self {
-        LmdbKV {
-            size: value.len(),
-            data: value,
-        }
+    fn set_expiry(&self, key: &str, expiry_seconds: i64) {
+        let result = self.expire(key, now_seconds() + expiry_seconds);
+        self.check(result);
     }
-}
 
-impl<'a> LmdbKV<'a> {
-    fn new(data: &'a str) -> LmdbKV {
-        LmdbKV {
-            size: data.len(),
-            data,
-        }
+    fn purge_expired(&self) {
+        let result = self.purge();
+        self.check(result);
     }
 }
@@ -14,97 +14,179 @@
 
 use std::{
     collections::{HashMap, LinkedList},
-    sync::Mutex,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
 };
 
 use crate::{
-    collection::Collection,
+    collection::{expiry_scopes, now_seconds, Collection},
+    utils::regex_cache::cached_regex,
     variables::{KeyExclusions, VariableValue},
 };
 
+/// A collection kept in the memory of the process, shared by its threads:
+/// readers share the lock, writers wait for it.
 pub struct InMemoryPerProcess {
     m_name: String,
+    m_data: RwLock<InMemoryData>,
+}
+
+struct InMemoryData {
     collection: HashMap<String, LinkedList<String>>,
-    lock: Mutex<()>,
+    /// Expiry times of keys and records, see `set_expiry`.
+    expiry: HashMap<String, i64>,
 }
 
-impl Collection for InMemoryPerProcess {
-    fn new(name: &str) -> InMemoryPerProcess {
-        InMemoryPerProcess {
-            m_name: name.to_string(),
-            // todo: whether key is case insensitive?
-            collection: HashMap::with_capacity(1000),
-            lock: Mutex::new(()),
+impl InMemoryData {
+    fn is_expired(&self, key: &str) -> bool {
+        let now = now_seconds();
+        expiry_scopes(key).any(|scope| self.expiry.get(scope).is_some_and(|at| *at <= now))
+    }
+
+    fn values(&self, key: &str) -> Option<&LinkedList<String>> {
+        if self.is_expired(key) {
+            return None;
         }
+        self.collection.get(key)
     }
 
-    fn store(&mut self, key: String, value: String) {
-        if let Ok(_) = self.lock.try_lock() {
-            self.collection
-                .entry(key)
-                .or_insert_with(LinkedList::new)
-                .push_back(value);
+    /// Remove the expired key, or the keys of the expired record, `scope`.
+    fn purge(&mut self, scope: &str) {
+        self.expiry.remove(scope);
+        self.collection.remove(scope);
+        let prefix = format!("{scope}::");
+        self.collection.retain(|k, _| !k.starts_with(&prefix));
+        self.expiry.retain(|k, _| !k.starts_with(&prefix));
+    }
+
+    /// Remove what expired of `key` before writing it.
+    fn purge_scopes(&mut self, key: &str) {
+        let now = now_seconds();
+        let expired = expiry_scopes(key)
+            .filter(|scope| self.expiry.get(*scope).is_some_and(|at| *at <= now))
+            .map(str::to_string)
+            .collect::<Vec<String>>();
+        for scope in expired {
+            self.purge(&scope);
         }
     }
 
     fn update_first(&mut self, key: &str, value: &str) -> bool {
-        let mut ok = false;
-        if let Ok(_) = self.lock.try_lock() {
-            self.collection.entry(key.to_string()).and_modify(|ll| {
-                if let Some(v) = ll.front_mut() {
-                    *v = value.to_string();
-                    ok = true;
-                }
-            });
+        match self.collection.get_mut(key).and_then(|ll| ll.front_mut()) {
+            Some(v) => {
+                *v = value.to_string();
+                true
+            }
+            None => false,
         }
+    }
+
+    fn store(&mut self, key: String, value: String) {
+        self.collection.entry(key).or_default().push_back(value);
+    }
+}
 
-        ok
+impl InMemoryPerProcess {
+    /// A poisoned lock is still usable: the maps are never left half
+    /// written.
+    fn read(&self) -> RwLockReadGuard<'_, InMemoryData> {
+        self.m_data.read().unwrap_or_else(|e| e.into_inner())
     }
 
-    fn store_or_update_first(&mut self, key: &str, value: &str) -> bool {
-        if !self.update_first(key, value) {
-            self.store(key.to_string(), value.to_string());
+    fn write(&self) -> RwLockWriteGuard<'_, InMemoryData> {
+        self.m_data.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Collection for InMemoryPerProcess {
+    fn new(name: &str) -> InMemoryPerProcess {
+        InMemoryPerProcess {
+            m_name: name.to_string(),
+            m_data: RwLock::new(InMemoryData {
+                // todo: whether key is case insensitive?
+                collection: HashMap::with_capacity(1000),
+                expiry: HashMap::new(),
+            }),
         }
+    }
 
-        return true;
+    fn store(&self, key: String, value: String) {
+        let mut data = self.write();
+        data.purge_scopes(&key);
+        data.store(key, value);
     }
 
-    fn del(&mut self, key: &str) {
-        if let Ok(_) = self.lock.try_lock() {
-            self.collection.remove(key);
+    fn update_first(&self, key: &str, value: &str) -> bool {
+        let mut data = self.write();
+        data.purge_scopes(key);
+        data.update_first(key, value)
+    }
+
+    fn store_or_update_first(&self, key: &str, value: &str) -> bool {
+        let mut data = self.write();
+        data.purge_scopes(key);
+        if !data.update_first(key, value) {
+            data.store(key.to_string(), value.to_string());
         }
+
+        true
     }
 
-    fn resolve_first(&self, key: &str) -> Option<&str> {
-        match self.collection.get(key) {
-            None => None,
-            Some(ll) => ll.front().and_then(|first| Some(first.as_str())),
+    fn create_record(
+        &self,
+        compartment: &str,
+        values: &[(&str, &str)],
+        expiry_seconds: i64,
+    ) -> bool {
+        let mut data = self.write();
+        for (i, (name, value)) in values.iter().enumerate() {
+            let key = format!("{compartment}::{name}");
+            data.purge_scopes(&key);
+            if i == 0 && data.collection.get(&key).is_some_and(|ll| !ll.is_empty()) {
+                return false;
+            }
+            if !data.update_first(&key, value) {
+                data.store(key, value.to_string());
+            }
         }
+        data.expiry
+            .insert(compartment.to_string(), now_seconds() + expiry_seconds);
+        true
     }
 
-    fn resolve_single_match(&self, key: &str, mut l: Vec<VariableValue>) {
-        self.collection.get(key).iter().for_each(|ll| {
+    fn del(&self, key: &str) {
+        let mut data = self.write();
+        data.collection.remove(key);
+        data.expiry.remove(key);
+    }
+
+    fn resolve_first(&self, key: &str) -> Option<String> {
+        self.read().values(key).and_then(|ll| ll.front()).cloned()
+    }
+
+    fn resolve_single_match(&self, key: &str, l: &mut Vec<VariableValue>) {
+        self.read().values(key).iter().for_each(|ll| {
             for v in ll.iter() {
                 l.push(VariableValue::new_with_collection(&self.m_name, key, v));
             }
         });
     }
 
-    fn resolve_multi_matches(&self, key: &str, mut l: Vec<VariableValue>, ke: KeyExclusions) {
+    fn resolve_multi_matches(&self, key: &str, l: &mut Vec<VariableValue>, ke: &KeyExclusions) {
+        let data = self.read();
         l.reserve(15);
-        if key.len() == 0 {
-            self.collection.iter().for_each(|(k, ll)| {
-                if !ke.to_omit(k) {
+        if key.is_empty() {
+            data.collection.iter().for_each(|(k, ll)| {
+                if !ke.to_omit(k) && !data.is_expired(k) {
                     ll.iter().for_each(|v| {
-                        l.insert(0, VariableValue::new_with_collection(&self.m_name, k, v))
+                        l.push(VariableValue::new_with_collection(&self.m_name, k, v))
                     })
                 }
             })
         } else {
-            self.collection.get(key).iter().for_each(|ll| {
+            data.values(key).iter().for_each(|ll| {
                 if !ke.to_omit(key) {
                     ll.iter().for_each(|v| {
-                        l.insert(0, VariableValue::new_with_collection(&self.m_name, key, v))
+                        l.push(VariableValue::new_with_collection(&self.m_name, key, v))
                     })
                 }
             })
@@ -114,18 +196,40 @@ impl Collection for InMemoryPerProcess {
     fn resolve_regular_expression(
         &self,
         re_key: &str,
-        mut l: Vec<VariableValue>,
-        ke: KeyExclusions,
+        l: &mut Vec<VariableValue>,
+        ke: &KeyExclusions,
     ) {
-        self.collection
+        let Ok(re) = cached_regex(re_key) else {
+            return;
+        };
+        let data = self.read();
+        data.collection
             .iter()
-            .filter(|(k, _)| !ke.to_omit(k))
-            // todo: Regex
-            // .filter(|(k, _)| regex_search(re_key))
+            .filter(|(k, _)| re.is_match(k))
+            .filter(|(k, _)| !ke.to_omit(k) && !data.is_expired(k))
             .for_each(|(k, ll)| {
-                ll.iter().for_each(|v| {
-                    l.insert(0, VariableValue::new_with_collection(&self.m_name, k, v))
-                })
+                ll.iter()
+                    .for_each(|v| l.push(VariableValue::new_with_collection(&self.m_name, k, v)))
             })
     }
+
+    fn set_expiry(&self, key: &str, expiry_seconds: i64) {
+        self.write()
+            .expiry
+            .insert(key.to_string(), now_seconds() + expiry_seconds);
+    }
+
+    fn purge_expired(&self) {
+        let mut data = self.write();
+        let now = now_seconds();
+        let expired = data
+            .expiry
+            .iter()
+            .filter(|(_, at)| **at <= now)
+            .map(|(scope, _)| scope.clone())
+            .collect::<Vec<String>>();
+        for scope in expired {
+            data.purge(&scope);
+        }
+    }
 }
@@ -16,4 +16,143 @@ pub mod lmdb;
 pub mod memory;
 pub mod sled;
 
+pub use lmdb::*;
 pub use memory::*;
+// `self::` as the sled crate has the same name
+pub use self::sled::*;
+
+#[test]
+fn test_lmdb() {
+    use crate::collection::Collection;
+    use std::{env::temp_dir, fs, process};
+
+    let path = temp_dir().join(format!("modsecurity-lmdb-{}", process::id()));
+    let config = LmdbConfig::new(&path, DEFAULT_LMDB_MAP_SIZE);
+    {
+        let ip = LMDB::open("IP", &config).unwrap();
+        ip.store("10.0.0.1::score".to_string(), "1".to_string());
+        assert!(ip.store_or_update_first("10.0.0.1::score", "5"));
+        ip.store("10.0.0.1::tag".to_string(), "a".to_string());
+        ip.store("10.0.0.1::tag".to_string(), "b".to_string());
+        assert!(!ip.update_first("10.0.0.2::score", "1"));
+        // written, committed and visible to the next read
+        assert_eq!(ip.resolve_first("10.0.0.1::score").unwrap(), "5");
+        assert!(ip.last_error().is_none());
+
+        // oversized keys are errors, not panics
+        ip.store("k".repeat(1024), "v".to_string());
+        assert!(ip
+            .last_error()
+            .unwrap()
+            .starts_with("LMDB: failed to store"));
+        assert!(ip.put(&"k".repeat(1024), "v").is_err());
+    }
+
+    // survives reopening
+    let ip = LMDB::open("IP", &config).unwrap();
+    assert_eq!(ip.get("10.0.0.1::score").unwrap().as_deref(), Some("5"));
+    assert_eq!(ip.get_all("10.0.0.1::tag").unwrap(), vec!["a", "b"]);
+    assert_eq!(ip.entries().unwrap().len(), 3);
+    ip.del("10.0.0.1::tag");
+    assert!(ip.get_all("10.0.0.1::tag").unwrap().is_empty());
+
+    // expiry of keys and records
+    ip.set_expiry("10.0.0.1::score", 0);
+    assert!(ip.resolve_first("10.0.0.1::score").is_none());
+    assert!(ip.init_record("10.0.0.9", 3600));
+    ip.store("10.0.0.9::score".to_string(), "1".to_string());
+    ip.set_expiry("10.0.0.9", 0);
+    assert!(ip.get("10.0.0.9::score").unwrap().is_none());
+    // a new record does not bring back the keys of the expired one, nor
+    // purges the other expired keys
+    assert!(ip.init_record("10.0.0.9", 3600));
+    assert!(ip.get("10.0.0.9::score").unwrap().is_none());
+    ip.set_expiry("10.0.0.9", 0);
+    assert_eq!(ip.purge().unwrap(), 2);
+    assert!(ip.entries().unwrap().is_empty());
+    drop(ip);
+
+    // an unusable path is reported by open
+    fs::write(path.join("file"), "").unwrap();
+    let config = LmdbConfig::new(path.join("file"), DEFAULT_LMDB_MAP_SIZE);
+    assert!(LMDB::open("IP", &config).is_err());
+
+    _ = fs::remove_dir_all(&path);
+}
+
+#[test]
+fn test_sled() {
+    use crate::collection::Collection;
+    use std::{env::temp_dir, process};
+
+    let path = temp_dir().join(format!("modsecurity-sled-{}", process::id()));
+    let config = SledConfig::new(&path, DEFAULT_SLED_CACHE_CAPACITY);
+    {
+        let ip = Sled::open("IP", &config).unwrap();
+        ip.store("10.0.0.1::tag".to_string(), "b".to_string());
+        ip.store("10.0.0.1::tag".to_string(), "a".to_string());
+        ip.store("10.0.0.1::tag::x".to_string(), "c".to_string());
+        assert!(ip.store_or_update_first("10.0.0.1::score", "1"));
+        assert!(ip.update_first("10.0.0.1::score", "5"));
+        assert!(!ip.update_first("10.0.0.2::score", "1"));
+
+        // values in the order they were stored, other keys left out
+        assert_eq!(ip.get_all("10.0.0.1::tag").unwrap(), vec!["b", "a"]);
+        assert_eq!(ip.resolve_first("10.0.0.1::score").unwrap(), "5");
+        ip.del("10.0.0.1::tag::x");
+        assert!(ip.last_error().is_none());
+        ip.flush().unwrap();
+    }
+
+    // survives reopening and compaction
+    let mut ip = Sled::open("IP", &config).unwrap();
+    ip.compact().unwrap();
+    assert_eq!(ip.get_all("10.0.0.1::tag").unwrap(), vec!["b", "a"]);
+    assert_eq!(ip.get("10.0.0.1::score").unwrap().as_deref(), Some("5"));
+    assert_eq!(ip.entries().unwrap().len(), 3);
+    ip.store("10.0.0.1::tag".to_string(), "c".to_string());
+    assert_eq!(ip.get_all("10.0.0.1::tag").unwrap(), vec!["b", "a", "c"]);
+    ip.set_expiry("10.0.0.1", 0);
+    assert!(ip.get_all("10.0.0.1::tag").unwrap().is_empty());
+    assert_eq!(ip.purge().unwrap(), 1);
+    assert!(ip.entries().unwrap().is_empty());
+    drop(ip);
+
+    _ = std::fs::remove_dir_all(&path);
+}
+
+#[test]
+fn test_init_record_once() {
+    use crate::collection::{Collection, SharedCollection};
+    use std::{env::temp_dir, process, sync::Arc, thread};
+
+    // of the threads initialising the same record, a single one creates it
+    let created = |collection: SharedCollection| {
+        let threads = (0..8)
+            .map(|_| {
+                let collection = collection.clone();
+                thread::spawn(move || collection.init_record("10.0.0.1", 3600))
+            })
+            .collect::<Vec<_>>();
+        let created = threads
+            .into_iter()
+            .map(|t| t.join().unwrap())
+            .filter(|new| *new)
+            .count();
+        let mut l = vec![];
+        collection.resolve_single_match("10.0.0.1::UPDATE_COUNTER", &mut l);
+        assert_eq!(l.len(), 1);
+        created
+    };
+
+    let path = temp_dir().join(format!("modsecurity-init-record-{}", process::id()));
+    let lmdb_config = LmdbConfig::new(path.join("lmdb"), DEFAULT_LMDB_MAP_SIZE);
+    let lmdb = LMDB::open("IP", &lmdb_config).unwrap();
+    let sled_config = SledConfig::new(path.join("sled"), DEFAULT_SLED_CACHE_CAPACITY);
+    let sled = Sled::open("IP", &sled_config).unwrap();
+    assert_eq!(created(Arc::new(InMemoryPerProcess::new("IP"))), 1);
+    assert_eq!(created(Arc::new(lmdb)), 1);
+    assert_eq!(created(Arc::new(sled)), 1);
+
+    _ = std::fs::remove_dir_all(&path);
+}
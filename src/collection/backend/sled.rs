@@ -1,15 +1,561 @@
 // Copyright 2023 young2j
-// 
+//
 // Licensed under the Apache License, Version 2.0 (the "License");
 // you may not use this file except in compliance with the License.
 // You may obtain a copy of the License at
-// 
+//
 //     http://www.apache.org/licenses/LICENSE-2.0
-// 
+//
 // Unless required by applicable law or agreed to in writing, software
 // distributed under the License is distributed on an "AS IS" BASIS,
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-// todo: sled 
\ No newline at end of file
+use std::{
+    env::temp_dir,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+use sled::{Batch, Db, IVec, Tree};
+
+use crate::{
+    collection::{expiry_scopes, now_seconds, Collection},
+    modsecurity::Metrics,
+    utils::regex_cache::cached_regex,
+    variables::{KeyExclusions, VariableValue},
+};
+
+/// Size of the sled page cache of a collection, 64MiB.
+pub const DEFAULT_SLED_CACHE_CAPACITY: u64 = 64 * 1024 * 1024;
+
+/// How long `open` waits for the lock of a database dropped a moment ago:
+/// its background flusher thread, which wakes up every 500ms, releases it
+/// when it stops.
+const SLED_LOCK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Where the sled databases are and how much memory they may use.
+#[derive(Debug, Clone)]
+pub struct SledConfig {
+    /// Each collection is a database in its own subdirectory, e.g.
+    /// `m_path/IP`.
+    pub m_path: PathBuf,
+    pub m_cache_capacity: u64,
+}
+
+impl SledConfig {
+    pub fn new(path: impl Into<PathBuf>, cache_capacity: u64) -> SledConfig {
+        SledConfig {
+            m_path: path.into(),
+            m_cache_capacity: cache_capacity,
+        }
+    }
+}
+
+impl Default for SledConfig {
+    fn default() -> Self {
+        SledConfig::new(
+            temp_dir().join("modsecurity-sled"),
+            DEFAULT_SLED_CACHE_CAPACITY,
+        )
+    }
+}
+
+/// Persistent collection stored in a sled database, a pure Rust
+/// alternative to `LMDB`. A database is opened by a single process.
+///
+/// Every value is an entry of its own, keyed by the collection key, a NUL
+/// byte and a big endian id increasing with each store: the values of a
+/// key are a prefix scan away, in the order they were stored. The expiry
+/// times of the keys and records are in the `expiry` tree. Writes are
+/// flushed to disk in the background, see `flush`.
+///
+/// As for `LMDB`, the errors of the `Collection` methods are available
+/// through `last_error`, the inherent methods return them.
+pub struct Sled {
+    m_name: String,
+    m_dir: PathBuf,
+    m_cache_capacity: u64,
+    /// The database, or why it could not be opened.
+    m_db: Result<Db, String>,
+    m_last_error: Mutex<Option<String>>,
+    /// Held by `put_first` and `create`, a read followed by writes, so
+    /// that threads updating the same key do not both add it.
+    m_put_first_lock: Mutex<()>,
+    m_metrics: Option<Arc<Metrics>>,
+}
+
+impl Sled {
+    /// Open, creating it if needed, the database of the collection `name`
+    /// under `config.m_path`.
+    pub fn open(name: &str, config: &SledConfig) -> Result<Sled, String> {
+        let mut sled = Sled::with_config(name, config);
+        sled.m_db = Ok(open_db(&sled.m_dir, sled.m_cache_capacity)?);
+        Ok(sled)
+    }
+
+    fn with_config(name: &str, config: &SledConfig) -> Sled {
+        Sled {
+            m_name: name.to_string(),
+            m_dir: config.m_path.join(name),
+            m_cache_capacity: config.m_cache_capacity,
+            m_db: Err(format!("Sled: {name} is not open")),
+            m_last_error: Mutex::new(None),
+            m_put_first_lock: Mutex::new(()),
+            m_metrics: None,
+        }
+    }
+
+    /// The last error of a `Collection` method, cleared when returned.
+    pub fn last_error(&self) -> Option<String> {
+        self.m_last_error
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+    }
+
+    /// Add `value` to the values of `key`.
+    pub fn put(&self, key: &str, value: &str) -> Result<(), String> {
+        self.timed("store", || {
+            self.purge_scopes(key)?;
+            let db = self.db()?;
+            let id = db
+                .generate_id()
+                .map_err(|e| format!("Sled: failed to store {key}: {e}"))?;
+            db.insert(entry_key(key, id), value.as_bytes())
+                .map_err(|e| format!("Sled: failed to store {key}: {e}"))?;
+            Ok(())
+        })
+    }
+
+    /// Replace the first value of `key`, adding it when `key` has no value
+    /// and `insert` is set. Returns whether a value was written.
+    pub fn put_first(&self, key: &str, value: &str, insert: bool) -> Result<bool, String> {
+        let _guard = self.lock_put_first();
+        self.put_first_locked(key, value, insert)
+    }
+
+    /// Create the record `compartment`, expiring at `at`, unless the first
+    /// of its `values` is set. Returns whether it was created.
+    pub fn create(
+        &self,
+        compartment: &str,
+        values: &[(&str, &str)],
+        at: i64,
+    ) -> Result<bool, String> {
+        let _guard = self.lock_put_first();
+        for (i, (name, value)) in values.iter().enumerate() {
+            let key = format!("{compartment}::{name}");
+            self.purge_scopes(&key)?;
+            if i == 0 && !self.timed("get", || self.values(&key))?.is_empty() {
+                return Ok(false);
+            }
+            self.put_first_locked(&key, value, true)?;
+        }
+        self.expire(compartment, at)?;
+        Ok(true)
+    }
+
+    fn lock_put_first(&self) -> MutexGuard<'_, ()> {
+        self.m_put_first_lock
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// `put_first`, `m_put_first_lock` held.
+    fn put_first_locked(&self, key: &str, value: &str, insert: bool) -> Result<bool, String> {
+        self.purge_scopes(key)?;
+        let first = self.timed("get", || self.values(key))?.into_iter().next();
+        match first {
+            // the entry keeps its id, and so its position
+            Some((entry, _)) => self.timed("update", || {
+                self.db()?
+                    .insert(entry, value.as_bytes())
+                    .map_err(|e| format!("Sled: failed to store {key}: {e}"))?;
+                Ok(true)
+            }),
+            None if insert => self.put(key, value).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Remove all the values of `key`, at once, and its expiry.
+    pub fn delete(&self, key: &str) -> Result<(), String> {
+        self.timed("delete", || {
+            let mut batch = Batch::default();
+            for (entry, _) in self.values(key)? {
+                batch.remove(entry);
+            }
+            self.db()?
+                .apply_batch(batch)
+                .map_err(|e| format!("Sled: failed to delete {key}: {e}"))?;
+            self.expiry()?
+                .remove(key)
+                .map_err(|e| format!("Sled: failed to delete {key}: {e}"))?;
+            Ok(())
+        })
+    }
+
+    /// First value of `key`, the oldest one.
+    pub fn get(&self, key: &str) -> Result<Option<String>, String> {
+        self.timed("get", || {
+            if self.is_expired(key)? {
+                return Ok(None);
+            }
+            Ok(self.values(key)?.into_iter().next().map(|(_, v)| v))
+        })
+    }
+
+    /// All the values of `key`, in the order they were stored.
+    pub fn get_all(&self, key: &str) -> Result<Vec<String>, String> {
+        self.timed("get", || {
+            if self.is_expired(key)? {
+                return Ok(Vec::new());
+            }
+            Ok(self.values(key)?.into_iter().map(|(_, v)| v).collect())
+        })
+    }
+
+    /// Expire the key, or record, `key` at `at`, in seconds since the
+    /// epoch.
+    pub fn expire(&self, key: &str, at: i64) -> Result<(), String> {
+        self.timed("expire", || {
+            self.expiry()?
+                .insert(key, &at.to_be_bytes()[..])
+                .map_err(|e| format!("Sled: failed to store the expiry of {key}: {e}"))?;
+            Ok(())
+        })
+    }
+
+    /// Remove the expired keys and records, returns how many expired.
+    pub fn purge(&self) -> Result<usize, String> {
+        self.timed("purge", || {
+            let now = now_seconds();
+            let mut expired = Vec::new();
+            for item in self.expiry()?.iter() {
+                let (scope, at) = item.map_err(|e| {
+                    format!("Sled: failed to read the expiry of {}: {e}", self.m_name)
+                })?;
+                if decode_time(&at).is_some_and(|at| at <= now) {
+                    expired.push(String::from_utf8_lossy(&scope).into_owned());
+                }
+            }
+            for scope in expired.iter() {
+                self.purge_scope(scope)?;
+            }
+            Ok(expired.len())
+        })
+    }
+
+    /// All the keys and values, sorted by key, but the expired ones.
+    pub fn entries(&self) -> Result<Vec<(String, String)>, String> {
+        self.timed("scan", || {
+            let mut entries = Vec::new();
+            for item in self.db()?.iter() {
+                let (entry, value) =
+                    item.map_err(|e| format!("Sled: failed to read {}: {e}", self.m_name))?;
+                let Some(key) = decode_key(&entry) else {
+                    continue;
+                };
+                if !self.is_expired(&String::from_utf8_lossy(key))? {
+                    entries.push((
+                        String::from_utf8_lossy(key).into_owned(),
+                        String::from_utf8_lossy(&value).into_owned(),
+                    ));
+                }
+            }
+            Ok(entries)
+        })
+    }
+
+    /// Write the pending changes to disk.
+    pub fn flush(&self) -> Result<(), String> {
+        self.db()?
+            .flush()
+            .map(|_| ())
+            .map_err(|e| format!("Sled: failed to flush {}: {e}", self.m_name))
+    }
+
+    /// Rewrite the collection into a new database, renumbering its
+    /// entries, and replace the current one with it. The space of the
+    /// removed and overwritten values is reclaimed at once. It takes the
+    /// collection alone, i.e. before it is shared with the transactions.
+    pub fn compact(&mut self) -> Result<(), String> {
+        let compacting = self.m_dir.with_extension("compacting");
+        _ = fs::remove_dir_all(&compacting);
+        {
+            let db = self.db()?;
+            let compacted = open_db(&compacting, self.m_cache_capacity)?;
+            let error = |e: sled::Error| format!("Sled: failed to compact {}: {e}", self.m_name);
+            let expiry = compacted.open_tree("expiry").map_err(error)?;
+            for item in self.expiry()?.iter() {
+                let (scope, at) = item.map_err(error)?;
+                expiry.insert(scope, at).map_err(error)?;
+            }
+            for item in db.iter() {
+                let (entry, value) = item.map_err(error)?;
+                let Some(key) = decode_key(&entry) else {
+                    continue;
+                };
+                // ids restart with the new database, the iteration order
+                // keeps the order of the values of a key
+                let id = compacted.generate_id().map_err(error)?;
+                compacted
+                    .insert(entry_key_bytes(key, id), value)
+                    .map_err(error)?;
+            }
+            compacted.flush().map_err(error)?;
+        }
+
+        // close the current database before replacing it
+        self.m_db = Err(format!("Sled: {} is being compacted", self.m_name));
+        let replace =
+            fs::remove_dir_all(&self.m_dir).and_then(|_| fs::rename(&compacting, &self.m_dir));
+        if let Err(e) = replace {
+            let err = format!("Sled: failed to replace {}: {e}", self.m_dir.display());
+            self.m_db = Err(err.clone());
+            return Err(err);
+        }
+        self.m_db = open_db(&self.m_dir, self.m_cache_capacity);
+        self.m_db.as_ref().map(|_| ()).map_err(|err| err.clone())
+    }
+
+    /// Entries and values of `key`, a prefix scan of `key` and the NUL
+    /// separator.
+    fn values(&self, key: &str) -> Result<Vec<(IVec, String)>, String> {
+        let mut prefix = key.as_bytes().to_vec();
+        prefix.push(0);
+
+        let mut values = Vec::new();
+        for item in self.db()?.scan_prefix(&prefix) {
+            let (entry, value) = item.map_err(|e| format!("Sled: failed to read {key}: {e}"))?;
+            // a longer key with a NUL byte shares the prefix
+            if entry.len() == prefix.len() + 8 {
+                values.push((entry, String::from_utf8_lossy(&value).into_owned()));
+            }
+        }
+        Ok(values)
+    }
+
+    fn db(&self) -> Result<&Db, String> {
+        self.m_db.as_ref().map_err(|err| err.clone())
+    }
+
+    fn expiry(&self) -> Result<Tree, String> {
+        self.db()?
+            .open_tree("expiry")
+            .map_err(|e| format!("Sled: failed to open the expiry of {}: {e}", self.m_name))
+    }
+
+    /// `key` itself or the records it belongs to, when expired.
+    fn expired_scopes<'k>(&self, key: &'k str) -> Result<Vec<&'k str>, String> {
+        let now = now_seconds();
+        let expiry = self.expiry()?;
+        let mut expired = Vec::new();
+        for scope in expiry_scopes(key) {
+            let at = expiry
+                .get(scope)
+                .map_err(|e| format!("Sled: failed to read the expiry of {scope}: {e}"))?;
+            if at
+                .and_then(|at| decode_time(&at))
+                .is_some_and(|at| at <= now)
+            {
+                expired.push(scope);
+            }
+        }
+        Ok(expired)
+    }
+
+    fn is_expired(&self, key: &str) -> Result<bool, String> {
+        Ok(!self.expired_scopes(key)?.is_empty())
+    }
+
+    /// Remove what expired of `key` before writing it.
+    fn purge_scopes(&self, key: &str) -> Result<(), String> {
+        for scope in self.expired_scopes(key)? {
+            self.purge_scope(scope)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the expired key, or the keys of the expired record, `scope`.
+    fn purge_scope(&self, scope: &str) -> Result<(), String> {
+        let error = |e: sled::Error| format!("Sled: failed to delete {scope}: {e}");
+        let prefix = format!("{scope}::");
+
+        let mut batch = Batch::default();
+        for (entry, _) in self.values(scope)? {
+            batch.remove(entry);
+        }
+        for item in self.db()?.scan_prefix(&prefix) {
+            batch.remove(item.map_err(error)?.0);
+        }
+        self.db()?.apply_batch(batch).map_err(error)?;
+
+        let expiry = self.expiry()?;
+        let mut batch = Batch::default();
+        batch.remove(scope);
+        for item in expiry.scan_prefix(&prefix) {
+            batch.remove(item.map_err(error)?.0);
+        }
+        expiry.apply_batch(batch).map_err(error)
+    }
+
+    fn timed<T>(&self, operation: &str, f: impl FnOnce() -> T) -> T {
+        match &self.m_metrics {
+            Some(metrics) => metrics.time_collection("sled", operation, f),
+            None => f(),
+        }
+    }
+
+    /// Keep the error of a `Collection` method, see `last_error`.
+    fn check<T: Default>(&self, result: Result<T, String>) -> T {
+        result.unwrap_or_else(|err| {
+            *self.m_last_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(err);
+            T::default()
+        })
+    }
+}
+
+fn open_db(dir: &Path, cache_capacity: u64) -> Result<Db, String> {
+    let deadline = Instant::now() + SLED_LOCK_TIMEOUT;
+    loop {
+        let db = sled::Config::new()
+            .path(dir)
+            .cache_capacity(cache_capacity)
+            .open();
+        match db {
+            // sled reports it as an error of kind Other
+            Err(sled::Error::Io(e))
+                if e.to_string().starts_with("could not acquire lock")
+                    && Instant::now() < deadline =>
+            {
+                thread::sleep(Duration::from_millis(10));
+            }
+            db => return db.map_err(|e| format!("Sled: failed to open {}: {e}", dir.display())),
+        }
+    }
+}
+
+fn entry_key(key: &str, id: u64) -> Vec<u8> {
+    entry_key_bytes(key.as_bytes(), id)
+}
+
+fn entry_key_bytes(key: &[u8], id: u64) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(key.len() + 9);
+    entry.extend_from_slice(key);
+    entry.push(0);
+    entry.extend_from_slice(&id.to_be_bytes());
+    entry
+}
+
+fn decode_time(at: &[u8]) -> Option<i64> {
+    Some(i64::from_be_bytes(at.try_into().ok()?))
+}
+
+fn decode_key(entry: &[u8]) -> Option<&[u8]> {
+    let len = entry.len().checked_sub(9)?;
+    (entry[len] == 0).then_some(&entry[..len])
+}
+
+impl Collection for Sled {
+    /// Open the collection with the default `SledConfig`. A collection that
+    /// could not be opened is a collection whose every operation fails, see
+    /// `Sled::open` to handle the error.
+    fn new(name: &str) -> Sled {
+        let config = SledConfig::default();
+        Sled::open(name, &config).unwrap_or_else(|err| {
+            let mut sled = Sled::with_config(name, &config);
+            sled.m_db = Err(err);
+            sled
+        })
+    }
+
+    fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.m_metrics = Some(metrics);
+    }
+
+    fn store(&self, key: String, value: String) {
+        let result = self.put(&key, &value);
+        self.check(result);
+    }
+
+    fn update_first(&self, key: &str, value: &str) -> bool {
+        let result = self.put_first(key, value, false);
+        self.check(result)
+    }
+
+    fn store_or_update_first(&self, key: &str, value: &str) -> bool {
+        let result = self.put_first(key, value, true);
+        self.check(result)
+    }
+
+    fn del(&self, key: &str) {
+        let result = self.delete(key);
+        self.check(result);
+    }
+
+    fn resolve_first(&self, key: &str) -> Option<String> {
+        self.check(self.get(key))
+    }
+
+    fn resolve_single_match(&self, key: &str, l: &mut Vec<VariableValue>) {
+        for v in self.check(self.get_all(key)) {
+            l.push(VariableValue::new_with_collection(&self.m_name, key, &v));
+        }
+    }
+
+    fn resolve_multi_matches(&self, key: &str, l: &mut Vec<VariableValue>, ke: &KeyExclusions) {
+        if key.is_empty() {
+            for (k, v) in self.check(self.entries()) {
+                if !ke.to_omit(&k) {
+                    l.push(VariableValue::new_with_collection(&self.m_name, &k, &v));
+                }
+            }
+        } else if !ke.to_omit(key) {
+            for v in self.check(self.get_all(key)) {
+                l.push(VariableValue::new_with_collection(&self.m_name, key, &v));
+            }
+        }
+    }
+
+    fn resolve_regular_expression(
+        &self,
+        re_key: &str,
+        l: &mut Vec<VariableValue>,
+        ke: &KeyExclusions,
+    ) {
+        let Some(re) = self.check(cached_regex(re_key).map(Some)) else {
+            return;
+        };
+        for (k, v) in self.check(self.entries()) {
+            if re.is_match(&k) && !ke.to_omit(&k) {
+                l.push(VariableValue::new_with_collection(&self.m_name, &k, &v));
+            }
+        }
+    }
+
+    fn create_record(
+        &self,
+        compartment: &str,
+        values: &[(&str, &str)],
+        expiry_seconds: i64,
+    ) -> bool {
+        let result = self.create(compartment, values, now_seconds() + expiry_seconds);
+        self.check(result)
+    }
+
+    fn set_expiry(&self, key: &str, expiry_seconds: i64) {
+        let result = self.expire(key, now_seconds() + expiry_seconds);
+        self.check(result);
+    }
+
+    fn purge_expired(&self) {
+        let result = self.purge();
+        self.check(result);
+    }
+}
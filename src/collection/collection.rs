@@ -12,27 +12,170 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::variables::{KeyExclusions, VariableValue};
-
-pub trait Collection {
-    fn new(name: &str) -> Self;
-    fn store(&mut self, key: String, value: String);
-    fn update_first(&mut self, key: &str, value: &str) -> bool;
-    fn store_or_update_first(&mut self, key: &str, value: &str) -> bool;
-    fn del(&mut self, key: &str);
-    fn resolve_first(&self, key: &str) -> Option<&str>;
-    fn resolve_single_match(&self, key: &str, l: Vec<VariableValue>);
-    fn resolve_multi_matches(&self, key: &str, l: Vec<VariableValue>, ke: KeyExclusions);
-    fn resolve_regular_expression(&self, re_key: &str, l: Vec<VariableValue>, ke: KeyExclusions);
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    modsecurity::Metrics,
+    utils::regex_cache::cached_regex,
+    variables::{KeyExclusions, VariableValue},
+};
+
+/// A collection shared by the `ModSecurity` instance and its transactions,
+/// possibly across threads.
+pub type SharedCollection = Arc<dyn Collection>;
+
+/// Seconds since the epoch, the unit of the expiry times and of the record
+/// metadata, comparable across processes.
+pub fn now_seconds() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Regular expression selecting the keys of `compartment`, e.g.
+/// `10.0.0.1::user_id` for `10.0.0.1`.
+pub fn compartment_regex(compartment: &str) -> String {
+    format!("^{}::", regex::escape(compartment))
+}
+
+/// The key itself and the records it belongs to, e.g. `10.0.0.1::score`
+/// and `10.0.0.1`: a key is expired when one of them is.
+pub fn expiry_scopes(key: &str) -> impl Iterator<Item = &str> {
+    std::iter::once(key).chain(key.match_indices("::").map(move |(i, _)| &key[..i]))
+}
+
+/// The methods take `&self`: a backend synchronises its own accesses, and
+/// a write waits for the lock rather than being dropped.
+pub trait Collection: Send + Sync {
+    fn new(name: &str) -> Self
+    where
+        Self: Sized;
+    fn store(&self, key: String, value: String);
+    fn update_first(&self, key: &str, value: &str) -> bool;
+    fn store_or_update_first(&self, key: &str, value: &str) -> bool;
+    fn del(&self, key: &str);
+    fn resolve_first(&self, key: &str) -> Option<String>;
+    /// The `resolve_*` methods append what they find to `l`.
+    fn resolve_single_match(&self, key: &str, l: &mut Vec<VariableValue>);
+    fn resolve_multi_matches(&self, key: &str, l: &mut Vec<VariableValue>, ke: &KeyExclusions);
+    fn resolve_regular_expression(
+        &self,
+        re_key: &str,
+        l: &mut Vec<VariableValue>,
+        ke: &KeyExclusions,
+    );
+
+    /// Record the latency of the backend operations, a no-op for the
+    /// backends that are not worth timing.
+    fn set_metrics(&mut self, _metrics: Arc<Metrics>) {}
+
+    /// Expire `key` in `expiry_seconds`, 0 to expire it at once. `key` may
+    /// be a record, i.e. a compartment, its keys then expire with it.
+    ///
+    /// Expired keys are not resolved anymore, and removed when written or
+    /// purged.
+    fn set_expiry(&self, key: &str, expiry_seconds: i64);
+
+    /// Create the record `compartment` with the `values` of its keys,
+    /// expiring in `expiry_seconds`, unless the first of them is set. The
+    /// check and the writes are atomic: of the threads, or processes,
+    /// creating the same record, a single one does.
+    ///
+    /// Returns whether the record was created.
+    fn create_record(
+        &self,
+        compartment: &str,
+        values: &[(&str, &str)],
+        expiry_seconds: i64,
+    ) -> bool;
+
+    /// Remove the expired keys and records, see
+    /// `ModSecurity::purge_expired_collections`.
+    fn purge_expired(&self);
+
+    /* setExpiry */
+    fn set_expiry_with_compartment(&self, key: String, compartment: String, expiry_seconds: i64) {
+        let nkey = format!("{compartment}::{key}");
+        self.set_expiry(&nkey, expiry_seconds);
+    }
+
+    fn set_expiry_with_compartments(
+        &self,
+        key: String,
+        compartment: String,
+        compartment2: String,
+        expiry_seconds: i64,
+    ) {
+        let nkey = format!("{compartment}::{compartment2}::{key}");
+        self.set_expiry(&nkey, expiry_seconds);
+    }
+
+    /* record metadata */
+    /// Load the record `compartment`, creating its metadata when it does
+    /// not exist or expired: CREATE_TIME, UPDATE_COUNTER, UPDATE_RATE,
+    /// LAST_UPDATE_TIME, TIMEOUT and IS_NEW. The record expires `timeout`
+    /// seconds after its last update, see SecCollectionTimeout.
+    ///
+    /// Returns whether the record is new.
+    fn init_record(&self, compartment: &str, timeout: i64) -> bool {
+        // the keys left by the expired record, if any, are purged by the
+        // backend when CREATE_TIME is written, the other records are left
+        // alone
+        let now = now_seconds().to_string();
+        let timeout_value = timeout.to_string();
+        let values = [
+            ("CREATE_TIME", now.as_str()),
+            ("LAST_UPDATE_TIME", now.as_str()),
+            ("UPDATE_COUNTER", "0"),
+            ("UPDATE_RATE", "0"),
+            ("TIMEOUT", timeout_value.as_str()),
+            ("IS_NEW", "1"),
+        ];
+        if self.create_record(compartment, &values, timeout) {
+            return true;
+        }
+        self.store_or_update_first(&format!("{compartment}::IS_NEW"), "0");
+        false
+    }
+
+    /// Account an update of the record `compartment`, see `init_record`:
+    /// UPDATE_RATE is the number of updates per minute since its creation.
+    /// The record expires TIMEOUT seconds from now.
+    fn update_record(&self, compartment: &str) {
+        let key = |name: &str| format!("{compartment}::{name}");
+        let number = |collection: &Self, name: &str| {
+            collection
+                .resolve_first(&key(name))
+                .and_then(|v| v.parse::<i64>().ok())
+        };
+        let Some(create_time) = number(self, "CREATE_TIME") else {
+            return;
+        };
+        let now = now_seconds();
+        let counter = number(self, "UPDATE_COUNTER").unwrap_or(0) + 1;
+        let rate = match now - create_time {
+            elapsed if elapsed > 0 => counter * 60 / elapsed,
+            _ => 0,
+        };
+        self.store_or_update_first(&key("UPDATE_COUNTER"), &counter.to_string());
+        self.store_or_update_first(&key("UPDATE_RATE"), &rate.to_string());
+        self.store_or_update_first(&key("LAST_UPDATE_TIME"), &now.to_string());
+        if let Some(timeout) = number(self, "TIMEOUT") {
+            self.set_expiry(compartment, timeout);
+        }
+    }
 
     /* store */
-    fn store_with_compartment(&mut self, key: String, value: String, compartment: String) {
+    fn store_with_compartment(&self, key: String, value: String, compartment: String) {
         let nkey = format!("{compartment}::{key}");
         self.store(nkey, value);
     }
 
     fn store_with_compartments(
-        &mut self,
+        &self,
         key: String,
         value: String,
         compartment: String,
@@ -44,63 +187,63 @@ pub trait Collection {
 
     /* storeOrUpdateFirst */
     fn store_or_update_first_with_compartment(
-        &mut self,
+        &self,
         key: String,
         value: String,
         compartment: String,
     ) -> bool {
         let nkey = format!("{compartment}::{key}");
-        return self.store_or_update_first(&nkey, &value);
+        self.store_or_update_first(&nkey, &value)
     }
 
     fn store_or_update_first_with_compartments(
-        &mut self,
+        &self,
         key: String,
         value: String,
         compartment: String,
         compartment2: String,
     ) -> bool {
         let nkey = format!("{compartment}::{compartment2}::{key}");
-        return self.store_or_update_first(&nkey, &value);
+        self.store_or_update_first(&nkey, &value)
     }
 
     /* updateFirst */
     fn update_first_with_compartment(
-        &mut self,
+        &self,
         key: String,
         value: String,
         compartment: String,
     ) -> bool {
         let nkey = format!("{compartment}::{key}");
-        return self.update_first(&nkey, &value);
+        self.update_first(&nkey, &value)
     }
 
     fn update_first_with_compartments(
-        &mut self,
+        &self,
         key: String,
         value: String,
         compartment: String,
         compartment2: String,
     ) -> bool {
         let nkey = format!("{compartment}::{compartment2}::{key}");
-        return self.update_first(&nkey, &value);
+        self.update_first(&nkey, &value)
     }
 
     /* del */
-    fn del_with_compartment(&mut self, key: String, compartment: String) {
+    fn del_with_compartment(&self, key: String, compartment: String) {
         let nkey = format!("{compartment}::{key}");
         self.del(&nkey);
     }
 
-    fn del_with_compartments(&mut self, key: String, compartment: String, compartment2: String) {
+    fn del_with_compartments(&self, key: String, compartment: String, compartment2: String) {
         let nkey = format!("{compartment}::{compartment2}::{key}");
         self.del(&nkey);
     }
 
     /* resolveFirst */
-    fn resolve_first_with_compartment(&self, key: String, compartment: String) -> Option<&str> {
+    fn resolve_first_with_compartment(&self, key: String, compartment: String) -> Option<String> {
         let nkey = format!("{compartment}::{key}");
-        return self.resolve_first(&nkey);
+        self.resolve_first(&nkey)
     }
 
     fn resolve_first_with_compartments(
@@ -108,9 +251,9 @@ pub trait Collection {
         key: String,
         compartment: String,
         compartment2: String,
-    ) -> Option<&str> {
+    ) -> Option<String> {
         let nkey = format!("{compartment}::{compartment2}::{key}");
-        return self.resolve_first(&nkey);
+        self.resolve_first(&nkey)
     }
 
     /* resolveSingleMatch */
@@ -118,7 +261,7 @@ pub trait Collection {
         &self,
         key: String,
         compartment: String,
-        l: Vec<VariableValue>,
+        l: &mut Vec<VariableValue>,
     ) {
         let nkey = format!("{compartment}::{key}");
         self.resolve_single_match(&nkey, l);
@@ -129,7 +272,7 @@ pub trait Collection {
         key: String,
         compartment: String,
         compartment2: String,
-        l: Vec<VariableValue>,
+        l: &mut Vec<VariableValue>,
     ) {
         let nkey = format!("{compartment}::{compartment2}::{key}");
         self.resolve_single_match(&nkey, l);
@@ -140,8 +283,8 @@ pub trait Collection {
         &self,
         key: String,
         compartment: String,
-        l: Vec<VariableValue>,
-        ke: KeyExclusions,
+        l: &mut Vec<VariableValue>,
+        ke: &KeyExclusions,
     ) {
         let nkey = format!("{compartment}::{key}");
         self.resolve_multi_matches(&nkey, l, ke);
@@ -152,23 +295,37 @@ pub trait Collection {
         key: String,
         compartment: String,
         compartment2: String,
-        l: Vec<VariableValue>,
-        ke: KeyExclusions,
+        l: &mut Vec<VariableValue>,
+        ke: &KeyExclusions,
     ) {
         let nkey = format!("{compartment}::{compartment2}::{key}");
         self.resolve_multi_matches(&nkey, l, ke)
     }
 
     /* resolveRegularExpression */
+    /// `key` is matched against the names of the keys of `compartment`, the
+    /// compartment left out: `^user_` selects `10.0.0.1::user_id`.
     fn resolve_regular_expression_with_compartment(
         &self,
         key: String,
         compartment: String,
-        l: Vec<VariableValue>,
-        ke: KeyExclusions,
+        l: &mut Vec<VariableValue>,
+        ke: &KeyExclusions,
     ) {
-        let nkey = format!("{compartment}::{key}");
-        self.resolve_regular_expression(&nkey, l, ke);
+        let (Ok(prefix), Ok(re)) = (
+            cached_regex(&compartment_regex(&compartment)),
+            cached_regex(&key),
+        ) else {
+            return;
+        };
+        let mut found = Vec::new();
+        self.resolve_regular_expression(prefix.as_str(), &mut found, ke);
+        l.extend(found.into_iter().filter(|v| {
+            let name = prefix
+                .find(v.get_key())
+                .map_or("", |m| &v.get_key()[m.end()..]);
+            re.is_match(name)
+        }));
     }
 
     fn resolve_regular_expression_with_compartments(
@@ -176,10 +333,10 @@ pub trait Collection {
         key: String,
         compartment: String,
         compartment2: String,
-        l: Vec<VariableValue>,
-        ke: KeyExclusions,
+        l: &mut Vec<VariableValue>,
+        ke: &KeyExclusions,
     ) {
-        let nkey = format!("{compartment}::{compartment2}::{key}");
-        self.resolve_regular_expression(&nkey, l, ke);
+        let compartment = format!("{compartment}::{compartment2}");
+        self.resolve_regular_expression_with_compartment(key, compartment, l, ke);
     }
 }
@@ -12,31 +12,40 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{backend::InMemoryPerProcess, Collection};
+use std::{collections::HashSet, sync::Arc};
 
-pub struct Collections<C>
-where
-    C: Collection,
-{
+use super::{backend::InMemoryPerProcess, Collection, SharedCollection};
+
+/// The collections of a transaction: the persistent ones, shared with the
+/// other transactions, with the key of the record of this transaction,
+/// and TX.
+pub struct Collections {
     pub m_global_collection_key: String,
     pub m_ip_collection_key: String,
     pub m_session_collection_key: String,
     pub m_user_collection_key: String,
     pub m_resource_collection_key: String,
 
-    pub m_global_collection: C,
-    pub m_ip_collection: C,
-    pub m_session_collection: C,
-    pub m_user_collection: C,
-    pub m_resource_collection: C,
+    pub m_global_collection: SharedCollection,
+    pub m_ip_collection: SharedCollection,
+    pub m_session_collection: SharedCollection,
+    pub m_user_collection: SharedCollection,
+    pub m_resource_collection: SharedCollection,
     pub m_tx_collection: InMemoryPerProcess,
+
+    /// Persistent collections whose record the transaction wrote, see
+    /// `update_records`.
+    m_updated: HashSet<String>,
 }
 
-impl<C> Collections<C>
-where
-    C: Collection,
-{
-    pub fn new(global: C, ip: C, session: C, user: C, resource: C) -> Collections<C> {
+impl Collections {
+    pub fn new(
+        global: SharedCollection,
+        ip: SharedCollection,
+        session: SharedCollection,
+        user: SharedCollection,
+        resource: SharedCollection,
+    ) -> Collections {
         Collections {
             m_global_collection_key: String::from(""),
             m_ip_collection_key: String::from(""),
@@ -51,6 +60,88 @@ where
             m_resource_collection: resource,
 
             m_tx_collection: InMemoryPerProcess::new("TX"),
+
+            m_updated: HashSet::new(),
+        }
+    }
+
+    /// Collections of a transaction created without a `ModSecurity`
+    /// instance, not shared with any other transaction.
+    pub fn new_in_memory() -> Collections {
+        let collection =
+            |name: &str| -> SharedCollection { Arc::new(InMemoryPerProcess::new(name)) };
+        Collections::new(
+            collection("GLOBAL"),
+            collection("IP"),
+            collection("SESSION"),
+            collection("USER"),
+            collection("RESOURCE"),
+        )
+    }
+
+    /// The persistent collection `name`, e.g. `IP`, and the key of the
+    /// record of the transaction in it.
+    pub fn persistent(&self, name: &str) -> Result<(&SharedCollection, &str), String> {
+        let (collection, key) = match name.to_uppercase().as_str() {
+            "GLOBAL" => (&self.m_global_collection, &self.m_global_collection_key),
+            "IP" => (&self.m_ip_collection, &self.m_ip_collection_key),
+            "SESSION" => (&self.m_session_collection, &self.m_session_collection_key),
+            "USER" => (&self.m_user_collection, &self.m_user_collection_key),
+            "RESOURCE" => (&self.m_resource_collection, &self.m_resource_collection_key),
+            _ => return Err(format!("Unknown collection {name}.")),
+        };
+        if key.is_empty() {
+            return Err(format!("Collection {name} is not initialised."));
+        }
+        Ok((collection, key))
+    }
+
+    /// Bind the collection `name` to the record `key`, created when it
+    /// does not exist or expired, see `Collection::init_record`. Returns
+    /// whether the record is new.
+    pub fn init_collection(&mut self, name: &str, key: &str, timeout: i64) -> Result<bool, String> {
+        if key.is_empty() {
+            return Err(format!("Empty key for collection {name}."));
+        }
+        let record_key = match name.to_uppercase().as_str() {
+            "GLOBAL" => &mut self.m_global_collection_key,
+            "IP" => &mut self.m_ip_collection_key,
+            "SESSION" => &mut self.m_session_collection_key,
+            "USER" => &mut self.m_user_collection_key,
+            "RESOURCE" => &mut self.m_resource_collection_key,
+            _ => return Err(format!("Unknown collection {name}.")),
+        };
+        *record_key = key.to_string();
+
+        let (collection, compartment) = self.persistent(name)?;
+        Ok(collection.init_record(compartment, timeout))
+    }
+
+    /// Expire `key` of the collection `name` in `expiry_seconds`, see
+    /// `Collection::set_expiry`.
+    pub fn set_expiry(&mut self, name: &str, key: &str, expiry_seconds: i64) -> Result<(), String> {
+        if name.eq_ignore_ascii_case("TX") {
+            self.m_tx_collection.set_expiry(key, expiry_seconds);
+            return Ok(());
+        }
+        let (collection, compartment) = self.persistent(name)?;
+        collection.set_expiry_with_compartment(
+            key.to_string(),
+            compartment.to_string(),
+            expiry_seconds,
+        );
+        self.m_updated.insert(name.to_uppercase());
+        Ok(())
+    }
+
+    /// Account an update of every record the transaction wrote, see
+    /// `Collection::update_record`: a record in use expires TIMEOUT seconds
+    /// after its last update, not after its creation.
+    pub fn update_records(&mut self) {
+        for name in std::mem::take(&mut self.m_updated) {
+            if let Ok((collection, compartment)) = self.persistent(&name) {
+                collection.update_record(compartment);
+            }
         }
     }
 }
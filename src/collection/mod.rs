@@ -19,3 +19,179 @@ pub mod collections;
 // re-export
 pub use collection::*;
 pub use collections::*;
+
+#[test]
+fn test_collection_expiry() {
+    use crate::{
+        actions::{action::Action, expire_var::ExpireVar, init_col::InitCol},
+        modsecurity::{modsecurity::ModSecurity, RulesSetProperties},
+        rules::RuleWithActions,
+    };
+    use backend::InMemoryPerProcess;
+    use std::sync::Arc;
+
+    let ip = InMemoryPerProcess::new("IP");
+    ip.store("10.0.0.1::score".to_string(), "1".to_string());
+    ip.set_expiry("10.0.0.1::score", 0);
+    assert!(ip.resolve_first("10.0.0.1::score").is_none());
+    ip.store("10.0.0.1::score".to_string(), "2".to_string());
+    assert_eq!(ip.resolve_first("10.0.0.1::score").unwrap(), "2");
+
+    // record metadata
+    assert!(ip.init_record("10.0.0.1", 3600));
+    let metadata =
+        |ip: &InMemoryPerProcess, name: &str| ip.resolve_first(&format!("10.0.0.1::{name}"));
+    assert_eq!(metadata(&ip, "IS_NEW").unwrap(), "1");
+    assert_eq!(metadata(&ip, "TIMEOUT").unwrap(), "3600");
+    assert!(metadata(&ip, "CREATE_TIME").is_some());
+    assert!(!ip.init_record("10.0.0.1", 3600));
+    assert_eq!(metadata(&ip, "IS_NEW").unwrap(), "0");
+    ip.update_record("10.0.0.1");
+    assert_eq!(metadata(&ip, "UPDATE_COUNTER").unwrap(), "1");
+    assert!(metadata(&ip, "LAST_UPDATE_TIME").is_some());
+
+    // the keys of an expired record expire with it
+    ip.set_expiry("10.0.0.1", 0);
+    assert!(metadata(&ip, "score").is_none());
+    assert!(ip.init_record("10.0.0.1", 3600));
+    assert!(metadata(&ip, "score").is_none());
+
+    // expirevar
+    let modsec = ModSecurity::<InMemoryPerProcess>::new();
+    let t = modsec.new_transaction(Arc::new(RulesSetProperties::new()));
+    t.m_collections.borrow_mut().m_ip_collection_key = "10.0.0.2".to_string();
+    modsec
+        .m_ip_collection
+        .store("10.0.0.2::score".to_string(), "5".to_string());
+    let ra = RuleWithActions {};
+    ExpireVar::new("expirevar:IP.score=60")
+        .unwrap()
+        .evaluate_rule_actions(&ra, &t);
+    let score = || modsec.m_ip_collection.resolve_first("10.0.0.2::score");
+    assert_eq!(score().unwrap(), "5");
+    ExpireVar::new("expirevar:ip.score=0")
+        .unwrap()
+        .evaluate_rule_actions(&ra, &t);
+    assert!(score().is_none());
+    // SESSION is not initialised, nothing to expire
+    ExpireVar::new("expirevar:SESSION.score=0")
+        .unwrap()
+        .evaluate_rule_actions(&ra, &t);
+    assert!(ExpireVar::new("expirevar:IP.score").is_err());
+    assert!(ExpireVar::new("expirevar:score=10").is_err());
+
+    // a record written by a transaction outlives the TIMEOUT it was created
+    // with
+    let mut rules = RulesSetProperties::new();
+    rules.m_collection_timeout = 3;
+    let rules = Arc::new(rules);
+    let transaction = || {
+        let mut t = modsec.new_transaction(rules.clone());
+        InitCol::new("initcol:ip=10.0.0.3")
+            .unwrap()
+            .evaluate_rule_actions(&ra, &t);
+        ExpireVar::new("expirevar:IP.score=60")
+            .unwrap()
+            .evaluate_rule_actions(&ra, &t);
+        t.process_logging().unwrap();
+    };
+    let counter = || {
+        modsec
+            .m_ip_collection
+            .resolve_first("10.0.0.3::UPDATE_COUNTER")
+    };
+    transaction();
+    assert_eq!(counter().unwrap(), "1");
+    std::thread::sleep(std::time::Duration::from_millis(1500));
+    transaction();
+    std::thread::sleep(std::time::Duration::from_millis(1600));
+    assert_eq!(counter().unwrap(), "2");
+}
+
+#[test]
+fn test_regular_expression_keys() {
+    use crate::{
+        utils::regex_cache::cached_regex,
+        variables::{KeyExclusion, KeyExclusionRegex, KeyExclusionString, KeyExclusions},
+    };
+
+    let re = cached_regex("^user_").unwrap();
+    assert!(re.is_match("USER_id"));
+    assert!(!re.is_match("id_user_"));
+    assert!(std::sync::Arc::ptr_eq(
+        &re,
+        &cached_regex("^user_").unwrap()
+    ));
+    assert!(cached_regex("(").is_err());
+
+    // the pattern applies to the name of the key, not to the record it
+    // belongs to
+    let ip = backend::InMemoryPerProcess::new("IP");
+    for key in ["user_id", "id_user_", "old_password", "admin"] {
+        ip.store(format!("10.0.0.1::{key}"), "1".to_string());
+    }
+    ip.store("10.0.0.2::user_id".to_string(), "1".to_string());
+    let names = |re_key: &str| {
+        let mut l = vec![];
+        let ke = KeyExclusions::new();
+        ip.resolve_regular_expression_with_compartment(
+            re_key.to_string(),
+            "10.0.0.1".to_string(),
+            &mut l,
+            &ke,
+        );
+        let mut names = l
+            .iter()
+            .map(|v| v.get_key().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    };
+    assert_eq!(names("^user_"), vec!["10.0.0.1::user_id"]);
+    assert_eq!(names("pass"), vec!["10.0.0.1::old_password"]);
+    assert_eq!(
+        names("^user_|^admin$"),
+        vec!["10.0.0.1::admin", "10.0.0.1::user_id"]
+    );
+    assert_eq!(
+        names("^id|^user"),
+        vec!["10.0.0.1::id_user_", "10.0.0.1::user_id"]
+    );
+
+    assert!(KeyExclusionRegex::new("^pass").unwrap().m_match("Password"));
+    assert!(KeyExclusionString::new("password").m_match("PASSWORD"));
+    assert!(!KeyExclusionString::new("password").m_match("pass"));
+}
+
+#[test]
+fn test_resolve_into() {
+    use crate::variables::{anchored::AnchoredSetVariable, KeyExclusionString, KeyExclusions};
+    use backend::InMemoryPerProcess;
+
+    let tx = InMemoryPerProcess::new("TX");
+    tx.store("anomaly_score".to_string(), "5".to_string());
+    tx.store("anomaly_level".to_string(), "2".to_string());
+    tx.store("blocking".to_string(), "1".to_string());
+
+    let mut l = vec![];
+    tx.resolve_single_match("anomaly_score", &mut l);
+    tx.resolve_regular_expression("^anomaly", &mut l, &KeyExclusions::new());
+    assert_eq!(l.len(), 3);
+    assert_eq!(l[0].get_key_with_collection(), "TX:anomaly_score");
+    assert_eq!(l[0].get_value(), "5");
+
+    let mut ke = KeyExclusions::new();
+    ke.push(Box::new(KeyExclusionString::new("blocking")));
+    let mut l = vec![];
+    tx.resolve_multi_matches("", &mut l, &ke);
+    assert_eq!(l.len(), 2);
+
+    let mut args = AnchoredSetVariable::new("ARGS");
+    args.set("user_name", "alice", 0);
+    args.set("password", "secret", 16);
+    let mut l = vec![];
+    args.resolve_regular_expression("^user_", &mut l);
+    args.resolve_by_key("Password", &mut l);
+    let values: Vec<&str> = l.iter().map(|v| v.get_value()).collect();
+    assert_eq!(values, ["alice", "secret"]);
+}
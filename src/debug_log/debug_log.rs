@@ -0,0 +1,157 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fs::File,
+    io::Write,
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Mutex,
+    },
+};
+
+use crate::audit_log::writer::open_append;
+
+/// Receives every line of the debug log, without the trailing newline.
+pub type DebugLogCallback = Box<dyn Fn(&str) + Send + Sync>;
+
+enum DebugLogOutput {
+    File(File),
+    Callback(DebugLogCallback),
+}
+
+/// Parse SecDebugLogLevel, a verbosity between 0 and 9.
+pub fn parse_debug_log_level(value: &str) -> Result<i32, String> {
+    value
+        .trim()
+        .parse::<i32>()
+        .ok()
+        .filter(|level| (0..=9).contains(level))
+        .ok_or_else(|| format!("Invalid value for SecDebugLogLevel: {}", value))
+}
+
+/// SecDebugLog and SecDebugLogLevel, shared by the transactions of a rule
+/// set and the audit log workers.
+///
+/// The levels are those of ModSecurity:
+///
+/// - 0: no logging
+/// - 1: errors
+/// - 2: warnings
+/// - 3: notices
+/// - 4: details of how the transactions are handled
+/// - 5: as 4, with every piece of information handled
+/// - 9: everything, including the rules evaluation details
+///
+/// Nothing is written until a file or a callback is set.
+pub struct DebugLog {
+    m_level: AtomicI32,
+    m_path: Mutex<String>,
+    m_output: Mutex<Option<DebugLogOutput>>,
+}
+
+impl DebugLog {
+    pub fn new() -> DebugLog {
+        DebugLog {
+            m_level: AtomicI32::new(0),
+            m_path: Mutex::new(String::new()),
+            m_output: Mutex::new(None),
+        }
+    }
+
+    /// SecDebugLog, the file is appended to.
+    pub fn set_debug_log_file(&self, path: &str) -> Result<(), String> {
+        let file = open_append(path, 0o600)
+            .map_err(|e| format!("Failed to open the debug log {}: {}", path, e))?;
+        *self.m_path.lock().unwrap_or_else(|e| e.into_inner()) = path.to_string();
+        *self.m_output.lock().unwrap_or_else(|e| e.into_inner()) = Some(DebugLogOutput::File(file));
+        Ok(())
+    }
+
+    /// Send the debug log to `callback` instead of a file.
+    pub fn set_debug_log_callback(&self, callback: DebugLogCallback) {
+        self.m_path
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        *self.m_output.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some(DebugLogOutput::Callback(callback));
+    }
+
+    /// SecDebugLogLevel, see `parse_debug_log_level`.
+    pub fn set_debug_log_level(&self, level: i32) -> Result<(), String> {
+        if !(0..=9).contains(&level) {
+            return Err(format!("Invalid value for SecDebugLogLevel: {}", level));
+        }
+        self.m_level.store(level, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn get_debug_log_level(&self) -> i32 {
+        self.m_level.load(Ordering::Relaxed)
+    }
+
+    pub fn get_debug_log_file(&self) -> String {
+        self.m_path
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn is_log_file_set(&self) -> bool {
+        !self.get_debug_log_file().is_empty()
+    }
+
+    pub fn is_log_level_set(&self) -> bool {
+        self.get_debug_log_level() > 0
+    }
+
+    /// Whether a message of `level` would be written, to skip building it
+    /// otherwise.
+    pub fn is_enabled(&self, level: i32) -> bool {
+        level <= self.get_debug_log_level()
+            && self
+                .m_output
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .is_some()
+    }
+
+    /// Write `msg` as is, when `level` is enabled.
+    pub fn write(&self, level: i32, msg: &str) {
+        if level > self.get_debug_log_level() {
+            return;
+        }
+
+        let mut guard = self.m_output.lock().unwrap_or_else(|e| e.into_inner());
+        match guard.as_mut() {
+            None => {}
+            Some(DebugLogOutput::Callback(callback)) => callback(msg),
+            // the debug log has nowhere to report its own failures
+            Some(DebugLogOutput::File(file)) => _ = writeln!(file, "{}", msg),
+        }
+    }
+
+    /// Write `msg` of the transaction `id`, for `uri`, prefixed as
+    /// `[<id>] [<uri>] [<level>] `.
+    pub fn write_transaction(&self, level: i32, id: &str, uri: &str, msg: &str) {
+        self.write(level, &format!("[{}] [{}] [{}] {}", id, uri, level, msg));
+    }
+}
+
+impl Default for DebugLog {
+    fn default() -> Self {
+        DebugLog::new()
+    }
+}
@@ -1,14 +1,93 @@
 // Copyright 2023 young2j
-// 
+//
 // Licensed under the Apache License, Version 2.0 (the "License");
 // you may not use this file except in compliance with the License.
 // You may obtain a copy of the License at
-// 
+//
 //     http://www.apache.org/licenses/LICENSE-2.0
-// 
+//
 // Unless required by applicable law or agreed to in writing, software
 // distributed under the License is distributed on an "AS IS" BASIS,
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod debug_log;
+
+pub use debug_log::*;
+
+/// Write a message of the transaction to the debug log, formatted only when
+/// `level` is enabled.
+///
+/// `ms_dbg!(transaction, 4, "Adding request argument: {}", name)`
+#[macro_export]
+macro_rules! ms_dbg {
+    ($transaction:expr, $level:expr, $($arg:tt)+) => {
+        if $transaction.m_rules.m_debug_log.is_enabled($level) {
+            $transaction.debug($level, &format!($($arg)+));
+        }
+    };
+}
+
+/// Same as `ms_dbg!`, for a message of the rule `rule_id`, prefixed with
+/// `(Rule: <id>)`.
+#[macro_export]
+macro_rules! ms_dbg_rule {
+    ($transaction:expr, $level:expr, $rule_id:expr, $($arg:tt)+) => {
+        if $transaction.m_rules.m_debug_log.is_enabled($level) {
+            $transaction.debug_rule($level, $rule_id, &format!($($arg)+));
+        }
+    };
+}
+
+#[test]
+fn test_debug_log() {
+    use crate::{modsecurity::RulesSetProperties, transaction::Transaction};
+    use std::{
+        env::temp_dir,
+        fs,
+        sync::{Arc, Mutex},
+    };
+
+    assert_eq!(parse_debug_log_level("9").unwrap(), 9);
+    assert!(parse_debug_log_level("10").is_err());
+
+    let rules = Arc::new(RulesSetProperties::new());
+    let debug_log = &rules.m_debug_log;
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let received = lines.clone();
+    debug_log.set_debug_log_callback(Box::new(move |line| {
+        received.lock().unwrap().push(line.to_string())
+    }));
+
+    // below the level of the message, nothing is written
+    debug_log.set_debug_log_level(3).unwrap();
+    let mut t = Transaction::new_with_id(rules.clone(), "unique-6");
+    t.process_uri("/?a=1", "GET", "1.1");
+    assert!(lines.lock().unwrap().is_empty());
+
+    debug_log.set_debug_log_level(4).unwrap();
+    let mut t = Transaction::new_with_id(rules.clone(), "unique-7");
+    t.process_uri("/?a=1", "GET", "1.1");
+    t.debug_rule(4, 1234, "Matched");
+    assert_eq!(
+        *lines.lock().unwrap(),
+        vec![
+            "[unique-7] [/?a=1] [4] Adding request argument (GET): name \"a\", value \"1\"",
+            "[unique-7] [/?a=1] [4] (Rule: 1234) Matched",
+        ]
+    );
+
+    let path = temp_dir().join("modsecurity-rs-debug.log");
+    _ = fs::remove_file(&path);
+    debug_log
+        .set_debug_log_file(path.to_str().unwrap())
+        .unwrap();
+    assert!(debug_log.is_log_file_set());
+    t.debug(1, "Error");
+    t.debug(5, "Not written");
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "[unique-7] [/?a=1] [1] Error\n"
+    );
+}
@@ -12,10 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+// modules mirror the libmodsecurity sources, e.g. audit_log/audit_log.rs
+#![allow(clippy::module_inception)]
+
 pub mod collection;
 
 pub mod actions;
+pub mod audit_log;
+pub mod debug_log;
 pub mod modsecurity;
+pub mod operators;
+pub mod request_body_processor;
 pub mod rules;
 pub mod transaction;
+pub mod utils;
 pub mod variables;
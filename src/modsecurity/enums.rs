@@ -77,6 +77,22 @@ pub enum Phases {
     NumberOfPhases,
 }
 
+impl Phases {
+    /// Name of the phase, e.g. `request_headers`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Phases::ConnectionPhase => "connection",
+            Phases::UriPhase => "uri",
+            Phases::RequestHeadersPhase => "request_headers",
+            Phases::RequestBodyPhase => "request_body",
+            Phases::ResponseHeadersPhase => "response_headers",
+            Phases::ResponseBodyPhase => "response_body",
+            Phases::LoggingPhase => "logging",
+            Phases::NumberOfPhases => "",
+        }
+    }
+}
+
 /**
  *
  * Properties used to configure the general log callback.
@@ -0,0 +1,31 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// What the disruptive actions of a transaction asked for, see
+/// `Transaction::intervene`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModSecurityIntervention {
+    /// HTTP status code to return to the client.
+    pub m_status: i32,
+    pub m_disruptive: bool,
+}
+
+impl Default for ModSecurityIntervention {
+    fn default() -> Self {
+        ModSecurityIntervention {
+            m_status: 200,
+            m_disruptive: false,
+        }
+    }
+}
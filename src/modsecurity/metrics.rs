@@ -0,0 +1,286 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Upper bounds, in seconds, of the collection latency histogram.
+pub const DEFAULT_LATENCY_BUCKETS: [f64; 10] = [
+    0.00001, 0.00005, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 1.0,
+];
+
+/// A counter per combination of label values.
+pub struct CounterFamily {
+    m_name: &'static str,
+    m_help: &'static str,
+    m_labels: &'static [&'static str],
+    m_values: Mutex<BTreeMap<Vec<String>, u64>>,
+}
+
+impl CounterFamily {
+    pub fn new(
+        name: &'static str,
+        help: &'static str,
+        labels: &'static [&'static str],
+    ) -> CounterFamily {
+        CounterFamily {
+            m_name: name,
+            m_help: help,
+            m_labels: labels,
+            m_values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn inc(&self, labels: &[&str]) {
+        self.inc_by(labels, 1);
+    }
+
+    pub fn inc_by(&self, labels: &[&str], n: u64) {
+        debug_assert_eq!(labels.len(), self.m_labels.len());
+        let key = labels.iter().map(|l| l.to_string()).collect();
+        let mut values = self.m_values.lock().unwrap_or_else(|e| e.into_inner());
+        *values.entry(key).or_insert(0) += n;
+    }
+
+    /// Value of the counter, 0 when never incremented.
+    pub fn get(&self, labels: &[&str]) -> u64 {
+        let key = labels
+            .iter()
+            .map(|l| l.to_string())
+            .collect::<Vec<String>>();
+        let values = self.m_values.lock().unwrap_or_else(|e| e.into_inner());
+        values.get(&key).copied().unwrap_or(0)
+    }
+
+    fn render(&self, out: &mut String) {
+        _ = writeln!(out, "# HELP {} {}", self.m_name, self.m_help);
+        _ = writeln!(out, "# TYPE {} counter", self.m_name);
+        let values = self.m_values.lock().unwrap_or_else(|e| e.into_inner());
+        for (key, value) in values.iter() {
+            _ = writeln!(
+                out,
+                "{}{} {}",
+                self.m_name,
+                format_labels(self.m_labels, key, None),
+                value
+            );
+        }
+    }
+}
+
+#[derive(Clone)]
+struct HistogramValue {
+    m_buckets: Vec<u64>,
+    m_sum: f64,
+    m_count: u64,
+}
+
+/// A histogram per combination of label values.
+pub struct HistogramFamily {
+    m_name: &'static str,
+    m_help: &'static str,
+    m_labels: &'static [&'static str],
+    m_bounds: Vec<f64>,
+    m_values: Mutex<BTreeMap<Vec<String>, HistogramValue>>,
+}
+
+impl HistogramFamily {
+    pub fn new(
+        name: &'static str,
+        help: &'static str,
+        labels: &'static [&'static str],
+        bounds: &[f64],
+    ) -> HistogramFamily {
+        HistogramFamily {
+            m_name: name,
+            m_help: help,
+            m_labels: labels,
+            m_bounds: bounds.to_vec(),
+            m_values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn observe(&self, labels: &[&str], value: f64) {
+        debug_assert_eq!(labels.len(), self.m_labels.len());
+        let key = labels.iter().map(|l| l.to_string()).collect();
+        let mut values = self.m_values.lock().unwrap_or_else(|e| e.into_inner());
+        let histogram = values.entry(key).or_insert_with(|| HistogramValue {
+            m_buckets: vec![0; self.m_bounds.len()],
+            m_sum: 0.0,
+            m_count: 0,
+        });
+        if let Some(i) = self.m_bounds.iter().position(|bound| value <= *bound) {
+            histogram.m_buckets[i] += 1;
+        }
+        histogram.m_sum += value;
+        histogram.m_count += 1;
+    }
+
+    /// Number of observations, 0 when never observed.
+    pub fn count(&self, labels: &[&str]) -> u64 {
+        let key = labels
+            .iter()
+            .map(|l| l.to_string())
+            .collect::<Vec<String>>();
+        let values = self.m_values.lock().unwrap_or_else(|e| e.into_inner());
+        values.get(&key).map_or(0, |h| h.m_count)
+    }
+
+    fn render(&self, out: &mut String) {
+        _ = writeln!(out, "# HELP {} {}", self.m_name, self.m_help);
+        _ = writeln!(out, "# TYPE {} histogram", self.m_name);
+        let values = self.m_values.lock().unwrap_or_else(|e| e.into_inner());
+        for (key, histogram) in values.iter() {
+            // buckets are cumulative in the exposition format
+            let mut cumulative = 0;
+            for (bound, n) in self.m_bounds.iter().zip(histogram.m_buckets.iter()) {
+                cumulative += n;
+                _ = writeln!(
+                    out,
+                    "{}_bucket{} {}",
+                    self.m_name,
+                    format_labels(self.m_labels, key, Some(&bound.to_string())),
+                    cumulative
+                );
+            }
+            _ = writeln!(
+                out,
+                "{}_bucket{} {}",
+                self.m_name,
+                format_labels(self.m_labels, key, Some("+Inf")),
+                histogram.m_count
+            );
+            let labels = format_labels(self.m_labels, key, None);
+            _ = writeln!(out, "{}_sum{} {}", self.m_name, labels, histogram.m_sum);
+            _ = writeln!(out, "{}_count{} {}", self.m_name, labels, histogram.m_count);
+        }
+    }
+}
+
+/// `{name="value",...}`, with the `le` label of the histogram buckets.
+fn format_labels(names: &[&str], values: &[String], le: Option<&str>) -> String {
+    let mut labels = names
+        .iter()
+        .zip(values.iter())
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+        .collect::<Vec<String>>();
+    if let Some(le) = le {
+        labels.push(format!("le=\"{}\"", le));
+    }
+    if labels.is_empty() {
+        return String::new();
+    }
+    format!("{{{}}}", labels.join(","))
+}
+
+fn escape_label_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Counters and histograms of a `ModSecurity` instance, shared by its
+/// transactions and rendered in the Prometheus text exposition format.
+pub struct Metrics {
+    /// Transactions that reached a phase
+    pub m_transactions: CounterFamily,
+    /// Transactions disrupted by a rule, by status of the intervention
+    pub m_interventions: CounterFamily,
+    pub m_rule_hits: CounterFamily,
+    pub m_rule_tag_hits: CounterFamily,
+    /// Failures of the request body processors, by REQBODY_PROCESSOR
+    pub m_body_processor_errors: CounterFamily,
+    pub m_collection_latency: HistogramFamily,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics {
+            m_transactions: CounterFamily::new(
+                "modsecurity_transactions_total",
+                "Transactions that reached a phase.",
+                &["phase"],
+            ),
+            m_interventions: CounterFamily::new(
+                "modsecurity_interventions_total",
+                "Transactions disrupted by a rule.",
+                &["status"],
+            ),
+            m_rule_hits: CounterFamily::new(
+                "modsecurity_rule_hits_total",
+                "Messages logged by a rule.",
+                &["rule_id"],
+            ),
+            m_rule_tag_hits: CounterFamily::new(
+                "modsecurity_rule_tag_hits_total",
+                "Messages logged by the rules with a tag.",
+                &["tag"],
+            ),
+            m_body_processor_errors: CounterFamily::new(
+                "modsecurity_body_processor_errors_total",
+                "Request bodies the body processor failed to parse.",
+                &["processor"],
+            ),
+            m_collection_latency: HistogramFamily::new(
+                "modsecurity_collection_operation_seconds",
+                "Latency of the collection backend operations.",
+                &["backend", "operation"],
+                &DEFAULT_LATENCY_BUCKETS,
+            ),
+        }
+    }
+
+    /// Account a collection backend operation.
+    pub fn observe_collection(&self, backend: &str, operation: &str, elapsed: Duration) {
+        self.m_collection_latency
+            .observe(&[backend, operation], elapsed.as_secs_f64());
+    }
+
+    /// Run and time a collection backend operation.
+    pub fn time_collection<T>(&self, backend: &str, operation: &str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.observe_collection(backend, operation, start.elapsed());
+        result
+    }
+
+    /// All the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.m_transactions.render(&mut out);
+        self.m_interventions.render(&mut out);
+        self.m_rule_hits.render(&mut out);
+        self.m_rule_tag_hits.render(&mut out);
+        self.m_body_processor_errors.render(&mut out);
+        self.m_collection_latency.render(&mut out);
+        out
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
@@ -14,10 +14,184 @@
 
 pub mod actions;
 pub mod enums;
+pub mod intervention;
+pub mod metrics;
 pub mod modsecurity;
+pub mod profiler;
+pub mod rules_set_properties;
+
+pub use intervention::*;
+pub use metrics::*;
+pub use profiler::*;
+pub use rules_set_properties::*;
 
 #[test]
 fn test() {
     use enums::*;
     let _p = LogProperty::TextLogProperty;
 }
+
+#[test]
+fn test_rule_profiler() {
+    use crate::collection::backend::InMemoryPerProcess;
+    use modsecurity::ModSecurity;
+    use std::{sync::Arc, thread, time::Duration};
+
+    let modsec = ModSecurity::<InMemoryPerProcess>::new();
+    let t = modsec.new_transaction(Arc::new(RulesSetProperties::new()));
+    let evaluate = |rule_id: i64, input: &str, delay: u64| {
+        let mut timer = t.profile_rule(rule_id);
+        let input = timer.transformations(|| input.to_lowercase());
+        timer.operator(&input, |input| {
+            thread::sleep(Duration::from_millis(delay));
+            input.contains("select")
+        })
+    };
+
+    // not recorded until enabled
+    evaluate(1, "SELECT", 0);
+    assert!(modsec.rule_profile_report().is_empty());
+
+    modsec.set_rule_profiling(true);
+    assert!(evaluate(1, "SELECT", 0));
+    assert!(!evaluate(1, "a=1", 0));
+    assert!(!evaluate(2, "b=2", 20));
+
+    let report = modsec.rule_profile_report();
+    assert_eq!(report.len(), 2);
+    assert_eq!(report[0].m_rule_id, 2);
+    assert!(report[0].m_max_operator_time >= Duration::from_millis(20));
+    assert_eq!(report[0].m_max_time, report[0].total_time());
+    let rule = &report[1];
+    assert_eq!(rule.m_rule_id, 1);
+    assert_eq!((rule.m_evaluations, rule.m_matches), (2, 1));
+    assert_eq!(rule.m_bytes_inspected, 9);
+    let text = modsec.rule_profile_report_text(1);
+    assert_eq!(text.lines().count(), 2);
+    assert!(text.lines().nth(1).unwrap().trim_start().starts_with("2 "));
+
+    modsec.reset_rule_profiles();
+    assert!(modsec.rule_profile_report().is_empty());
+}
+
+#[test]
+fn test_metrics() {
+    use crate::{collection::backend::InMemoryPerProcess, rules::RuleMessage};
+    use modsecurity::ModSecurity;
+    use std::{rc::Rc, sync::Arc, time::Duration};
+
+    let modsec = ModSecurity::<InMemoryPerProcess>::new();
+    let rules = Arc::new(RulesSetProperties::new());
+    {
+        let mut t = modsec.new_transaction(rules.clone());
+        t.process_uri("/upload", "POST", "1.1");
+        t.add_request_header("Content-Type", "multipart/form-data");
+        t.append_request_body(b"--x\r\n");
+        t.process_request_body();
+        t.process_response_headers(200, "HTTP 1.1");
+
+        let mut rm = RuleMessage::new(&t);
+        rm.m_rule_id = 942100;
+        rm.m_tags = vec!["attack-sqli".to_string(), "a \"quoted\" tag".to_string()];
+        rm.m_is_disruptive = true;
+        t.m_rules_messages.push(Rc::new(rm));
+        // labelled with the status of the intervention, not the response
+        t.intervene(403);
+    }
+    {
+        let mut t = modsec.new_transaction(rules);
+        t.process_uri("/", "GET", "1.1");
+    }
+    modsec
+        .metrics()
+        .observe_collection("memory", "get", Duration::from_micros(20));
+
+    let metrics = modsec.metrics();
+    assert_eq!(metrics.m_transactions.get(&["uri"]), 2);
+    assert_eq!(metrics.m_transactions.get(&["request_body"]), 1);
+    assert_eq!(metrics.m_interventions.get(&["403"]), 1);
+    assert_eq!(metrics.m_rule_hits.get(&["942100"]), 1);
+    assert_eq!(metrics.m_body_processor_errors.get(&["MULTIPART"]), 1);
+    assert_eq!(metrics.m_collection_latency.count(&["memory", "get"]), 1);
+
+    let text = modsec.render_metrics();
+    assert!(text.contains("# TYPE modsecurity_transactions_total counter\n"));
+    assert!(text.contains("modsecurity_transactions_total{phase=\"uri\"} 2\n"));
+    assert!(text.contains("modsecurity_interventions_total{status=\"403\"} 1\n"));
+    assert!(text.contains("modsecurity_rule_tag_hits_total{tag=\"a \\\"quoted\\\" tag\"} 1\n"));
+    assert!(text.contains("# TYPE modsecurity_collection_operation_seconds histogram\n"));
+    assert!(text.contains(
+        "modsecurity_collection_operation_seconds_bucket{backend=\"memory\",operation=\"get\",le=\"0.00001\"} 0\n"
+    ));
+    assert!(text.contains(
+        "modsecurity_collection_operation_seconds_bucket{backend=\"memory\",operation=\"get\",le=\"0.00005\"} 1\n"
+    ));
+    assert!(text.contains(
+        "modsecurity_collection_operation_seconds_count{backend=\"memory\",operation=\"get\"} 1\n"
+    ));
+}
+
+#[test]
+fn test_shared_across_threads() {
+    use crate::collection::{backend::InMemoryPerProcess, Collection};
+    use modsecurity::ModSecurity;
+    use std::{sync::Arc, thread};
+
+    let modsec = Arc::new(ModSecurity::<InMemoryPerProcess>::new());
+    let rules = Arc::new(RulesSetProperties::new());
+    let threads = (0..8)
+        .map(|i| {
+            let modsec = modsec.clone();
+            let rules = rules.clone();
+            thread::spawn(move || {
+                let t = modsec.new_transaction(rules);
+                let mut collections = t.m_collections.borrow_mut();
+                collections.init_collection("IP", "10.0.0.1", 3600).unwrap();
+                for j in 0..100 {
+                    let value = format!("{i}-{j}");
+                    collections
+                        .m_ip_collection
+                        .store("10.0.0.1::hit".to_string(), value);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    threads.into_iter().for_each(|t| t.join().unwrap());
+
+    // no write is lost to a contended lock
+    let ip = &modsec.m_ip_collection;
+    let mut l = vec![];
+    ip.resolve_single_match("10.0.0.1::hit", &mut l);
+    assert_eq!(l.len(), 800);
+    let mut l = vec![];
+    ip.resolve_single_match("10.0.0.1::CREATE_TIME", &mut l);
+    assert_eq!(l.len(), 1);
+}
+
+#[test]
+fn test_collection_purge() {
+    use crate::collection::{
+        backend::{LmdbConfig, DEFAULT_LMDB_MAP_SIZE, LMDB},
+        Collection,
+    };
+    use modsecurity::{ModSecurity, COLLECTION_PURGE_INTERVAL};
+    use std::{env::temp_dir, fs, process, sync::Arc};
+
+    let path = temp_dir().join(format!("modsecurity-purge-{}", process::id()));
+    let config = LmdbConfig::new(&path, DEFAULT_LMDB_MAP_SIZE);
+    let mut modsec = ModSecurity::<LMDB>::new();
+    modsec.m_ip_collection = Arc::new(LMDB::open("IP", &config).unwrap());
+    let ip = modsec.m_ip_collection.clone();
+    ip.store("10.0.0.1::score".to_string(), "1".to_string());
+    ip.set_expiry("10.0.0.1", 0);
+
+    // the expired record is reclaimed without being written again
+    let rules = Arc::new(RulesSetProperties::new());
+    for _ in 0..COLLECTION_PURGE_INTERVAL {
+        modsec.new_transaction(rules.clone());
+    }
+    assert_eq!(ip.purge().unwrap(), 0);
+
+    drop((modsec, ip));
+    _ = fs::remove_dir_all(&path);
+}
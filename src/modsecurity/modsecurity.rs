@@ -12,14 +12,25 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use md5::{Digest, Md5};
 use rand::{rngs::StdRng, SeedableRng};
 
-use super::enums::LogProperty;
-use crate::{collection::Collection, rules::RuleMessage};
+use super::{enums::LogProperty, Metrics, RuleProfile, RuleProfiler, RulesSetProperties};
+use crate::{
+    collection::{Collection, Collections},
+    rules::RuleMessage,
+    transaction::Transaction,
+    utils::system::hostname,
+};
 use std::{
     any::Any,
+    cell::RefCell,
     env::consts,
     rc::Rc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
     time::{SystemTime, UNIX_EPOCH},
 };
 
@@ -30,6 +41,10 @@ pub const MODSECURITY_TAG: &str = "";
 pub const MODSECURITY_TAG_NUM: i32 = 100;
 pub const MODSECURITY_VERSION_NUM: i32 = 30100100;
 
+/// Number of transactions between two purges of the expired records of the
+/// persistent collections, see `ModSecurity::purge_expired_collections`.
+pub const COLLECTION_PURGE_INTERVAL: usize = 1000;
+
 pub fn modsecurity_version() -> String {
     format!(
         "{}.{}.{}",
@@ -37,6 +52,24 @@ pub fn modsecurity_version() -> String {
     )
 }
 
+/// e.g. "ModSecurity v3.0.10 (linux)", see `ModSecurity::who_am_i`.
+pub fn modsecurity_who_am_i() -> String {
+    // todo: to CamelCase
+    format!("ModSecurity v{} ({})", modsecurity_version(), consts::OS)
+}
+
+/// Identifier of this server, the md5 of its host name, reported as
+/// `server_id` in the JSON audit log.
+pub fn unique_id() -> &'static str {
+    static UNIQUE_ID: OnceLock<String> = OnceLock::new();
+    UNIQUE_ID.get_or_init(|| {
+        Md5::digest(hostname().as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    })
+}
+
 /*
  * The callback is going to be called on every log request.
  *
@@ -59,20 +92,31 @@ pub struct ModSecurity<C>
 where
     C: Collection,
 {
-    pub m_global_collection: C,
-    pub m_resource_collection: C,
-    pub m_ip_collection: C,
-    pub m_session_collection: C,
-    pub m_user_collection: C,
+    /// Persistent collections, opened with the default configuration of
+    /// their backend and shared by the transactions. They may be replaced,
+    /// before any transaction, by configured ones, e.g. opened with
+    /// `LMDB::open` and given the `metrics()` of this instance.
+    ///
+    /// The collections synchronise their own accesses: the instance may be
+    /// shared, e.g. in an `Arc`, by the threads of the server, each running
+    /// its own transactions.
+    pub m_global_collection: Arc<C>,
+    pub m_resource_collection: Arc<C>,
+    pub m_ip_collection: Arc<C>,
+    pub m_session_collection: Arc<C>,
+    pub m_user_collection: Arc<C>,
     m_connector: String,
     m_whoami: String,
     m_logcb: ModSecLogCb,
     m_log_properties: LogProperty,
+    m_profiler: Arc<RuleProfiler>,
+    m_metrics: Arc<Metrics>,
+    m_transactions: AtomicUsize,
 }
 
 impl<C> ModSecurity<C>
 where
-    C: Collection,
+    C: Collection + 'static,
 {
     pub fn new() -> Self {
         // todo: uniqueId
@@ -88,20 +132,27 @@ where
         // curl_global_init(CURL_GLOBAL_ALL);
         // xmlInitParser();
 
-        // todo: to CamelCase
-        let platform = consts::OS;
-        let m_whoami = format!("ModSecurity v{} ({})", modsecurity_version(), platform);
+        let m_whoami = modsecurity_who_am_i();
+        let m_metrics = Arc::new(Metrics::new());
+        let collection = |name: &str| {
+            let mut collection = C::new(name);
+            collection.set_metrics(m_metrics.clone());
+            Arc::new(collection)
+        };
         ModSecurity {
-            m_global_collection: C::new("GLOBAL"),
-            m_resource_collection: C::new("RESOURCE"),
-            m_ip_collection: C::new("IP"),
-            m_session_collection: C::new("SESSION"),
-            m_user_collection: C::new("USER"),
+            m_global_collection: collection("GLOBAL"),
+            m_resource_collection: collection("RESOURCE"),
+            m_ip_collection: collection("IP"),
+            m_session_collection: collection("SESSION"),
+            m_user_collection: collection("USER"),
             m_connector: String::new(),
             m_whoami,
             // todo: logcb init
             m_logcb: || {},
             m_log_properties: LogProperty::TextLogProperty,
+            m_profiler: Arc::new(RuleProfiler::new()),
+            m_metrics,
+            m_transactions: AtomicUsize::new(0),
         }
     }
 
@@ -124,11 +175,13 @@ where
     /// 'connector' is consuming the API.
     ///
     /// It is strongly recommended to set a information in the following
-    ///       pattern:
+    /// pattern:
     ///
-    ///       ConnectorName vX.Y.Z-tag (something else)
-    ///       For instance: ModSecurity-nginx v0.0.1-alpha (Whee)
+    /// ```text
+    /// ConnectorName vX.Y.Z-tag (something else)
+    /// ```
     ///
+    /// For instance: ModSecurity-nginx v0.0.1-alpha (Whee)
     pub fn set_connector_information(&mut self, connector: &str) {
         self.m_connector = String::from(connector);
     }
@@ -142,6 +195,75 @@ where
         &self.m_connector
     }
 
+    /// Create a transaction evaluated against `rules`, reporting this
+    /// instance and its connector as the producer of its audit log entry,
+    /// and sharing the persistent collections of this instance.
+    ///
+    /// Every COLLECTION_PURGE_INTERVAL transactions, the expired records
+    /// are purged first.
+    pub fn new_transaction(&self, rules: Arc<RulesSetProperties>) -> Transaction {
+        let count = self.m_transactions.fetch_add(1, Ordering::Relaxed) + 1;
+        if count.is_multiple_of(COLLECTION_PURGE_INTERVAL) {
+            self.purge_expired_collections();
+        }
+
+        let mut transaction = Transaction::new(rules);
+        transaction.m_collections = RefCell::new(Collections::new(
+            self.m_global_collection.clone(),
+            self.m_ip_collection.clone(),
+            self.m_session_collection.clone(),
+            self.m_user_collection.clone(),
+            self.m_resource_collection.clone(),
+        ));
+        transaction.m_whoami = self.m_whoami.clone();
+        transaction.m_connector = self.m_connector.clone();
+        transaction.m_profiler = Some(self.m_profiler.clone());
+        transaction.m_metrics = Some(self.m_metrics.clone());
+        transaction
+    }
+
+    /// Remove the expired keys and records of the persistent collections,
+    /// which are otherwise only reclaimed when they are written again.
+    pub fn purge_expired_collections(&self) {
+        self.m_global_collection.purge_expired();
+        self.m_resource_collection.purge_expired();
+        self.m_ip_collection.purge_expired();
+        self.m_session_collection.purge_expired();
+        self.m_user_collection.purge_expired();
+    }
+
+    /// Record the evaluations of every rule, see `RuleProfiler`.
+    pub fn set_rule_profiling(&self, enabled: bool) {
+        self.m_profiler.set_enabled(enabled);
+    }
+
+    /// Profiles of the rules evaluated by the transactions of this
+    /// instance, the most time consuming first.
+    pub fn rule_profile_report(&self) -> Vec<RuleProfile> {
+        self.m_profiler.report()
+    }
+
+    /// The `limit` most time consuming rules, as a table.
+    pub fn rule_profile_report_text(&self, limit: usize) -> String {
+        self.m_profiler.report_text(limit)
+    }
+
+    pub fn reset_rule_profiles(&self) {
+        self.m_profiler.reset();
+    }
+
+    /// Metrics of the transactions of this instance, also fed by the
+    /// collection backends.
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.m_metrics
+    }
+
+    /// The metrics in the Prometheus text exposition format, to be served
+    /// by the connector.
+    pub fn render_metrics(&self) -> String {
+        self.m_metrics.render()
+    }
+
     pub fn server_log(&self, data: &dyn Any, rm: Rc<RuleMessage>) {
         // todo: server log
     }
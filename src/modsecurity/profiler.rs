@@ -0,0 +1,232 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Counters of one rule, see `RuleProfiler`.
+#[derive(Debug, Clone, Default)]
+pub struct RuleProfile {
+    pub m_rule_id: i64,
+    pub m_evaluations: u64,
+    pub m_matches: u64,
+    /// Cumulative time spent in the transformations
+    pub m_transformation_time: Duration,
+    /// Cumulative time spent in the operator
+    pub m_operator_time: Duration,
+    /// Slowest transformations of an evaluation
+    pub m_max_transformation_time: Duration,
+    /// Slowest operator of an evaluation
+    pub m_max_operator_time: Duration,
+    /// Slowest evaluation, transformations and operator
+    pub m_max_time: Duration,
+    /// Bytes given to the operator
+    pub m_bytes_inspected: u64,
+}
+
+impl RuleProfile {
+    pub fn total_time(&self) -> Duration {
+        self.m_transformation_time + self.m_operator_time
+    }
+}
+
+/// Per rule evaluation counters and timings, shared by the transactions of
+/// a `ModSecurity` instance. Disabled by default, recording then costs a
+/// single atomic load.
+pub struct RuleProfiler {
+    m_enabled: AtomicBool,
+    m_profiles: Mutex<HashMap<i64, RuleProfile>>,
+}
+
+impl RuleProfiler {
+    pub fn new() -> RuleProfiler {
+        RuleProfiler {
+            m_enabled: AtomicBool::new(false),
+            m_profiles: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.m_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.m_enabled.load(Ordering::Relaxed)
+    }
+
+    /// Account an evaluation of the rule `rule_id`.
+    pub fn record(
+        &self,
+        rule_id: i64,
+        transformation_time: Duration,
+        operator_time: Duration,
+        bytes_inspected: u64,
+        matched: bool,
+    ) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let mut profiles = self.m_profiles.lock().unwrap_or_else(|e| e.into_inner());
+        let profile = profiles.entry(rule_id).or_insert_with(|| RuleProfile {
+            m_rule_id: rule_id,
+            ..Default::default()
+        });
+        profile.m_evaluations += 1;
+        if matched {
+            profile.m_matches += 1;
+        }
+        profile.m_transformation_time += transformation_time;
+        profile.m_operator_time += operator_time;
+        profile.m_max_transformation_time =
+            profile.m_max_transformation_time.max(transformation_time);
+        profile.m_max_operator_time = profile.m_max_operator_time.max(operator_time);
+        profile.m_max_time = profile.m_max_time.max(transformation_time + operator_time);
+        profile.m_bytes_inspected += bytes_inspected;
+    }
+
+    /// Profiles of the rules evaluated since the last reset, the most time
+    /// consuming first.
+    pub fn report(&self) -> Vec<RuleProfile> {
+        let mut report = self
+            .m_profiles
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .cloned()
+            .collect::<Vec<RuleProfile>>();
+        report.sort_by(|a, b| {
+            b.total_time()
+                .cmp(&a.total_time())
+                .then(a.m_rule_id.cmp(&b.m_rule_id))
+        });
+        report
+    }
+
+    /// The `limit` most time consuming rules as a table, times in
+    /// microseconds.
+    pub fn report_text(&self, limit: usize) -> String {
+        let mut text = format!(
+            "{:>10} {:>10} {:>10} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12}\n",
+            "rule",
+            "evals",
+            "matches",
+            "total_us",
+            "tfn_us",
+            "op_us",
+            "max_tfn_us",
+            "max_op_us",
+            "bytes"
+        );
+        for profile in self.report().iter().take(limit) {
+            _ = writeln!(
+                text,
+                "{:>10} {:>10} {:>10} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12}",
+                profile.m_rule_id,
+                profile.m_evaluations,
+                profile.m_matches,
+                profile.total_time().as_micros(),
+                profile.m_transformation_time.as_micros(),
+                profile.m_operator_time.as_micros(),
+                profile.m_max_transformation_time.as_micros(),
+                profile.m_max_operator_time.as_micros(),
+                profile.m_bytes_inspected
+            );
+        }
+        text
+    }
+
+    /// Forget the profiles recorded so far.
+    pub fn reset(&self) {
+        self.m_profiles
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+impl Default for RuleProfiler {
+    fn default() -> Self {
+        RuleProfiler::new()
+    }
+}
+
+/// Times an evaluation of a rule, recorded to the profiler when dropped.
+/// Without an enabled profiler, the closures are run untimed.
+pub struct RuleTimer<'a> {
+    m_profiler: Option<&'a RuleProfiler>,
+    m_rule_id: i64,
+    m_transformation_time: Duration,
+    m_operator_time: Duration,
+    m_bytes_inspected: u64,
+    m_matched: bool,
+}
+
+impl<'a> RuleTimer<'a> {
+    pub fn new(profiler: Option<&'a RuleProfiler>, rule_id: i64) -> RuleTimer<'a> {
+        RuleTimer {
+            m_profiler: profiler.filter(|p| p.is_enabled()),
+            m_rule_id: rule_id,
+            m_transformation_time: Duration::ZERO,
+            m_operator_time: Duration::ZERO,
+            m_bytes_inspected: 0,
+            m_matched: false,
+        }
+    }
+
+    /// Run the transformations of a value.
+    pub fn transformations<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        if self.m_profiler.is_none() {
+            return f();
+        }
+        let start = Instant::now();
+        let res = f();
+        self.m_transformation_time += start.elapsed();
+        res
+    }
+
+    /// Run the operator on `input`, returns whether it matched.
+    pub fn operator(&mut self, input: &str, f: impl FnOnce(&str) -> bool) -> bool {
+        if self.m_profiler.is_none() {
+            return f(input);
+        }
+        let start = Instant::now();
+        let matched = f(input);
+        self.m_operator_time += start.elapsed();
+        self.m_bytes_inspected += input.len() as u64;
+        self.m_matched |= matched;
+        matched
+    }
+}
+
+impl Drop for RuleTimer<'_> {
+    fn drop(&mut self) {
+        if let Some(profiler) = self.m_profiler {
+            profiler.record(
+                self.m_rule_id,
+                self.m_transformation_time,
+                self.m_operator_time,
+                self.m_bytes_inspected,
+                self.m_matched,
+            );
+        }
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use crate::{audit_log::AuditLog, debug_log::DebugLog};
+
+pub enum UploadKeepFiles {
+    /// Keep the uploaded files after the transaction is over.
+    OnUploadKeepFiles,
+    /// Remove the uploaded files when the transaction is over.
+    OffUploadKeepFiles,
+    /// Keep the uploaded files only if the transaction was relevant, that is
+    /// if at least one rule logged a message.
+    RelevantOnlyUploadKeepFiles,
+}
+
+impl UploadKeepFiles {
+    pub fn from_directive(value: &str) -> Result<UploadKeepFiles, String> {
+        match value.to_lowercase().as_str() {
+            "on" => Ok(UploadKeepFiles::OnUploadKeepFiles),
+            "off" => Ok(UploadKeepFiles::OffUploadKeepFiles),
+            "relevantonly" => Ok(UploadKeepFiles::RelevantOnlyUploadKeepFiles),
+            _ => Err(format!("Invalid value for SecUploadKeepFiles: {}", value)),
+        }
+    }
+}
+
+pub enum RuleEngine {
+    /// Process the rules.
+    EnabledRuleEngine,
+    /// Do not process any rule.
+    DisabledRuleEngine,
+    /// Process the rules but never execute the disruptive actions.
+    DetectionOnlyRuleEngine,
+}
+
+impl RuleEngine {
+    pub fn from_directive(value: &str) -> Result<RuleEngine, String> {
+        match value.to_lowercase().as_str() {
+            "on" => Ok(RuleEngine::EnabledRuleEngine),
+            "off" => Ok(RuleEngine::DisabledRuleEngine),
+            "detectiononly" => Ok(RuleEngine::DetectionOnlyRuleEngine),
+            _ => Err(format!("Invalid value for SecRuleEngine: {}", value)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleEngine::EnabledRuleEngine => "Enabled",
+            RuleEngine::DisabledRuleEngine => "Disabled",
+            RuleEngine::DetectionOnlyRuleEngine => "DetectionOnly",
+        }
+    }
+}
+
+/// Properties shared by every transaction created from the same rule set.
+///
+/// Each field corresponds to a configuration directive; the directive name is
+/// given in the field documentation.
+pub struct RulesSetProperties {
+    /// SecRuleEngine On|Off|DetectionOnly
+    pub m_sec_rule_engine: RuleEngine,
+
+    /// SecComponentSignature "name/version"
+    ///
+    /// Rule sets loaded, e.g. `OWASP_CRS/3.3.2`, reported in the audit log.
+    pub m_components: Vec<String>,
+
+    /// SecBodyCharsetDecoding On|Off
+    ///
+    /// Decode request and response bodies to UTF-8, according to the charset
+    /// declared in the Content-Type header, before the body processors run.
+    pub m_body_charset_decoding: bool,
+
+    /// SecUploadDir /path/to/dir
+    ///
+    /// Directory where the files of multipart uploads are written. Files are
+    /// not written to disk when it is empty.
+    pub m_upload_directory: String,
+
+    /// SecUploadFileMode octal
+    ///
+    /// Permissions of the files written to SecUploadDir, 0600 by default.
+    pub m_upload_file_mode: u32,
+
+    /// SecUploadKeepFiles On|Off|RelevantOnly
+    pub m_upload_keep_files: UploadKeepFiles,
+
+    /// SecGraphQLBodyProcessor On|Off
+    ///
+    /// Parse application/graphql bodies, and application/json bodies
+    /// carrying a GraphQL query, with the GraphQL body processor.
+    pub m_graphql_body_processor: bool,
+
+    /// SecGraphQLMaxDepth number
+    ///
+    /// Deepest field nesting allowed in a GraphQL query, 0 for no limit.
+    pub m_graphql_max_depth: usize,
+
+    /// SecGraphQLMaxAliases number
+    ///
+    /// Number of aliases allowed in a GraphQL query, 0 for no limit.
+    pub m_graphql_max_aliases: usize,
+
+    /// SecGraphQLMaxFields number
+    ///
+    /// Number of field paths and argument values a GraphQL query may expand
+    /// to, 10000 by default, 0 for no limit.
+    pub m_graphql_max_fields: usize,
+
+    /// SecCollectionTimeout seconds
+    ///
+    /// Seconds a record of a persistent collection lives after its last
+    /// update, 3600 by default, see `Collection::init_record`.
+    pub m_collection_timeout: i64,
+
+    /// SecAuditEngine, SecAuditLog, SecAuditLogParts, SecAuditLogType...
+    pub m_audit_log: AuditLog,
+
+    /// SecDebugLog /path/to/file, SecDebugLogLevel 0-9
+    ///
+    /// Shared with the audit log, whose workers report their failures to it.
+    pub m_debug_log: Arc<DebugLog>,
+}
+
+impl RulesSetProperties {
+    pub fn new() -> RulesSetProperties {
+        let m_debug_log = Arc::new(DebugLog::new());
+        let mut m_audit_log = AuditLog::new();
+        m_audit_log.set_debug_log(m_debug_log.clone());
+
+        RulesSetProperties {
+            m_sec_rule_engine: RuleEngine::EnabledRuleEngine,
+            m_components: Vec::new(),
+            m_body_charset_decoding: false,
+            m_upload_directory: String::new(),
+            m_upload_file_mode: 0o600,
+            m_upload_keep_files: UploadKeepFiles::OffUploadKeepFiles,
+            m_graphql_body_processor: false,
+            m_graphql_max_depth: 0,
+            m_graphql_max_aliases: 0,
+            m_graphql_max_fields: 10000,
+            m_collection_timeout: 3600,
+            m_audit_log,
+            m_debug_log,
+        }
+    }
+}
+
+impl Default for RulesSetProperties {
+    fn default() -> Self {
+        RulesSetProperties::new()
+    }
+}
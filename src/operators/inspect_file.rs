@@ -0,0 +1,189 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    io::Read,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use wait_timeout::ChildExt;
+
+use crate::{ms_dbg, transaction::Transaction};
+
+use super::{BaseOperator, Operator};
+
+pub const INSPECT_FILE_DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+pub const INSPECT_FILE_DEFAULT_MAX_CONCURRENCY: usize = 4;
+
+/// @inspectFile /path/to/script
+///
+/// Runs the script with the path of the file to inspect as its only
+/// argument. As in ModSecurity, an output starting with `1` means the file
+/// is clean; anything else, including a script that cannot be run, times
+/// out or cannot get a slot, is a match.
+///
+/// At most `set_max_concurrency` scripts run at the same time in the
+/// process, whatever the rule they belong to, the others wait for a slot no
+/// longer than `m_timeout`. A script still running after `m_timeout` is
+/// killed, and an output still open, e.g. by a process it left behind, is
+/// abandoned.
+pub struct InspectFile {
+    base: BaseOperator,
+    pub m_timeout: Duration,
+}
+
+/// The slots shared by every @inspectFile of the process.
+struct InspectFileSlots {
+    m_running: Mutex<usize>,
+    m_released: Condvar,
+    m_max_concurrency: AtomicUsize,
+}
+
+static INSPECT_FILE_SLOTS: InspectFileSlots = InspectFileSlots {
+    m_running: Mutex::new(0),
+    m_released: Condvar::new(),
+    m_max_concurrency: AtomicUsize::new(INSPECT_FILE_DEFAULT_MAX_CONCURRENCY),
+};
+
+impl InspectFile {
+    pub fn new(param: &str) -> InspectFile {
+        InspectFile {
+            base: BaseOperator::new("inspectFile", param),
+            m_timeout: INSPECT_FILE_DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.m_timeout = timeout;
+    }
+
+    /// Number of scripts run at the same time by all the @inspectFile of
+    /// the process.
+    pub fn set_max_concurrency(max_concurrency: usize) {
+        INSPECT_FILE_SLOTS
+            .m_max_concurrency
+            .store(max_concurrency.max(1), Ordering::Relaxed);
+    }
+
+    /// Wait for a free slot, at most until `deadline`.
+    fn acquire(&self, deadline: Instant) -> bool {
+        let slots = &INSPECT_FILE_SLOTS;
+        let mut running = slots.m_running.lock().unwrap_or_else(|e| e.into_inner());
+        while *running >= slots.m_max_concurrency.load(Ordering::Relaxed) {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            running = match slots.m_released.wait_timeout(running, deadline - now) {
+                Ok((guard, _)) => guard,
+                Err(e) => e.into_inner().0,
+            };
+        }
+        *running += 1;
+
+        true
+    }
+
+    fn release(&self) {
+        let slots = &INSPECT_FILE_SLOTS;
+        let mut running = slots.m_running.lock().unwrap_or_else(|e| e.into_inner());
+        *running -= 1;
+        slots.m_released.notify_one();
+    }
+
+    /// Run the script on `file`, returns its output.
+    pub fn run(&self, file: &str, deadline: Instant) -> Result<String, String> {
+        let mut child = Command::new(&self.base.m_param)
+            .arg(file)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Failed to execute {}: {}", self.base.m_param, e))?;
+
+        // read on a side thread, a chatty script must not block on a full
+        // pipe; the thread is left to the processes holding it after the
+        // deadline
+        let mut stdout = child.stdout.take().unwrap();
+        let (sender, output) = mpsc::channel();
+        thread::spawn(move || {
+            let mut output = String::new();
+            _ = stdout.read_to_string(&mut output);
+            _ = sender.send(output);
+        });
+
+        let status = child
+            .wait_timeout(deadline.saturating_duration_since(Instant::now()))
+            .map_err(|e| format!("Failed to wait for {}: {}", self.base.m_param, e))?;
+        if status.is_none() {
+            _ = child.kill();
+            _ = child.wait();
+            return Err(format!(
+                "{} timed out after {:?}",
+                self.base.m_param, self.m_timeout
+            ));
+        }
+
+        output
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .map_err(|_| {
+                format!(
+                    "Failed to read the output of {} within {:?}",
+                    self.base.m_param, self.m_timeout
+                )
+            })
+    }
+}
+
+impl Operator for InspectFile {
+    fn init(&mut self, _file: &str) -> Result<(), String> {
+        if self.base.m_param.ends_with(".lua") {
+            return Err("Lua scripts are not supported by @inspectFile.".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn evaluate(&self, transaction: &Transaction, input: &str) -> bool {
+        let deadline = Instant::now() + self.m_timeout;
+        if !self.acquire(deadline) {
+            ms_dbg!(
+                transaction,
+                4,
+                "@inspectFile: no slot available to run {} on {}",
+                self.base.m_param,
+                input
+            );
+            return self.base.negate(true);
+        }
+        let result = self.run(input, deadline);
+        self.release();
+
+        let clean = match result {
+            Ok(output) => output.starts_with('1'),
+            Err(e) => {
+                ms_dbg!(transaction, 2, "@inspectFile: {}", e);
+                false
+            }
+        };
+
+        self.base.negate(!clean)
+    }
+}
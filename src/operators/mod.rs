@@ -1,14 +1,79 @@
 // Copyright 2023 young2j
-// 
+//
 // Licensed under the Apache License, Version 2.0 (the "License");
 // you may not use this file except in compliance with the License.
 // You may obtain a copy of the License at
-// 
+//
 //     http://www.apache.org/licenses/LICENSE-2.0
-// 
+//
 // Unless required by applicable law or agreed to in writing, software
 // distributed under the License is distributed on an "AS IS" BASIS,
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod inspect_file;
+pub mod operator;
+
+pub use inspect_file::*;
+pub use operator::*;
+
+#[cfg(unix)]
+#[test]
+fn test_inspect_file() {
+    use crate::{modsecurity::RulesSetProperties, transaction::Transaction};
+    use std::{
+        env::temp_dir,
+        fs,
+        os::unix::fs::PermissionsExt,
+        sync::Arc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    let script = |name: &str, body: &str| {
+        let path = temp_dir().join(name);
+        fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o700)).unwrap();
+        path.to_str().unwrap().to_string()
+    };
+    let t = Transaction::new(Arc::new(RulesSetProperties::new()));
+
+    let clean = InspectFile::new(&script("msc-inspect-clean.sh", "echo '1 clean'"));
+    assert!(!clean.evaluate(&t, "/tmp/upload"));
+
+    let infected = InspectFile::new(&script("msc-inspect-infected.sh", "echo '0 infected'"));
+    assert!(infected.evaluate(&t, "/tmp/upload"));
+
+    let mut slow = InspectFile::new(&script("msc-inspect-slow.sh", "sleep 5; echo 1"));
+    slow.set_timeout(Duration::from_millis(100));
+    assert!(slow.evaluate(&t, "/tmp/upload"));
+
+    // an output left open by a background process is not waited for, and
+    // the slot is released
+    InspectFile::set_max_concurrency(1);
+    let mut detached = InspectFile::new(&script("msc-inspect-detached.sh", "sleep 5 & echo 1"));
+    detached.set_timeout(Duration::from_millis(200));
+    let start = Instant::now();
+    assert!(detached.evaluate(&t, "/tmp/upload"));
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert!(!clean.evaluate(&t, "/tmp/upload"));
+
+    // one bound for all the rules: the second script waits for the first
+    InspectFile::set_max_concurrency(1);
+    let start = Instant::now();
+    let rules = (0..2)
+        .map(|i| {
+            let script = script(&format!("msc-inspect-rule{i}.sh"), "sleep 0.3; echo 1");
+            thread::spawn(move || {
+                let t = Transaction::new(Arc::new(RulesSetProperties::new()));
+                InspectFile::new(&script).evaluate(&t, "/tmp/upload")
+            })
+        })
+        .collect::<Vec<_>>();
+    for rule in rules {
+        assert!(!rule.join().unwrap());
+    }
+    assert!(start.elapsed() >= Duration::from_millis(600));
+    InspectFile::set_max_concurrency(INSPECT_FILE_DEFAULT_MAX_CONCURRENCY);
+}
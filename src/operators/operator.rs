@@ -0,0 +1,50 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::transaction::Transaction;
+
+pub trait Operator {
+    fn init(&mut self, _file: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Returns true when the operator matched the input.
+    fn evaluate(&self, transaction: &Transaction, input: &str) -> bool;
+}
+
+pub struct BaseOperator {
+    pub m_op: String,
+    pub m_param: String,
+    pub m_negation: bool,
+}
+
+impl BaseOperator {
+    /// e.g. `BaseOperator::new("inspectFile", "/usr/bin/scan.sh")`
+    pub fn new(op: &str, param: &str) -> BaseOperator {
+        let (m_negation, m_op) = match op.strip_prefix('!') {
+            None => (false, op),
+            Some(op) => (true, op),
+        };
+
+        BaseOperator {
+            m_op: m_op.trim_start_matches('@').to_string(),
+            m_param: param.to_string(),
+            m_negation,
+        }
+    }
+
+    pub fn negate(&self, matched: bool) -> bool {
+        matched != self.m_negation
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Cow;
+
+use encoding_rs::Encoding;
+
+/// Extract the `charset` parameter of a Content-Type header value.
+///
+/// e.g. `text/html; charset="Shift_JIS"` => `Shift_JIS`
+pub fn charset_from_content_type(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        let value = value.trim().trim_matches('"').trim();
+        if value.is_empty() {
+            None
+        } else {
+            Some(value)
+        }
+    })
+}
+
+/// Decode `body` to UTF-8 according to `charset`.
+///
+/// A byte order mark, if any, takes precedence over the declared charset.
+/// Bodies that are already valid in the target charset are borrowed when
+/// possible. Unknown charsets and malformed byte sequences are reported as
+/// errors, the raw bytes are left untouched.
+pub fn decode_to_utf8<'b>(body: &'b [u8], charset: &str) -> Result<Cow<'b, str>, String> {
+    let encoding = match Encoding::for_label(charset.as_bytes()) {
+        None => return Err(format!("Unknown charset: {}", charset)),
+        Some(encoding) => encoding,
+    };
+
+    let (decoded, used, had_errors) = encoding.decode(body);
+    if had_errors {
+        return Err(format!("Invalid byte sequence for charset {}", used.name()));
+    }
+
+    Ok(decoded)
+}
+
+/// Decode an argument of a body to UTF-8 according to `charset`, the
+/// charset of the body when SecBodyCharsetDecoding is on, or as UTF-8.
+/// Malformed byte sequences are replaced, arguments are never rejected.
+pub fn decode_argument(value: &[u8], charset: Option<&str>) -> String {
+    match charset.map(|charset| decode_to_utf8(value, charset)) {
+        Some(Ok(decoded)) => decoded.into_owned(),
+        _ => String::from_utf8_lossy(value).into_owned(),
+    }
+}
+
+/// Decode `body` to UTF-8 according to the charset declared in
+/// `content_type`.
+///
+/// Returns `Ok(None)` when no charset is declared, the body is then left to
+/// the body processors as is.
+pub fn decode_body<'b>(body: &'b [u8], content_type: &str) -> Result<Option<Cow<'b, str>>, String> {
+    match charset_from_content_type(content_type) {
+        None => Ok(None),
+        Some(charset) => decode_to_utf8(body, charset).map(Some),
+    }
+}
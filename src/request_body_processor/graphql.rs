@@ -0,0 +1,864 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value as JsonValue;
+
+use crate::transaction::Transaction;
+
+/// Nesting allowed by the parser itself, whatever the configured limits, so
+/// a hostile document cannot exhaust the stack.
+const GRAPHQL_MAX_NESTING: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphQLValue {
+    Variable(String),
+    Int(String),
+    Float(String),
+    String(String),
+    Boolean(bool),
+    Null,
+    Enum(String),
+    List(Vec<GraphQLValue>),
+    Object(Vec<(String, GraphQLValue)>),
+}
+
+#[derive(Debug, Clone)]
+pub struct GraphQLField {
+    pub m_alias: Option<String>,
+    pub m_name: String,
+    pub m_arguments: Vec<(String, GraphQLValue)>,
+    pub m_selection_set: Vec<GraphQLSelection>,
+}
+
+#[derive(Debug, Clone)]
+pub enum GraphQLSelection {
+    Field(GraphQLField),
+    FragmentSpread(String),
+    InlineFragment(Vec<GraphQLSelection>),
+}
+
+#[derive(Debug, Clone)]
+pub struct GraphQLOperation {
+    /// query, mutation or subscription
+    pub m_kind: String,
+    pub m_name: Option<String>,
+    pub m_selection_set: Vec<GraphQLSelection>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GraphQLFragment {
+    pub m_name: String,
+    pub m_selection_set: Vec<GraphQLSelection>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GraphQLDocument {
+    pub m_operations: Vec<GraphQLOperation>,
+    pub m_fragments: Vec<GraphQLFragment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Punctuator(char),
+    Spread,
+    Name(String),
+    Int(String),
+    Float(String),
+    String(String),
+}
+
+struct Lexer<'q> {
+    m_input: &'q [u8],
+    m_pos: usize,
+}
+
+impl<'q> Lexer<'q> {
+    fn new(query: &'q str) -> Lexer<'q> {
+        Lexer {
+            m_input: query.as_bytes(),
+            m_pos: 0,
+        }
+    }
+
+    fn tokenize(mut self) -> Result<Vec<Token>, String> {
+        let mut tokens = Vec::new();
+        while let Some(token) = self.next_token()? {
+            tokens.push(token);
+        }
+
+        Ok(tokens)
+    }
+
+    fn peek(&self, n: usize) -> Option<u8> {
+        self.m_input.get(self.m_pos + n).copied()
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, String> {
+        // whitespace, commas, BOM and comments are ignored
+        loop {
+            match self.peek(0) {
+                None => return Ok(None),
+                Some(b' ' | b'\t' | b'\n' | b'\r' | b',') => self.m_pos += 1,
+                Some(0xef) if self.m_input[self.m_pos..].starts_with(&[0xef, 0xbb, 0xbf]) => {
+                    self.m_pos += 3
+                }
+                Some(b'#') => {
+                    while !matches!(self.peek(0), None | Some(b'\n' | b'\r')) {
+                        self.m_pos += 1;
+                    }
+                }
+                Some(_) => break,
+            }
+        }
+
+        let c = self.m_input[self.m_pos];
+        match c {
+            b'!' | b'$' | b'&' | b'(' | b')' | b':' | b'=' | b'@' | b'[' | b']' | b'{' | b'|'
+            | b'}' => {
+                self.m_pos += 1;
+                Ok(Some(Token::Punctuator(c as char)))
+            }
+            b'.' => {
+                if self.m_input[self.m_pos..].starts_with(b"...") {
+                    self.m_pos += 3;
+                    Ok(Some(Token::Spread))
+                } else {
+                    Err(format!("GraphQL: unexpected '.' at offset {}", self.m_pos))
+                }
+            }
+            b'"' => self.read_string().map(Some),
+            b'-' | b'0'..=b'9' => self.read_number().map(Some),
+            b'_' | b'a'..=b'z' | b'A'..=b'Z' => {
+                let start = self.m_pos;
+                while matches!(
+                    self.peek(0),
+                    Some(b'_' | b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9')
+                ) {
+                    self.m_pos += 1;
+                }
+                Ok(Some(Token::Name(self.slice(start, self.m_pos))))
+            }
+            _ => Err(format!(
+                "GraphQL: unexpected character '{}' at offset {}",
+                c as char, self.m_pos
+            )),
+        }
+    }
+
+    fn slice(&self, start: usize, end: usize) -> String {
+        String::from_utf8_lossy(&self.m_input[start..end]).into_owned()
+    }
+
+    fn read_number(&mut self) -> Result<Token, String> {
+        let start = self.m_pos;
+        let mut is_float = false;
+        if self.peek(0) == Some(b'-') {
+            self.m_pos += 1;
+        }
+        while let Some(c) = self.peek(0) {
+            match c {
+                b'0'..=b'9' => {}
+                b'.' | b'e' | b'E' => is_float = true,
+                b'+' | b'-' if matches!(self.m_input[self.m_pos - 1], b'e' | b'E') => {}
+                _ => break,
+            }
+            self.m_pos += 1;
+        }
+
+        let number = self.slice(start, self.m_pos);
+        if number == "-" {
+            return Err(format!("GraphQL: invalid number at offset {}", start));
+        }
+        if is_float {
+            Ok(Token::Float(number))
+        } else {
+            Ok(Token::Int(number))
+        }
+    }
+
+    fn read_string(&mut self) -> Result<Token, String> {
+        let start = self.m_pos;
+        if self.m_input[self.m_pos..].starts_with(b"\"\"\"") {
+            self.m_pos += 3;
+            let content_start = self.m_pos;
+            loop {
+                match self.peek(0) {
+                    None => {
+                        return Err(format!("GraphQL: unterminated string at offset {}", start))
+                    }
+                    Some(b'\\') if self.m_input[self.m_pos..].starts_with(b"\\\"\"\"") => {
+                        self.m_pos += 4
+                    }
+                    Some(b'"') if self.m_input[self.m_pos..].starts_with(b"\"\"\"") => {
+                        let content = self
+                            .slice(content_start, self.m_pos)
+                            .replace("\\\"\"\"", "\"\"\"");
+                        self.m_pos += 3;
+                        return Ok(Token::String(content));
+                    }
+                    Some(_) => self.m_pos += 1,
+                }
+            }
+        }
+
+        self.m_pos += 1;
+        let mut value = String::new();
+        let mut chunk_start = self.m_pos;
+        loop {
+            match self.peek(0) {
+                None | Some(b'\n' | b'\r') => {
+                    return Err(format!("GraphQL: unterminated string at offset {}", start))
+                }
+                Some(b'"') => {
+                    value.push_str(&self.slice(chunk_start, self.m_pos));
+                    self.m_pos += 1;
+                    return Ok(Token::String(value));
+                }
+                Some(b'\\') => {
+                    value.push_str(&self.slice(chunk_start, self.m_pos));
+                    let escaped = self.peek(1);
+                    self.m_pos += 2;
+                    match escaped {
+                        Some(b'"') => value.push('"'),
+                        Some(b'\\') => value.push('\\'),
+                        Some(b'/') => value.push('/'),
+                        Some(b'b') => value.push('\u{8}'),
+                        Some(b'f') => value.push('\u{c}'),
+                        Some(b'n') => value.push('\n'),
+                        Some(b'r') => value.push('\r'),
+                        Some(b't') => value.push('\t'),
+                        Some(b'u') => {
+                            let hex = self
+                                .m_input
+                                .get(self.m_pos..self.m_pos + 4)
+                                .map(|h| String::from_utf8_lossy(h).into_owned())
+                                .unwrap_or_default();
+                            match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                                Some(c) => value.push(c),
+                                None => value.push(char::REPLACEMENT_CHARACTER),
+                            }
+                            self.m_pos = (self.m_pos + 4).min(self.m_input.len());
+                        }
+                        _ => {
+                            return Err(format!(
+                                "GraphQL: invalid escape sequence at offset {}",
+                                self.m_pos - 2
+                            ))
+                        }
+                    }
+                    chunk_start = self.m_pos;
+                }
+                Some(_) => self.m_pos += 1,
+            }
+        }
+    }
+}
+
+struct Parser {
+    m_tokens: Vec<Token>,
+    m_pos: usize,
+    m_nesting: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.m_tokens.get(self.m_pos)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .m_tokens
+            .get(self.m_pos)
+            .cloned()
+            .ok_or_else(|| "GraphQL: unexpected end of document".to_string())?;
+        self.m_pos += 1;
+        Ok(token)
+    }
+
+    fn is_punctuator(&self, c: char) -> bool {
+        self.peek() == Some(&Token::Punctuator(c))
+    }
+
+    fn expect_punctuator(&mut self, c: char) -> Result<(), String> {
+        match self.next()? {
+            Token::Punctuator(p) if p == c => Ok(()),
+            t => Err(format!("GraphQL: expected '{}', found {:?}", c, t)),
+        }
+    }
+
+    fn expect_name(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Name(name) => Ok(name),
+            t => Err(format!("GraphQL: expected a name, found {:?}", t)),
+        }
+    }
+
+    fn enter(&mut self) -> Result<(), String> {
+        self.m_nesting += 1;
+        if self.m_nesting > GRAPHQL_MAX_NESTING {
+            return Err("GraphQL: document nested too deeply".to_string());
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.m_nesting -= 1;
+    }
+
+    fn parse_document(&mut self) -> Result<GraphQLDocument, String> {
+        let mut document = GraphQLDocument::default();
+        while let Some(token) = self.peek().cloned() {
+            match token {
+                Token::Punctuator('{') => document.m_operations.push(GraphQLOperation {
+                    m_kind: "query".to_string(),
+                    m_name: None,
+                    m_selection_set: self.parse_selection_set()?,
+                }),
+                Token::Name(name) if name == "fragment" => {
+                    self.m_pos += 1;
+                    let fragment_name = self.expect_name()?;
+                    match self.next()? {
+                        Token::Name(on) if on == "on" => {}
+                        t => return Err(format!("GraphQL: expected 'on', found {:?}", t)),
+                    }
+                    self.expect_name()?;
+                    self.parse_directives()?;
+                    document.m_fragments.push(GraphQLFragment {
+                        m_name: fragment_name,
+                        m_selection_set: self.parse_selection_set()?,
+                    });
+                }
+                Token::Name(kind)
+                    if kind == "query" || kind == "mutation" || kind == "subscription" =>
+                {
+                    self.m_pos += 1;
+                    let name = match self.peek() {
+                        Some(Token::Name(_)) => Some(self.expect_name()?),
+                        _ => None,
+                    };
+                    if self.is_punctuator('(') {
+                        self.parse_variable_definitions()?;
+                    }
+                    self.parse_directives()?;
+                    document.m_operations.push(GraphQLOperation {
+                        m_kind: kind,
+                        m_name: name,
+                        m_selection_set: self.parse_selection_set()?,
+                    });
+                }
+                t => return Err(format!("GraphQL: unexpected {:?} at document level", t)),
+            }
+        }
+
+        if document.m_operations.is_empty() {
+            return Err("GraphQL: no operation found in the document".to_string());
+        }
+
+        Ok(document)
+    }
+
+    /// ($id: ID! = 1 @dir, $list: [String])
+    fn parse_variable_definitions(&mut self) -> Result<(), String> {
+        self.expect_punctuator('(')?;
+        while !self.is_punctuator(')') {
+            self.expect_punctuator('$')?;
+            self.expect_name()?;
+            self.expect_punctuator(':')?;
+            self.parse_type()?;
+            if self.is_punctuator('=') {
+                self.m_pos += 1;
+                self.parse_value()?;
+            }
+            self.parse_directives()?;
+        }
+        self.expect_punctuator(')')
+    }
+
+    fn parse_type(&mut self) -> Result<(), String> {
+        self.enter()?;
+        if self.is_punctuator('[') {
+            self.m_pos += 1;
+            self.parse_type()?;
+            self.expect_punctuator(']')?;
+        } else {
+            self.expect_name()?;
+        }
+        if self.is_punctuator('!') {
+            self.m_pos += 1;
+        }
+        self.leave();
+        Ok(())
+    }
+
+    /// Directives are parsed to validate the document, then discarded.
+    fn parse_directives(&mut self) -> Result<(), String> {
+        while self.is_punctuator('@') {
+            self.m_pos += 1;
+            self.expect_name()?;
+            if self.is_punctuator('(') {
+                self.parse_arguments()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_selection_set(&mut self) -> Result<Vec<GraphQLSelection>, String> {
+        self.enter()?;
+        self.expect_punctuator('{')?;
+        let mut selections = Vec::new();
+        while !self.is_punctuator('}') {
+            if self.peek() == Some(&Token::Spread) {
+                self.m_pos += 1;
+                match self.peek() {
+                    Some(Token::Name(name)) if name != "on" => {
+                        let name = self.expect_name()?;
+                        self.parse_directives()?;
+                        selections.push(GraphQLSelection::FragmentSpread(name));
+                    }
+                    _ => {
+                        if self.peek() == Some(&Token::Name("on".to_string())) {
+                            self.m_pos += 1;
+                            self.expect_name()?;
+                        }
+                        self.parse_directives()?;
+                        selections.push(GraphQLSelection::InlineFragment(
+                            self.parse_selection_set()?,
+                        ));
+                    }
+                }
+                continue;
+            }
+
+            let mut name = self.expect_name()?;
+            let mut alias = None;
+            if self.is_punctuator(':') {
+                self.m_pos += 1;
+                alias = Some(name);
+                name = self.expect_name()?;
+            }
+            let arguments = if self.is_punctuator('(') {
+                self.parse_arguments()?
+            } else {
+                Vec::new()
+            };
+            self.parse_directives()?;
+            let selection_set = if self.is_punctuator('{') {
+                self.parse_selection_set()?
+            } else {
+                Vec::new()
+            };
+            selections.push(GraphQLSelection::Field(GraphQLField {
+                m_alias: alias,
+                m_name: name,
+                m_arguments: arguments,
+                m_selection_set: selection_set,
+            }));
+        }
+        self.expect_punctuator('}')?;
+        self.leave();
+
+        Ok(selections)
+    }
+
+    fn parse_arguments(&mut self) -> Result<Vec<(String, GraphQLValue)>, String> {
+        self.expect_punctuator('(')?;
+        let mut arguments = Vec::new();
+        while !self.is_punctuator(')') {
+            let name = self.expect_name()?;
+            self.expect_punctuator(':')?;
+            arguments.push((name, self.parse_value()?));
+        }
+        self.expect_punctuator(')')?;
+
+        Ok(arguments)
+    }
+
+    fn parse_value(&mut self) -> Result<GraphQLValue, String> {
+        self.enter()?;
+        let value = match self.next()? {
+            Token::Punctuator('$') => GraphQLValue::Variable(self.expect_name()?),
+            Token::Int(i) => GraphQLValue::Int(i),
+            Token::Float(f) => GraphQLValue::Float(f),
+            Token::String(s) => GraphQLValue::String(s),
+            Token::Name(n) if n == "true" => GraphQLValue::Boolean(true),
+            Token::Name(n) if n == "false" => GraphQLValue::Boolean(false),
+            Token::Name(n) if n == "null" => GraphQLValue::Null,
+            Token::Name(n) => GraphQLValue::Enum(n),
+            Token::Punctuator('[') => {
+                let mut list = Vec::new();
+                while !self.is_punctuator(']') {
+                    list.push(self.parse_value()?);
+                }
+                self.m_pos += 1;
+                GraphQLValue::List(list)
+            }
+            Token::Punctuator('{') => {
+                let mut object = Vec::new();
+                while !self.is_punctuator('}') {
+                    let name = self.expect_name()?;
+                    self.expect_punctuator(':')?;
+                    object.push((name, self.parse_value()?));
+                }
+                self.m_pos += 1;
+                GraphQLValue::Object(object)
+            }
+            t => return Err(format!("GraphQL: unexpected {:?} in value", t)),
+        };
+        self.leave();
+
+        Ok(value)
+    }
+}
+
+impl GraphQLDocument {
+    pub fn parse(query: &str) -> Result<GraphQLDocument, String> {
+        let mut parser = Parser {
+            m_tokens: Lexer::new(query).tokenize()?,
+            m_pos: 0,
+            m_nesting: 0,
+        };
+        parser.parse_document()
+    }
+
+    fn fragment(&self, name: &str) -> Option<&GraphQLFragment> {
+        self.m_fragments.iter().find(|f| f.m_name == name)
+    }
+
+    /// Number of aliased fields the operations resolve, fragment spreads
+    /// expanded: the aliases of a fragment count as many times as it is
+    /// spread. Must only be called on documents whose `depth` succeeded.
+    pub fn alias_count(&self) -> usize {
+        // as for `depth`, the count of every fragment is computed once
+        let mut counts = HashMap::new();
+        self.m_operations.iter().fold(0, |count, operation| {
+            count
+                .saturating_add(self.selection_alias_count(&operation.m_selection_set, &mut counts))
+        })
+    }
+
+    fn selection_alias_count<'d>(
+        &'d self,
+        selections: &'d [GraphQLSelection],
+        counts: &mut HashMap<&'d str, usize>,
+    ) -> usize {
+        let mut count = 0usize;
+        for selection in selections {
+            let c = match selection {
+                GraphQLSelection::Field(f) => (f.m_alias.is_some() as usize)
+                    .saturating_add(self.selection_alias_count(&f.m_selection_set, counts)),
+                GraphQLSelection::InlineFragment(set) => self.selection_alias_count(set, counts),
+                GraphQLSelection::FragmentSpread(name) => match self.fragment(name) {
+                    None => 0,
+                    Some(fragment) => match counts.get(fragment.m_name.as_str()) {
+                        Some(c) => *c,
+                        None => {
+                            // a cycle, reported by `depth`, counts 0
+                            counts.insert(&fragment.m_name, 0);
+                            let c = self.selection_alias_count(&fragment.m_selection_set, counts);
+                            counts.insert(&fragment.m_name, c);
+                            c
+                        }
+                    },
+                },
+            };
+            count = count.saturating_add(c);
+        }
+        count
+    }
+
+    /// Deepest field nesting of the operations, fragment spreads expanded.
+    /// Fragment cycles are reported as errors.
+    pub fn depth(&self) -> Result<usize, String> {
+        // the depth of every fragment is computed once, however many times
+        // it is spread
+        let mut depths = HashMap::new();
+        let mut depth = 0;
+        for operation in self.m_operations.iter() {
+            let mut visiting = HashSet::new();
+            depth = depth.max(self.selection_depth(
+                &operation.m_selection_set,
+                &mut visiting,
+                &mut depths,
+            )?);
+        }
+        Ok(depth)
+    }
+
+    fn selection_depth<'d>(
+        &'d self,
+        selections: &'d [GraphQLSelection],
+        visiting: &mut HashSet<&'d str>,
+        depths: &mut HashMap<&'d str, usize>,
+    ) -> Result<usize, String> {
+        let mut depth = 0;
+        for selection in selections {
+            let d = match selection {
+                GraphQLSelection::Field(f) => {
+                    1 + self.selection_depth(&f.m_selection_set, visiting, depths)?
+                }
+                GraphQLSelection::InlineFragment(set) => {
+                    self.selection_depth(set, visiting, depths)?
+                }
+                GraphQLSelection::FragmentSpread(name) => {
+                    let fragment = match self.fragment(name) {
+                        None => return Err(format!("GraphQL: unknown fragment {}", name)),
+                        Some(f) => f,
+                    };
+                    if let Some(d) = depths.get(fragment.m_name.as_str()) {
+                        *d
+                    } else {
+                        if !visiting.insert(&fragment.m_name) {
+                            return Err(format!("GraphQL: fragment cycle through {}", name));
+                        }
+                        let d =
+                            self.selection_depth(&fragment.m_selection_set, visiting, depths)?;
+                        visiting.remove(fragment.m_name.as_str());
+                        depths.insert(&fragment.m_name, d);
+                        d
+                    }
+                }
+            };
+            depth = depth.max(d);
+        }
+        Ok(depth)
+    }
+
+    /// Flatten the document into ARGS-like (key, value) pairs:
+    ///
+    /// - `graphql.operation`: query, mutation or subscription
+    /// - `graphql.operation_name`: the operation name, if any
+    /// - `graphql.field`: the path of every selected field, e.g.
+    ///   `user.posts`; aliases are ignored, every fragment is expanded
+    ///   once, at its first spread
+    /// - `graphql.args.<path>.<argument>`: every argument value, objects
+    ///   flattened with dots, lists giving one entry per element
+    ///
+    /// More than `max_fields` field paths and argument values (0 for no
+    /// limit) are reported as an error. Must only be called on documents
+    /// whose `depth` succeeded.
+    pub fn flatten(&self, max_fields: usize) -> Result<Vec<(String, String)>, String> {
+        let mut args = Vec::new();
+        let mut expanded = HashSet::new();
+        for operation in self.m_operations.iter() {
+            args.push(("graphql.operation".to_string(), operation.m_kind.clone()));
+            if let Some(name) = &operation.m_name {
+                args.push(("graphql.operation_name".to_string(), name.clone()));
+            }
+            self.flatten_selections(&operation.m_selection_set, "", &mut expanded, &mut args);
+            let fields = args
+                .iter()
+                .filter(|(key, _)| key == "graphql.field" || key.starts_with("graphql.args."))
+                .count();
+            if max_fields > 0 && fields > max_fields {
+                return Err(format!(
+                    "GraphQL: query expands to more than {} fields and arguments",
+                    max_fields
+                ));
+            }
+        }
+        Ok(args)
+    }
+
+    fn flatten_selections<'d>(
+        &'d self,
+        selections: &'d [GraphQLSelection],
+        path: &str,
+        expanded: &mut HashSet<&'d str>,
+        args: &mut Vec<(String, String)>,
+    ) {
+        for selection in selections {
+            match selection {
+                GraphQLSelection::Field(f) => {
+                    let field_path = if path.is_empty() {
+                        f.m_name.clone()
+                    } else {
+                        format!("{}.{}", path, f.m_name)
+                    };
+                    args.push(("graphql.field".to_string(), field_path.clone()));
+                    for (name, value) in f.m_arguments.iter() {
+                        let key = format!("graphql.args.{}.{}", field_path, name);
+                        flatten_value(&key, value, args);
+                    }
+                    self.flatten_selections(&f.m_selection_set, &field_path, expanded, args);
+                }
+                GraphQLSelection::InlineFragment(set) => {
+                    self.flatten_selections(set, path, expanded, args)
+                }
+                GraphQLSelection::FragmentSpread(name) => {
+                    if let Some(fragment) = self.fragment(name) {
+                        if expanded.insert(&fragment.m_name) {
+                            self.flatten_selections(
+                                &fragment.m_selection_set,
+                                path,
+                                expanded,
+                                args,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn flatten_value(key: &str, value: &GraphQLValue, args: &mut Vec<(String, String)>) {
+    match value {
+        GraphQLValue::Variable(v) => args.push((key.to_string(), format!("${}", v))),
+        GraphQLValue::Int(v) | GraphQLValue::Float(v) | GraphQLValue::Enum(v) => {
+            args.push((key.to_string(), v.clone()))
+        }
+        GraphQLValue::String(v) => args.push((key.to_string(), v.clone())),
+        GraphQLValue::Boolean(b) => args.push((key.to_string(), b.to_string())),
+        GraphQLValue::Null => args.push((key.to_string(), String::new())),
+        GraphQLValue::List(list) => list.iter().for_each(|v| flatten_value(key, v, args)),
+        GraphQLValue::Object(object) => object
+            .iter()
+            .for_each(|(k, v)| flatten_value(&format!("{}.{}", key, k), v, args)),
+    }
+}
+
+fn flatten_json(key: &str, value: &JsonValue, args: &mut Vec<(String, String)>) {
+    match value {
+        JsonValue::Null => args.push((key.to_string(), String::new())),
+        JsonValue::Bool(b) => args.push((key.to_string(), b.to_string())),
+        JsonValue::Number(n) => args.push((key.to_string(), n.to_string())),
+        JsonValue::String(s) => args.push((key.to_string(), s.clone())),
+        JsonValue::Array(list) => list.iter().for_each(|v| flatten_json(key, v, args)),
+        JsonValue::Object(object) => object
+            .iter()
+            .for_each(|(k, v)| flatten_json(&format!("{}.{}", key, k), v, args)),
+    }
+}
+
+/// GraphQL request body processor.
+///
+/// Handles `application/graphql` bodies, and `application/json` bodies
+/// whose top level is an object with a string `query` member, or an array
+/// of such objects; other JSON bodies are left alone. The operations, field
+/// paths and argument values are exposed in ARGS and ARGS_POST, see
+/// `GraphQLDocument::flatten`; the `variables` member as
+/// `graphql.variables.<name>`.
+pub struct GraphQL {
+    /// SecGraphQLMaxDepth, 0 for no limit.
+    pub m_max_depth: usize,
+    /// SecGraphQLMaxAliases, 0 for no limit.
+    pub m_max_aliases: usize,
+    /// SecGraphQLMaxFields, 0 for no limit.
+    pub m_max_fields: usize,
+}
+
+impl GraphQL {
+    /// Whether the body may be a GraphQL request, `process_body` decides
+    /// whether it actually is one.
+    pub fn is_graphql(content_type: &str) -> bool {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        mime.eq_ignore_ascii_case("application/graphql")
+            || mime.eq_ignore_ascii_case("application/json")
+    }
+
+    /// Extract the (key, value) pairs of a request body, None if it is a
+    /// JSON body which does not hold a GraphQL request.
+    pub fn process_body(
+        &self,
+        content_type: &str,
+        body: &str,
+    ) -> Result<Option<Vec<(String, String)>>, String> {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        if mime.eq_ignore_ascii_case("application/graphql") {
+            return self.process_query(body).map(Some);
+        }
+
+        let json: JsonValue = match serde_json::from_str(body) {
+            Ok(json) => json,
+            Err(_) => return Ok(None),
+        };
+        let requests = match json {
+            JsonValue::Array(batch) if !batch.is_empty() => batch,
+            JsonValue::Array(_) => return Ok(None),
+            single => vec![single],
+        };
+        let queries = requests
+            .iter()
+            .map(|request| match request.get("query") {
+                Some(JsonValue::String(query)) => Some(query),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>();
+        let queries = match queries {
+            Some(queries) => queries,
+            None => return Ok(None),
+        };
+
+        let mut args = Vec::new();
+        for (request, query) in requests.iter().zip(queries) {
+            args.extend(self.process_query(query)?);
+            if let Some(name) = request.get("operationName").and_then(|n| n.as_str()) {
+                args.push(("graphql.operationName".to_string(), name.to_string()));
+            }
+            if let Some(variables) = request.get("variables") {
+                flatten_json("graphql.variables", variables, &mut args);
+            }
+        }
+
+        Ok(Some(args))
+    }
+
+    fn process_query(&self, query: &str) -> Result<Vec<(String, String)>, String> {
+        let document = GraphQLDocument::parse(query)?;
+
+        let depth = document.depth()?;
+        if self.m_max_depth > 0 && depth > self.m_max_depth {
+            return Err(format!(
+                "GraphQL: query depth {} exceeds the limit of {}",
+                depth, self.m_max_depth
+            ));
+        }
+        let aliases = document.alias_count();
+        if self.m_max_aliases > 0 && aliases > self.m_max_aliases {
+            return Err(format!(
+                "GraphQL: {} aliases exceed the limit of {}",
+                aliases, self.m_max_aliases
+            ));
+        }
+
+        let mut args = document.flatten(self.m_max_fields)?;
+        args.push(("graphql.query".to_string(), query.to_string()));
+        Ok(args)
+    }
+
+    /// Populate ARGS and ARGS_POST from the transaction request body,
+    /// false if the body is not a GraphQL request.
+    pub fn process(&self, t: &mut Transaction, content_type: &str) -> Result<bool, String> {
+        let args = match self.process_body(content_type, &t.request_body_for_processors())? {
+            Some(args) => args,
+            None => return Ok(false),
+        };
+        // the flattened values have no place in the body, their origin is
+        // left empty
+        for (key, value) in args.iter() {
+            t.m_variables
+                .m_variable_args_post
+                .borrow_mut()
+                .set_with_length(key, value, 0, 0);
+            t.m_variables
+                .m_variable_args
+                .borrow_mut()
+                .set_with_length(key, value, 0, 0);
+        }
+
+        Ok(true)
+    }
+}
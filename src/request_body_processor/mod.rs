@@ -1,14 +1,203 @@
 // Copyright 2023 young2j
-// 
+//
 // Licensed under the Apache License, Version 2.0 (the "License");
 // you may not use this file except in compliance with the License.
 // You may obtain a copy of the License at
-// 
+//
 //     http://www.apache.org/licenses/LICENSE-2.0
-// 
+//
 // Unless required by applicable law or agreed to in writing, software
 // distributed under the License is distributed on an "AS IS" BASIS,
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod charset;
+pub mod graphql;
+pub mod multipart;
+
+#[test]
+fn test_charset() {
+    use charset::*;
+
+    let ct = "application/x-www-form-urlencoded; charset=\"Shift_JIS\"";
+    assert_eq!(charset_from_content_type(ct), Some("Shift_JIS"));
+    assert_eq!(charset_from_content_type("text/plain"), None);
+
+    // "テスト" in Shift_JIS
+    let body = [0x83, 0x65, 0x83, 0x58, 0x83, 0x67];
+    assert_eq!(decode_body(&body, ct).unwrap().unwrap(), "テスト");
+
+    let body = [0xff, 0xfe, b'a', 0x00, b'b', 0x00];
+    assert_eq!(decode_to_utf8(&body, "UTF-16").unwrap(), "ab");
+    assert_eq!(decode_to_utf8(&[0xe9], "ISO-8859-1").unwrap(), "é");
+
+    assert!(decode_to_utf8(&[0xff, 0xfe, 0xfd], "UTF-8").is_err());
+    assert!(decode_to_utf8(b"abc", "x-unknown").is_err());
+
+    // the arguments are decoded, REQUEST_BODY and the origins stay raw
+    use crate::{modsecurity::RulesSetProperties, transaction::Transaction};
+    use std::sync::Arc;
+
+    let mut rules = RulesSetProperties::new();
+    rules.m_body_charset_decoding = true;
+    let rules = Arc::new(rules);
+    let mut t = Transaction::new(rules.clone());
+    t.add_request_header("Content-Type", ct);
+    t.append_request_body(b"q=%83e%83X%83g&r=\x83\x65");
+    t.process_request_body();
+    let args = t.m_variables.m_variable_args_post.borrow();
+    let q = args.values("q").next().unwrap();
+    assert_eq!(q.get_value(), "テスト");
+    assert_eq!(q.get_origin().front().unwrap().m_offset, 2);
+    assert_eq!(args.resolve_first("r"), Some("テ"));
+    drop(args);
+    assert_eq!(
+        t.m_variables.m_variable_request_body.evaluate(),
+        String::from_utf8_lossy(b"q=%83e%83X%83g&r=\x83\x65")
+    );
+
+    let mut t = Transaction::new(rules);
+    t.add_request_header("Content-Type", "multipart/form-data; boundary=xyz");
+    t.append_request_body(
+        b"--xyz\r\n\
+Content-Disposition: form-data; name=\"city\"\r\n\
+Content-Type: text/plain; charset=ISO-8859-1\r\n\r\n\
+Li\xe8ge\r\n\
+--xyz--\r\n",
+    );
+    t.process_request_body();
+    let args = t.m_variables.m_variable_args_post.borrow();
+    assert_eq!(args.resolve_first("city"), Some("Liège"));
+}
+
+#[test]
+fn test_multipart_upload() {
+    use crate::{
+        modsecurity::{RulesSetProperties, UploadKeepFiles},
+        transaction::Transaction,
+    };
+    use std::{env::temp_dir, fs, path::Path, sync::Arc};
+
+    let dir = temp_dir().join("modsecurity-rs-upload-test");
+    _ = fs::create_dir_all(&dir);
+    let mut rules = RulesSetProperties::new();
+    rules.m_upload_directory = dir.to_str().unwrap().to_string();
+    rules.m_upload_keep_files = UploadKeepFiles::OffUploadKeepFiles;
+
+    let mut t = Transaction::new(Arc::new(rules));
+    t.add_request_header("Content-Type", "multipart/form-data; boundary=xyz");
+    t.append_request_body(
+        b"--xyz\r\n\
+Content-Disposition: form-data; name=\"user\"\r\n\r\n\
+admin\r\n\
+--xyz\r\n\
+Content-Disposition: form-data; name=\"upload\"; filename=\"a.txt\"\r\n\
+Content-Type: text/plain\r\n\r\n\
+hello\r\n\
+--xyz--\r\n",
+    );
+    t.process_request_body();
+
+    let vars = &t.m_variables;
+    assert_eq!(
+        vars.m_variable_args_post.borrow().resolve_first("user"),
+        Some("admin")
+    );
+    assert_eq!(vars.m_variable_files.resolve_first("upload"), Some("a.txt"));
+    assert_eq!(
+        vars.m_variable_files_sizes.resolve_first("upload"),
+        Some("5")
+    );
+    assert_eq!(
+        vars.m_variable_files_tmp_content.resolve_first("upload"),
+        Some("hello")
+    );
+    assert_eq!(vars.m_variable_files_combined_size.evaluate(), "5");
+    assert_eq!(vars.m_variable_multipart_strict_error.evaluate(), "0");
+
+    let tmp_name = vars
+        .m_variable_files_tmp_names
+        .resolve_first("upload")
+        .unwrap()
+        .to_string();
+    assert_eq!(fs::read(&tmp_name).unwrap(), b"hello");
+    drop(t);
+    assert!(!Path::new(&tmp_name).exists());
+}
+
+#[test]
+fn test_graphql() {
+    use graphql::*;
+
+    let processor = GraphQL {
+        m_max_depth: 3,
+        m_max_aliases: 1,
+        m_max_fields: 100,
+    };
+    let body = r#"{
+        "query": "query GetUser($id: ID!) { user(id: $id, filter: {name: \"x' OR 1=1\"}) { ...f } } fragment f on User { posts(first: 10) { title } }",
+        "variables": {"id": "42"}
+    }"#;
+    let args = processor
+        .process_body("application/json", body)
+        .unwrap()
+        .unwrap();
+    let has = |k: &str, v: &str| args.iter().any(|(key, value)| key == k && value == v);
+
+    assert!(has("graphql.operation", "query"));
+    assert!(has("graphql.operation_name", "GetUser"));
+    assert!(has("graphql.field", "user.posts.title"));
+    assert!(has("graphql.args.user.id", "$id"));
+    assert!(has("graphql.args.user.filter.name", "x' OR 1=1"));
+    assert!(has("graphql.args.user.posts.first", "10"));
+    assert!(has("graphql.variables.id", "42"));
+
+    let deep = "{ a { b { c { d } } } }";
+    assert!(processor.process_body("application/graphql", deep).is_err());
+    let aliases = "{ a1: user { id } a2: user { id } }";
+    assert!(processor
+        .process_body("application/graphql", aliases)
+        .is_err());
+    // the aliases of a fragment count at every spread
+    let spreads = "{ a { ...f } b { ...f } } fragment f on Q { x1: id }";
+    assert!(processor
+        .process_body("application/graphql", spreads)
+        .is_err());
+    let cycle = "{ ...a } fragment a on Q { ...b } fragment b on Q { ...a }";
+    assert!(processor
+        .process_body("application/graphql", cycle)
+        .is_err());
+
+    // JSON bodies without a query are left to the other processors
+    for json in [
+        r#"{"a": 1}"#,
+        r#"[{"query": "{ a }"}, {"a": 1}]"#,
+        "[]",
+        "not json",
+    ] {
+        assert_eq!(processor.process_body("application/json", json), Ok(None));
+    }
+
+    // every fragment is expanded once, whatever the number of spreads
+    let mut doubling = "{ ...f0 } fragment f0 on Q { a }".to_string();
+    for i in 1..40 {
+        doubling.push_str(&format!(
+            " fragment f{} on Q {{ ...f{} ...f{} }}",
+            i,
+            i - 1,
+            i - 1
+        ));
+    }
+    doubling = doubling.replacen("...f0", "...f39", 1);
+    let args = processor
+        .process_body("application/graphql", &doubling)
+        .unwrap()
+        .unwrap();
+    assert_eq!(args.iter().filter(|(k, _)| k == "graphql.field").count(), 1);
+
+    let wide = format!("{{ {} }}", "a ".repeat(101));
+    assert!(processor
+        .process_body("application/graphql", &wide)
+        .is_err());
+}
@@ -0,0 +1,470 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    borrow::Cow,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+};
+
+use rand::{distributions::Alphanumeric, Rng};
+
+use crate::{
+    request_body_processor::charset::{charset_from_content_type, decode_argument},
+    transaction::Transaction,
+    utils::time::UtcTime,
+};
+
+pub struct MultipartPart<'b> {
+    pub m_name: String,
+    /// Set for the file parts only.
+    pub m_filename: Option<String>,
+    pub m_headers: Vec<(String, String)>,
+    pub m_value: &'b [u8],
+    /// Offset of the value in the request body.
+    pub m_value_offset: usize,
+}
+
+/// multipart/form-data request body processor.
+///
+/// The flags mirror the MULTIPART_* variables, they are all folded into
+/// MULTIPART_STRICT_ERROR.
+pub struct Multipart<'b> {
+    pub m_boundary: String,
+    pub m_parts: Vec<MultipartPart<'b>>,
+
+    pub m_boundary_quoted: bool,
+    pub m_boundary_whitespace: bool,
+    pub m_crlf_line: bool,
+    pub m_lf_line: bool,
+    pub m_data_before: bool,
+    pub m_data_after: bool,
+    pub m_header_folding: bool,
+    pub m_invalid_header_folding: bool,
+    pub m_invalid_part: bool,
+    pub m_invalid_quoting: bool,
+    pub m_missing_semicolon: bool,
+    pub m_unmatched_boundary: bool,
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    if from > haystack.len() {
+        return None;
+    }
+    haystack[from..]
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|i| i + from)
+}
+
+impl<'b> Multipart<'b> {
+    pub fn is_multipart(content_type: &str) -> bool {
+        content_type
+            .trim_start()
+            .to_lowercase()
+            .starts_with("multipart/form-data")
+    }
+
+    pub fn parse(content_type: &str, body: &'b [u8]) -> Result<Multipart<'b>, String> {
+        let mut m = Multipart {
+            m_boundary: String::new(),
+            m_parts: Vec::new(),
+            m_boundary_quoted: false,
+            m_boundary_whitespace: false,
+            m_crlf_line: false,
+            m_lf_line: false,
+            m_data_before: false,
+            m_data_after: false,
+            m_header_folding: false,
+            m_invalid_header_folding: false,
+            m_invalid_part: false,
+            m_invalid_quoting: false,
+            m_missing_semicolon: false,
+            m_unmatched_boundary: false,
+        };
+        m.parse_boundary(content_type)?;
+
+        let delimiter = format!("--{}", m.m_boundary).into_bytes();
+        let next_delimiter = [b"\n".as_slice(), &delimiter].concat();
+        let first = match find(body, &delimiter, 0) {
+            None => return Err("Multipart: boundary not found in the request body.".to_string()),
+            Some(i) => i,
+        };
+        m.m_data_before = body[..first].iter().any(|b| !b.is_ascii_whitespace());
+
+        let mut pos = first + delimiter.len();
+        let mut closed = false;
+        loop {
+            if body[pos..].starts_with(b"--") {
+                closed = true;
+                m.m_data_after = body[pos + 2..].iter().any(|b| !b.is_ascii_whitespace());
+                break;
+            }
+
+            if body[pos..].starts_with(b"\r\n") {
+                m.m_crlf_line = true;
+                pos += 2;
+            } else if body[pos..].starts_with(b"\n") {
+                m.m_lf_line = true;
+                pos += 1;
+            } else {
+                // the boundary is followed by something else than a line end
+                m.m_invalid_part = true;
+                pos = find(body, b"\n", pos).map_or(body.len(), |i| i + 1);
+            }
+
+            let next = find(body, &next_delimiter, pos);
+            let mut end = next.unwrap_or(body.len());
+            if next.is_some() && end > pos && body[end - 1] == b'\r' {
+                end -= 1;
+            }
+            m.parse_part(body, pos, end);
+
+            match next {
+                None => break,
+                Some(i) => pos = i + next_delimiter.len(),
+            }
+        }
+        m.m_unmatched_boundary = !closed;
+
+        Ok(m)
+    }
+
+    fn parse_boundary(&mut self, content_type: &str) -> Result<(), String> {
+        if !Multipart::is_multipart(content_type) {
+            return Err(format!("Multipart: invalid Content-Type: {}", content_type));
+        }
+
+        let param = content_type.split(';').skip(1).find_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            if name.trim().eq_ignore_ascii_case("boundary") {
+                Some((name, value))
+            } else {
+                None
+            }
+        });
+        let (name, value) = match param {
+            None => return Err("Multipart: boundary not found in Content-Type.".to_string()),
+            Some(p) => p,
+        };
+
+        self.m_boundary_whitespace = name.ends_with(char::is_whitespace)
+            || value.starts_with(char::is_whitespace)
+            || value.trim_end().len() != value.len();
+        let value = value.trim();
+        if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+            self.m_boundary_quoted = true;
+            self.m_boundary = value[1..value.len() - 1].to_string();
+        } else {
+            self.m_boundary = value.to_string();
+        }
+
+        if self.m_boundary.is_empty() {
+            return Err("Multipart: empty boundary in Content-Type.".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn parse_part(&mut self, body: &'b [u8], start: usize, end: usize) {
+        let data = &body[start..end];
+        let (headers_end, value_start) = match (find(data, b"\r\n\r\n", 0), find(data, b"\n\n", 0))
+        {
+            (Some(crlf), Some(lf)) if lf < crlf => (lf, lf + 2),
+            (Some(crlf), _) => (crlf, crlf + 4),
+            (None, Some(lf)) => (lf, lf + 2),
+            (None, None) => {
+                self.m_invalid_part = true;
+                return;
+            }
+        };
+
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for line in String::from_utf8_lossy(&data[..headers_end]).split('\n') {
+            let line = line.trim_end_matches('\r');
+            if line.starts_with([' ', '\t']) {
+                self.m_header_folding = true;
+                match headers.last_mut() {
+                    None => self.m_invalid_header_folding = true,
+                    Some((_, value)) => {
+                        value.push(' ');
+                        value.push_str(line.trim());
+                    }
+                }
+                continue;
+            }
+            match line.split_once(':') {
+                None => self.m_invalid_part = true,
+                Some((name, value)) => {
+                    headers.push((name.trim().to_string(), value.trim().to_string()))
+                }
+            }
+        }
+
+        let disposition = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("Content-Disposition"))
+            .map(|(_, value)| value.clone());
+        let (name, filename) = match disposition {
+            None => {
+                self.m_invalid_part = true;
+                return;
+            }
+            Some(d) => self.parse_content_disposition(&d),
+        };
+        let name = match name {
+            None => {
+                self.m_invalid_part = true;
+                return;
+            }
+            Some(n) => n,
+        };
+
+        self.m_parts.push(MultipartPart {
+            m_name: name,
+            m_filename: filename,
+            m_headers: headers,
+            m_value: &data[value_start..],
+            m_value_offset: start + value_start,
+        });
+    }
+
+    /// form-data; name="field"; filename="file.txt"
+    fn parse_content_disposition(&mut self, value: &str) -> (Option<String>, Option<String>) {
+        let mut params = value.split(';');
+        match params.next() {
+            Some(kind) if kind.trim().eq_ignore_ascii_case("form-data") => {}
+            Some(kind) if kind.trim().to_lowercase().starts_with("form-data") => {
+                self.m_missing_semicolon = true;
+            }
+            _ => self.m_invalid_part = true,
+        }
+
+        let mut name = None;
+        let mut filename = None;
+        for param in params {
+            let (key, val) = match param.split_once('=') {
+                None => {
+                    self.m_invalid_part = true;
+                    continue;
+                }
+                Some(kv) => kv,
+            };
+            let val = val.trim();
+            let val = if val.len() >= 2 && val.starts_with('"') && val.ends_with('"') {
+                &val[1..val.len() - 1]
+            } else {
+                if val.starts_with('\'') || val.contains('"') {
+                    self.m_invalid_quoting = true;
+                }
+                val
+            };
+            // a second equal sign usually means two parameters with the
+            // separator missing
+            if val.contains("=\"") {
+                self.m_missing_semicolon = true;
+            }
+
+            match key.trim().to_lowercase().as_str() {
+                "name" => name = Some(val.to_string()),
+                "filename" => filename = Some(val.to_string()),
+                _ => {}
+            }
+        }
+
+        (name, filename)
+    }
+
+    pub fn strict_error(&self) -> bool {
+        self.m_boundary_quoted
+            || self.m_boundary_whitespace
+            || (self.m_crlf_line && self.m_lf_line)
+            || self.m_data_before
+            || self.m_data_after
+            || self.m_header_folding
+            || self.m_invalid_header_folding
+            || self.m_invalid_part
+            || self.m_invalid_quoting
+            || self.m_missing_semicolon
+            || self.m_unmatched_boundary
+    }
+
+    /// Populate the transaction with the parts: ARGS/ARGS_POST for the
+    /// fields, FILES* for the files, and the MULTIPART_* flags.
+    ///
+    /// Files are written to SecUploadDir, if any, and removed when the
+    /// transaction is over according to SecUploadKeepFiles.
+    pub fn process(&self, t: &mut Transaction) -> Result<(), String> {
+        let mut combined_size = 0;
+        let mut result = Ok(());
+
+        // SecBodyCharsetDecoding: text fields are decoded from the charset
+        // of their part, or else of the request
+        let request_charset = t
+            .m_variables
+            .m_variable_request_headers
+            .resolve_first("Content-Type")
+            .and_then(charset_from_content_type)
+            .map(str::to_string);
+
+        for part in self.m_parts.iter() {
+            let name = part.m_name.as_str();
+            let value = String::from_utf8_lossy(part.m_value);
+            let offset = part.m_value_offset;
+
+            for (header_name, header_value) in part.m_headers.iter() {
+                t.m_variables.m_variable_multipart_part_headers.set(
+                    name,
+                    &format!("{}: {}", header_name, header_value),
+                    offset,
+                );
+            }
+            t.m_variables
+                .m_variable_multipart_name
+                .set(name, name, offset);
+
+            let filename = match &part.m_filename {
+                None => {
+                    let value = if t.m_rules.m_body_charset_decoding {
+                        let charset = part
+                            .m_headers
+                            .iter()
+                            .find(|(h, _)| h.eq_ignore_ascii_case("Content-Type"))
+                            .and_then(|(_, ct)| charset_from_content_type(ct))
+                            .or(request_charset.as_deref());
+                        Cow::Owned(decode_argument(part.m_value, charset))
+                    } else {
+                        value
+                    };
+                    t.m_variables
+                        .m_variable_args_post
+                        .borrow_mut()
+                        .set(name, &value, offset);
+                    t.m_variables
+                        .m_variable_args
+                        .borrow_mut()
+                        .set(name, &value, offset);
+                    continue;
+                }
+                Some(filename) => filename,
+            };
+
+            combined_size += part.m_value.len();
+            let vars = &mut t.m_variables;
+            vars.m_variable_files.set(name, filename, offset);
+            vars.m_variable_files_names.set(name, name, offset);
+            vars.m_variable_multipart_file_name
+                .set(name, filename, offset);
+            vars.m_variable_files_sizes
+                .set(name, &part.m_value.len().to_string(), offset);
+            vars.m_variable_files_tmp_content.set(name, &value, offset);
+
+            if t.m_rules.m_upload_directory.is_empty() {
+                continue;
+            }
+            match write_upload_file(t, part.m_value) {
+                Ok(path) => {
+                    t.m_variables
+                        .m_variable_files_tmp_names
+                        .set(name, &path, offset);
+                    t.m_upload_tmp_files.push(path);
+                }
+                Err(err) => result = Err(err),
+            }
+        }
+
+        let flag = |b: bool| if b { "1" } else { "0" };
+        let vars = &mut t.m_variables;
+        vars.m_variable_files_combined_size
+            .set(&combined_size.to_string(), 0);
+        vars.m_variable_multipart_boundary_quoted
+            .set(flag(self.m_boundary_quoted), 0);
+        vars.m_variable_multipart_boundary_white_space
+            .set(flag(self.m_boundary_whitespace), 0);
+        vars.m_variable_multipart_crlf_lf_lines
+            .set(flag(self.m_crlf_line && self.m_lf_line), 0);
+        vars.m_variable_multipart_data_before
+            .set(flag(self.m_data_before), 0);
+        vars.m_variable_multipart_data_after
+            .set(flag(self.m_data_after), 0);
+        vars.m_variable_multipart_header_folding
+            .set(flag(self.m_header_folding), 0);
+        vars.m_variable_multipart_invalid_header_folding
+            .set(flag(self.m_invalid_header_folding), 0);
+        vars.m_variable_multipart_invalid_part
+            .set(flag(self.m_invalid_part), 0);
+        vars.m_variable_multipart_invalid_quoting
+            .set(flag(self.m_invalid_quoting), 0);
+        vars.m_variable_multipart_lf_line
+            .set(flag(self.m_lf_line), 0);
+        vars.m_variable_multipart_missing_semicolon
+            .set(flag(self.m_missing_semicolon), 0);
+        vars.m_variable_multipart_unmatched_boundary
+            .set(flag(self.m_unmatched_boundary), 0);
+        vars.m_variable_multipart_strict_error
+            .set(flag(self.strict_error()), 0);
+
+        result
+    }
+}
+
+/// Write an uploaded file to SecUploadDir, as
+/// `<dir>/<YYYYmmdd-HHMMSS>-<unique id>-file-XXXXXX`, with SecUploadFileMode
+/// permissions. Returns the path of the file.
+fn write_upload_file(t: &Transaction, data: &[u8]) -> Result<String, String> {
+    let prefix = format!(
+        "{}/{}-{}-file-",
+        t.m_rules.m_upload_directory.trim_end_matches('/'),
+        UtcTime::now().ymd_hms(),
+        t.m_id
+    );
+
+    for _ in 0..16 {
+        let suffix: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(6)
+            .map(char::from)
+            .collect();
+        let path = format!("{}{}", prefix, suffix);
+
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("Failed to create file {}: {}", path, e)),
+        };
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let perms = fs::Permissions::from_mode(t.m_rules.m_upload_file_mode);
+            if let Err(e) = fs::set_permissions(&path, perms) {
+                _ = fs::remove_file(&path);
+                return Err(format!("Failed to set permissions on {}: {}", path, e));
+            }
+        }
+
+        return match file.write_all(data) {
+            Ok(_) => Ok(path),
+            Err(e) => {
+                _ = fs::remove_file(&path);
+                Err(format!("Failed to write file {}: {}", path, e))
+            }
+        };
+    }
+
+    Err(format!(
+        "Failed to create a unique file name for {}",
+        prefix
+    ))
+}
@@ -12,8 +12,265 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::cell::Cell;
+
+use crate::{
+    transaction::Transaction,
+    utils::string::{
+        from_hex_if_needed, limit_to, to_hex_if_needed, to_json_object, to_json_string,
+    },
+};
+
 pub enum LogMessageInfo {
     ErrorLogTailLogMessageInfo = 2,
     ClientLogMessageInfo = 4,
 }
-pub struct RuleMessage {}
+
+#[derive(Debug, Clone, Default)]
+/// Message produced when a rule matched, written to the server log and to
+/// part H, or the `messages` of the JSON format, of the audit log.
+pub struct RuleMessage {
+    pub m_accuracy: i32,
+    pub m_client_ip_address: String,
+    pub m_data: String,
+    /// Unique id of the transaction.
+    pub m_id: String,
+    pub m_is_disruptive: bool,
+    pub m_match: String,
+    pub m_maturity: i32,
+    pub m_message: String,
+    /// Cleared by the `auditlog` action, set by `noauditlog`.
+    pub m_no_audit_log: Cell<bool>,
+    pub m_phase: i32,
+    pub m_reference: String,
+    pub m_rev: String,
+    pub m_rule_file: String,
+    pub m_rule_id: i64,
+    pub m_rule_line: i32,
+    pub m_save_message: Cell<bool>,
+    pub m_server_ip_address: String,
+    pub m_severity: i32,
+    pub m_uri_no_query_string_decoded: String,
+    pub m_ver: String,
+    pub m_tags: Vec<String>,
+}
+
+impl RuleMessage {
+    pub fn new(transaction: &Transaction) -> RuleMessage {
+        RuleMessage {
+            m_accuracy: 0,
+            m_client_ip_address: transaction.m_client_ip_address.clone(),
+            m_data: String::new(),
+            m_id: transaction.m_id.clone(),
+            m_is_disruptive: false,
+            m_match: String::new(),
+            m_maturity: 0,
+            m_message: String::new(),
+            m_no_audit_log: Cell::new(false),
+            m_phase: 0,
+            m_reference: String::new(),
+            m_rev: String::new(),
+            m_rule_file: String::new(),
+            m_rule_id: 0,
+            m_rule_line: 0,
+            m_save_message: Cell::new(true),
+            m_server_ip_address: transaction.m_server_ip_address.clone(),
+            m_severity: 0,
+            m_uri_no_query_string_decoded: transaction
+                .m_variables
+                .m_variable_request_filename
+                .evaluate()
+                .to_string(),
+            m_ver: String::new(),
+            m_tags: Vec::new(),
+        }
+    }
+
+    fn details(&self) -> String {
+        let mut msg = String::with_capacity(512);
+        msg.push_str(&format!(" [file \"{}\"]", self.m_rule_file));
+        msg.push_str(&format!(" [line \"{}\"]", self.m_rule_line));
+        msg.push_str(&format!(" [id \"{}\"]", self.m_rule_id));
+        msg.push_str(&format!(
+            " [rev \"{}\"]",
+            to_hex_if_needed(self.m_rev.as_bytes(), true)
+        ));
+        msg.push_str(&format!(" [msg \"{}\"]", self.m_message));
+        msg.push_str(&format!(
+            " [data \"{}\"]",
+            to_hex_if_needed(limit_to(200, &self.m_data).as_bytes(), true)
+        ));
+        msg.push_str(&format!(" [severity \"{}\"]", self.m_severity));
+        msg.push_str(&format!(
+            " [ver \"{}\"]",
+            to_hex_if_needed(self.m_ver.as_bytes(), true)
+        ));
+        msg.push_str(&format!(" [maturity \"{}\"]", self.m_maturity));
+        msg.push_str(&format!(" [accuracy \"{}\"]", self.m_accuracy));
+        for tag in self.m_tags.iter() {
+            msg.push_str(&format!(
+                " [tag \"{}\"]",
+                to_hex_if_needed(tag.as_bytes(), true)
+            ));
+        }
+        msg.push_str(&format!(" [hostname \"{}\"]", self.m_server_ip_address));
+        msg.push_str(&format!(
+            " [uri \"{}\"]",
+            limit_to(200, &self.m_uri_no_query_string_decoded)
+        ));
+        msg.push_str(&format!(" [unique_id \"{}\"]", self.m_id));
+        msg.push_str(&format!(" [ref \"{}\"]", limit_to(200, &self.m_reference)));
+
+        msg
+    }
+
+    fn error_log_tail(&self) -> String {
+        format!(
+            "[hostname \"{}\"] [uri \"{}\"] [unique_id \"{}\"]",
+            self.m_server_ip_address,
+            limit_to(200, &self.m_uri_no_query_string_decoded),
+            self.m_id
+        )
+    }
+
+    /// "Access denied with code 403 (phase 2). ", `code` -1 leaves a `%d`
+    /// placeholder for the connector.
+    pub fn disruptive_error_message(&self, code: i32) -> String {
+        let code = if code == -1 {
+            "%d".to_string()
+        } else {
+            code.to_string()
+        };
+        format!(
+            "Access denied with code {} (phase {}). ",
+            code, self.m_phase
+        )
+    }
+
+    /// The message as written to the server log, `props` is a combination of
+    /// `LogMessageInfo`.
+    pub fn log(&self, props: i32, code: i32) -> String {
+        let mut msg = String::with_capacity(2048);
+        if props & LogMessageInfo::ClientLogMessageInfo as i32 != 0 {
+            msg.push_str(&format!("[client {}] ", self.m_client_ip_address));
+        }
+        if self.m_is_disruptive {
+            msg.push_str("ModSecurity: ");
+            msg.push_str(&self.disruptive_error_message(code));
+        } else {
+            msg.push_str("ModSecurity: Warning. ");
+        }
+        msg.push_str(&self.m_match);
+        msg.push_str(&self.details());
+        if props & LogMessageInfo::ErrorLogTailLogMessageInfo as i32 != 0 {
+            msg.push(' ');
+            msg.push_str(&self.error_log_tail());
+        }
+
+        to_hex_if_needed(msg.as_bytes(), false)
+    }
+    /// The message as an entry of `messages` in the JSON audit log.
+    pub fn to_json(&self) -> String {
+        let tags = self
+            .m_tags
+            .iter()
+            .map(|tag| to_json_string(tag))
+            .collect::<Vec<String>>()
+            .join(",");
+        let details = to_json_object(&[
+            ("match", to_json_string(&self.m_match)),
+            ("reference", to_json_string(&self.m_reference)),
+            ("ruleId", to_json_string(&self.m_rule_id.to_string())),
+            ("file", to_json_string(&self.m_rule_file)),
+            ("lineNumber", to_json_string(&self.m_rule_line.to_string())),
+            ("data", to_json_string(&self.m_data)),
+            ("severity", to_json_string(&self.m_severity.to_string())),
+            ("ver", to_json_string(&self.m_ver)),
+            ("rev", to_json_string(&self.m_rev)),
+            ("tags", format!("[{}]", tags)),
+            ("maturity", to_json_string(&self.m_maturity.to_string())),
+            ("accuracy", to_json_string(&self.m_accuracy.to_string())),
+        ]);
+
+        to_json_object(&[
+            ("message", to_json_string(&self.m_message)),
+            ("details", details),
+        ])
+    }
+    /// Parse a message written by `log`, e.g. from part H of the audit log.
+    ///
+    /// The match is the text before the first `[file "..."]`; fields that
+    /// were truncated stay truncated.
+    pub fn from_log(line: &str) -> Result<RuleMessage, String> {
+        let mut rm = RuleMessage::default();
+        rm.m_save_message.set(true);
+
+        let mut rest = line.trim_end();
+        if let Some(client) = rest.strip_prefix("[client ") {
+            let end = client
+                .find("] ")
+                .ok_or_else(|| format!("Invalid rule message: {}", line))?;
+            rm.m_client_ip_address = client[..end].to_string();
+            rest = &client[end + 2..];
+        }
+        rest = rest
+            .strip_prefix("ModSecurity: ")
+            .ok_or_else(|| format!("Invalid rule message: {}", line))?;
+        if let Some(warning) = rest.strip_prefix("Warning. ") {
+            rest = warning;
+        } else if let Some(denied) = rest.strip_prefix("Access denied with code ") {
+            let end = denied
+                .find("). ")
+                .ok_or_else(|| format!("Invalid rule message: {}", line))?;
+            rm.m_is_disruptive = true;
+            rm.m_phase = denied[..end]
+                .rsplit("(phase ")
+                .next()
+                .and_then(|phase| phase.parse().ok())
+                .unwrap_or_default();
+            rest = &denied[end + 3..];
+        }
+
+        let details = rest.find(" [file \"").unwrap_or(rest.len());
+        rm.m_match = unescape(&rest[..details]);
+        rest = &rest[details..];
+
+        let parse_i32 = |value: &str| value.parse::<i32>().unwrap_or_default();
+        while let Some(field) = rest.trim_start().strip_prefix('[') {
+            let (key, value) = match field.find(" \"") {
+                Some(i) => (&field[..i], &field[i + 2..]),
+                None => break,
+            };
+            let end = match value.find("\"]") {
+                Some(end) => end,
+                None => break,
+            };
+            let (value, raw) = (unescape(&value[..end]), &value[..end]);
+            match key {
+                "file" => rm.m_rule_file = value,
+                "line" => rm.m_rule_line = parse_i32(raw),
+                "id" => rm.m_rule_id = raw.parse().unwrap_or_default(),
+                "rev" => rm.m_rev = value,
+                "msg" => rm.m_message = value,
+                "data" => rm.m_data = value,
+                "severity" => rm.m_severity = parse_i32(raw),
+                "ver" => rm.m_ver = value,
+                "maturity" => rm.m_maturity = parse_i32(raw),
+                "accuracy" => rm.m_accuracy = parse_i32(raw),
+                "tag" => rm.m_tags.push(value),
+                "hostname" => rm.m_server_ip_address = value,
+                "uri" => rm.m_uri_no_query_string_decoded = value,
+                "unique_id" => rm.m_id = value,
+                "ref" => rm.m_reference = value,
+                _ => {}
+            }
+            rest = &field[key.len() + 2 + end + 2..];
+        }
+
+        Ok(rm)
+    }
+}
+
+fn unescape(s: &str) -> String {
+    String::from_utf8_lossy(&from_hex_if_needed(s)).into_owned()
+}
@@ -0,0 +1,331 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{borrow::Cow, io::Write, time::UNIX_EPOCH};
+
+use crate::{
+    audit_log::AuditLogParts,
+    modsecurity::modsecurity::unique_id,
+    utils::{
+        string::{log_escape, to_hex_if_needed, to_json_object, to_json_string},
+        time::UtcTime,
+    },
+};
+
+use super::Transaction;
+
+fn has_part(parts: i32, part: AuditLogParts) -> bool {
+    parts & part as i32 != 0
+}
+
+impl Transaction {
+    /// Cut `body` to the body limit of the audit log, the marker
+    /// `[...truncated <n> bytes]` replacing the `n` bytes left out.
+    pub fn audit_log_body<'a>(&self, body: Cow<'a, [u8]>) -> Cow<'a, [u8]> {
+        let limit = self.m_rules.m_audit_log.m_body_limit;
+        if limit == 0 || body.len() <= limit {
+            return body;
+        }
+
+        let mut truncated = body[..limit].to_vec();
+        _ = write!(truncated, "[...truncated {} bytes]", body.len() - limit);
+        Cow::Owned(truncated)
+    }
+
+    /// Timestamp of part A, e.g. `[31/Dec/2023:23:59:59 +0000]`.
+    pub fn audit_log_timestamp(&self) -> String {
+        let secs = self
+            .m_timestamp
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let t = UtcTime::from_unix(secs);
+        format!(
+            "[{:02}/{}/{}:{:02}:{:02}:{:02} +0000]",
+            t.day,
+            t.month_abbr(),
+            t.year,
+            t.hour,
+            t.minute,
+            t.second
+        )
+    }
+
+    /// Serialize the transaction in the native (serial) audit log format.
+    ///
+    /// Every part starts with `--<trailer>-<part>--`; the request body is
+    /// written as received, the response body with the non printable bytes
+    /// escaped, both cut to the body limit. Values of the sanitise actions
+    /// are masked.
+    pub fn to_old_audit_log_format(&self, parts: i32, trailer: &str) -> Vec<u8> {
+        let mut audit_log: Vec<u8> =
+            Vec::with_capacity(2048 + self.m_request_body.len() + self.m_response_body.len());
+        let section = |audit_log: &mut Vec<u8>, part: char| {
+            _ = writeln!(audit_log, "--{}-{}--", trailer, part);
+        };
+
+        section(&mut audit_log, 'A');
+        _ = writeln!(
+            audit_log,
+            "{} {} {} {} {} {}",
+            self.audit_log_timestamp(),
+            self.m_id,
+            self.m_client_ip_address,
+            self.m_client_port,
+            self.m_server_ip_address,
+            self.m_server_port
+        );
+
+        if has_part(parts, AuditLogParts::BAuditLogPart) {
+            let (uri, request_headers) = self.sanitised_request_head();
+            section(&mut audit_log, 'B');
+            _ = writeln!(
+                audit_log,
+                "{} {} HTTP/{}",
+                self.m_variables.m_variable_request_method.evaluate(),
+                uri,
+                self.m_http_version
+            );
+            for (key, value) in request_headers.iter() {
+                _ = writeln!(audit_log, "{}: {}", key, value);
+            }
+            _ = writeln!(audit_log);
+        }
+
+        if has_part(parts, AuditLogParts::CAuditLogPart) && !self.m_request_body.is_empty() {
+            section(&mut audit_log, 'C');
+            audit_log.extend_from_slice(&self.audit_log_body(self.sanitised_request_body()));
+            _ = writeln!(audit_log);
+            _ = writeln!(audit_log);
+        }
+
+        if has_part(parts, AuditLogParts::DAuditLogPart) {
+            section(&mut audit_log, 'D');
+            _ = writeln!(audit_log);
+        }
+
+        if has_part(parts, AuditLogParts::EAuditLogPart) && !self.m_response_body.is_empty() {
+            section(&mut audit_log, 'E');
+            _ = writeln!(
+                audit_log,
+                "{}",
+                to_hex_if_needed(
+                    &self.audit_log_body(Cow::Borrowed(self.m_response_body.as_slice())),
+                    false
+                )
+            );
+            _ = writeln!(audit_log);
+        }
+
+        if has_part(parts, AuditLogParts::FAuditLogPart) {
+            section(&mut audit_log, 'F');
+            _ = writeln!(
+                audit_log,
+                "HTTP/{} {}",
+                self.m_http_version, self.m_http_code_returned
+            );
+            for (key, value) in self.sanitised_response_headers().iter() {
+                _ = writeln!(audit_log, "{}: {}", key, value);
+            }
+        }
+        _ = writeln!(audit_log);
+
+        if has_part(parts, AuditLogParts::GAuditLogPart) {
+            section(&mut audit_log, 'G');
+            _ = writeln!(audit_log);
+        }
+
+        if has_part(parts, AuditLogParts::HAuditLogPart) {
+            section(&mut audit_log, 'H');
+            for rm in self.m_rules_messages.iter() {
+                _ = writeln!(audit_log, "{}", rm.log(0, self.m_http_code_returned));
+            }
+            _ = writeln!(audit_log);
+        }
+
+        if has_part(parts, AuditLogParts::IAuditLogPart) {
+            section(&mut audit_log, 'I');
+            _ = writeln!(audit_log);
+        }
+
+        if has_part(parts, AuditLogParts::JAuditLogPart) {
+            section(&mut audit_log, 'J');
+            _ = writeln!(audit_log);
+        }
+
+        if has_part(parts, AuditLogParts::KAuditLogPart) {
+            section(&mut audit_log, 'K');
+            _ = writeln!(audit_log);
+        }
+
+        section(&mut audit_log, 'Z');
+        _ = writeln!(audit_log);
+
+        audit_log
+    }
+
+    /// Serialize the transaction as a single line JSON object, with the
+    /// layout of libmodsecurity:
+    ///
+    /// `{"transaction":{"client_ip":..,"request":{..},"response":{..},"producer":{..},"messages":[..]}}`
+    ///
+    /// Part C adds the request body, E the response body, B and F the
+    /// headers and H the producer and the messages. The bodies are cut to
+    /// the body limit, values of the sanitise actions are masked.
+    pub fn to_json(&self, parts: i32) -> String {
+        let headers = |headers: &Vec<(String, String)>| {
+            let fields = headers
+                .iter()
+                .map(|(key, value)| (key.as_str(), to_json_string(value)))
+                .collect::<Vec<(&str, String)>>();
+            to_json_object(&fields)
+        };
+
+        let secs = self
+            .m_timestamp
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let mut transaction = vec![
+            ("client_ip", to_json_string(&self.m_client_ip_address)),
+            (
+                "time_stamp",
+                to_json_string(&UtcTime::from_unix(secs).asctime()),
+            ),
+            ("server_id", to_json_string(unique_id())),
+            ("client_port", self.m_client_port.to_string()),
+            ("host_ip", to_json_string(&self.m_server_ip_address)),
+            ("host_port", self.m_server_port.to_string()),
+            ("unique_id", to_json_string(&self.m_id)),
+        ];
+
+        // the HTTP version is a number, e.g. 1.1
+        let http_version = serde_json::from_str::<serde_json::Number>(&self.m_http_version)
+            .map(|n| n.to_string())
+            .unwrap_or_else(|_| to_json_string(&self.m_http_version));
+        let (uri, request_headers) = self.sanitised_request_head();
+        let mut request = vec![
+            (
+                "method",
+                to_json_string(self.m_variables.m_variable_request_method.evaluate()),
+            ),
+            ("http_version", http_version),
+            ("uri", to_json_string(&uri)),
+        ];
+        if has_part(parts, AuditLogParts::CAuditLogPart) {
+            request.push((
+                "body",
+                to_json_string(&String::from_utf8_lossy(
+                    &self.audit_log_body(self.sanitised_request_body()),
+                )),
+            ));
+        }
+        if has_part(parts, AuditLogParts::BAuditLogPart) {
+            request.push(("headers", headers(&request_headers)));
+        }
+        transaction.push(("request", to_json_object(&request)));
+
+        let mut response = Vec::new();
+        if has_part(parts, AuditLogParts::EAuditLogPart) {
+            response.push((
+                "body",
+                to_json_string(&String::from_utf8_lossy(
+                    &self.audit_log_body(Cow::Borrowed(self.m_response_body.as_slice())),
+                )),
+            ));
+        }
+        response.push(("http_code", self.m_http_code_returned.to_string()));
+        if has_part(parts, AuditLogParts::FAuditLogPart) {
+            response.push(("headers", headers(&self.sanitised_response_headers())));
+        }
+        transaction.push(("response", to_json_object(&response)));
+
+        if has_part(parts, AuditLogParts::HAuditLogPart) {
+            let components = self
+                .m_rules
+                .m_components
+                .iter()
+                .map(|c| to_json_string(c))
+                .collect::<Vec<String>>()
+                .join(",");
+            let producer = to_json_object(&[
+                ("modsecurity", to_json_string(&self.m_whoami)),
+                ("connector", to_json_string(&self.m_connector)),
+                (
+                    "secrules_engine",
+                    to_json_string(self.m_rules.m_sec_rule_engine.as_str()),
+                ),
+                ("components", format!("[{}]", components)),
+            ]);
+            transaction.push(("producer", producer));
+
+            let messages = self
+                .m_rules_messages
+                .iter()
+                .map(|rm| rm.to_json())
+                .collect::<Vec<String>>()
+                .join(",");
+            transaction.push(("messages", format!("[{}]", messages)));
+        }
+
+        let mut log = to_json_object(&[("transaction", to_json_object(&transaction))]);
+        log.push('\n');
+        log
+    }
+
+    /// Index line of a concurrent audit log entry, in the format expected
+    /// by the log collectors:
+    ///
+    /// `<host> <client ip> - - [date] "<request line>" <status> <bytes sent> "<referer>" "<user agent>" <unique id>
+    /// "<session id>" <file name> <offset> <size> md5:<hash>`
+    ///
+    /// The fields coming from the request are escaped, see `log_escape`.
+    pub fn to_old_audit_log_format_index(&self, filename: &str, size: usize, md5: &str) -> String {
+        let (uri, request_headers) = self.sanitised_request_head();
+        let header = |name: &str| {
+            request_headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+                .filter(|v| !v.is_empty())
+                .map_or("-".to_string(), log_escape)
+        };
+        let dash_if_empty = |s: &str| {
+            if s.is_empty() {
+                "-".to_string()
+            } else {
+                log_escape(s)
+            }
+        };
+
+        format!(
+            "{} {} - - {} \"{} {} HTTP/{}\" {} {} \"{}\" \"{}\" {} \"{}\" {} 0 {} md5:{}\n",
+            header("Host"),
+            dash_if_empty(&self.m_client_ip_address),
+            self.audit_log_timestamp(),
+            dash_if_empty(self.m_variables.m_variable_request_method.evaluate()),
+            log_escape(&uri),
+            self.m_http_version,
+            self.m_http_code_returned,
+            self.m_response_body.len(),
+            header("Referer"),
+            header("User-Agent"),
+            self.m_id,
+            dash_if_empty(self.m_variables.m_variable_session_id.borrow().evaluate()),
+            filename,
+            size,
+            md5
+        )
+    }
+}
@@ -0,0 +1,95 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::ms_dbg;
+
+use super::Transaction;
+
+impl Transaction {
+    /// initcol, setsid and setuid, bind the persistent collection
+    /// `collection` to the record `key`, see `Collections::init_collection`.
+    pub fn init_collection(&self, collection: &str, key: &str) -> bool {
+        let timeout = self.m_rules.m_collection_timeout;
+        let result = self
+            .m_collections
+            .borrow_mut()
+            .init_collection(collection, key, timeout);
+        match result {
+            Ok(is_new) => {
+                ms_dbg!(
+                    self,
+                    5,
+                    "Collection `{}' initialized with value: {}{}",
+                    collection.to_lowercase(),
+                    key,
+                    if is_new { ", a new record" } else { "" }
+                );
+                true
+            }
+            Err(err) => {
+                ms_dbg!(
+                    self,
+                    4,
+                    "Can not initialise collection {}: {}",
+                    collection,
+                    err
+                );
+                false
+            }
+        }
+    }
+
+    /// setsid, SESSIONID and the record of SESSION.
+    pub fn set_session_id(&self, session_id: &str) {
+        if self.init_collection("SESSION", session_id) {
+            let offset = self.m_variables.m_variable_offset as usize;
+            self.m_variables
+                .m_variable_session_id
+                .borrow_mut()
+                .set(session_id, offset);
+        }
+    }
+
+    /// setuid, USERID and the record of USER.
+    pub fn set_user_id(&self, user_id: &str) {
+        if self.init_collection("USER", user_id) {
+            let offset = self.m_variables.m_variable_offset as usize;
+            self.m_variables
+                .m_variable_user_id
+                .borrow_mut()
+                .set(user_id, offset);
+        }
+    }
+
+    /// expirevar, expire `key` of the collection `collection`, TX or the
+    /// record of the transaction in a persistent collection, in
+    /// `expiry_seconds`.
+    pub fn expire_var(&self, collection: &str, key: &str, expiry_seconds: i64) {
+        let result = self
+            .m_collections
+            .borrow_mut()
+            .set_expiry(collection, key, expiry_seconds);
+        match result {
+            Ok(()) => ms_dbg!(
+                self,
+                9,
+                "Variable {}.{} expires in {} seconds.",
+                collection,
+                key,
+                expiry_seconds
+            ),
+            Err(err) => ms_dbg!(self, 4, "Can not expire {}.{}: {}", collection, key, err),
+        }
+    }
+}
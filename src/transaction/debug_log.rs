@@ -0,0 +1,31 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::Transaction;
+
+impl Transaction {
+    /// Write `msg` to the debug log, prefixed with the unique id and the
+    /// URI of the transaction; see `ms_dbg!` to skip formatting messages
+    /// of disabled levels.
+    pub fn debug(&self, level: i32, msg: &str) {
+        self.m_rules
+            .m_debug_log
+            .write_transaction(level, &self.m_id, &self.m_uri, msg);
+    }
+
+    /// Same as `debug`, for a message of the rule `rule_id`.
+    pub fn debug_rule(&self, level: i32, rule_id: i64, msg: &str) {
+        self.debug(level, &format!("(Rule: {}) {}", rule_id, msg));
+    }
+}
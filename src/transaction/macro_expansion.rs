@@ -0,0 +1,119 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::collection::Collection;
+
+use super::Transaction;
+
+impl Transaction {
+    /// Expand the macros of an action argument, e.g. `%{REMOTE_ADDR}`,
+    /// `%{ARGS.user}` or `%{IP.score}`. Macros of unknown variables, or of
+    /// variables without a value, expand to nothing.
+    pub fn expand_macros(&self, text: &str) -> String {
+        let mut expanded = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("%{") {
+            let Some(len) = rest[start..].find('}') else {
+                break;
+            };
+            expanded.push_str(&rest[..start]);
+            let name = &rest[start + 2..start + len];
+            expanded.push_str(&self.resolve_macro(name).unwrap_or_default());
+            rest = &rest[start + len + 1..];
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+
+    /// First value of the variable `name`, `COLLECTION.key` for a variable
+    /// of a collection.
+    fn resolve_macro(&self, name: &str) -> Option<String> {
+        let vars = &self.m_variables;
+        let (collection, key) = match name.split_once(['.', ':']) {
+            Some((collection, key)) => (collection, Some(key)),
+            None => (name, None),
+        };
+        let scalar = match (collection.to_uppercase().as_str(), key) {
+            ("REMOTE_ADDR", None) => &vars.m_variable_remote_addr,
+            ("REMOTE_PORT", None) => &vars.m_variable_remote_port,
+            ("SERVER_ADDR", None) => &vars.m_variable_server_addr,
+            ("SERVER_NAME", None) => &vars.m_variable_server_name,
+            ("SERVER_PORT", None) => &vars.m_variable_server_port,
+            ("REQUEST_METHOD", None) => &vars.m_variable_request_method,
+            ("REQUEST_URI", None) => &vars.m_variable_request_uri,
+            ("REQUEST_FILENAME", None) => &vars.m_variable_request_filename,
+            ("QUERY_STRING", None) => &vars.m_variable_query_string,
+            ("UNIQUE_ID", None) => &vars.m_variable_unique_id,
+            ("SESSIONID", None) => {
+                return Some(vars.m_variable_session_id.borrow().resolve_first())
+            }
+            ("USERID", None) => return Some(vars.m_variable_user_id.borrow().resolve_first()),
+            ("REQUEST_LINE", None) => return Some(self.resolve_request_line().to_string()),
+            ("FULL_REQUEST", None) => return Some(self.resolve_full_request().to_string()),
+            ("FULL_REQUEST_LENGTH", None) => {
+                return Some(self.resolve_full_request_length().to_string())
+            }
+            ("ARGS", Some(key)) => {
+                return vars
+                    .m_variable_args
+                    .borrow()
+                    .resolve_first(key)
+                    .map(str::to_string)
+            }
+            ("ARGS_GET", Some(key)) => {
+                return vars
+                    .m_variable_args_get
+                    .borrow()
+                    .resolve_first(key)
+                    .map(str::to_string)
+            }
+            ("ARGS_POST", Some(key)) => {
+                return vars
+                    .m_variable_args_post
+                    .borrow()
+                    .resolve_first(key)
+                    .map(str::to_string)
+            }
+            ("REQUEST_HEADERS", Some(key)) => {
+                return vars
+                    .m_variable_request_headers
+                    .resolve_first(key)
+                    .map(str::to_string)
+            }
+            ("REQUEST_COOKIES", Some(key)) => {
+                return vars
+                    .m_variable_request_cookies
+                    .resolve_first(key)
+                    .map(str::to_string)
+            }
+            ("RESPONSE_HEADERS", Some(key)) => {
+                return vars
+                    .m_variable_response_headers
+                    .resolve_first(key)
+                    .map(str::to_string)
+            }
+            ("TX", Some(key)) => {
+                let collections = self.m_collections.borrow();
+                return collections.m_tx_collection.resolve_first(key);
+            }
+            (collection, Some(key)) => {
+                let collections = self.m_collections.borrow();
+                let (shared, compartment) = collections.persistent(collection).ok()?;
+                return shared.resolve_first(&format!("{compartment}::{key}"));
+            }
+            _ => return None,
+        };
+        Some(scalar.resolve_first())
+    }
+}
@@ -12,6 +12,184 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod audit_log;
+pub mod collections;
+pub mod debug_log;
+pub mod macro_expansion;
+pub mod sanitise;
+pub mod trace;
 pub mod transaction;
 
+pub use sanitise::*;
+pub use trace::*;
 pub use transaction::*;
+
+#[test]
+fn test_full_request() {
+    use crate::{
+        actions::{action::Action, set_uid::SetUID},
+        modsecurity::RulesSetProperties,
+        rules::RuleWithActions,
+    };
+    use std::sync::Arc;
+
+    let mut t = Transaction::new(Arc::new(RulesSetProperties::new()));
+    t.process_uri("/index.php?a=1", "POST", "1.1");
+    t.add_request_header("Host", "example.com");
+    t.append_request_body(b"b=2");
+
+    assert_eq!(&*t.resolve_request_line(), "POST /index.php?a=1 HTTP/1.1");
+    assert_eq!(
+        &*t.resolve_full_request(),
+        "POST /index.php?a=1 HTTP/1.1\nHost: example.com\n\nb=2"
+    );
+    assert_eq!(&*t.resolve_full_request_length(), "51");
+
+    t.append_request_body(b"&c=3");
+    assert!(t.resolve_full_request().ends_with("b=2&c=3"));
+
+    // the length of the raw request, whatever the body bytes
+    t.append_request_body(b"&d=\xff");
+    assert!(t.resolve_full_request().ends_with("&d=\u{fffd}"));
+    assert_eq!(&*t.resolve_full_request_length(), "59");
+    let origin_length = |t: &Transaction| {
+        let full_request = t.m_variables.m_variable_full_request.borrow();
+        full_request.get_origin().unwrap().m_length
+    };
+    assert_eq!(origin_length(&t), 59);
+
+    // built when a rule resolves them
+    let ra = RuleWithActions {};
+    let mut t = Transaction::new(Arc::new(RulesSetProperties::new()));
+    t.process_uri("/login", "GET", "1.1");
+    SetUID::new("setuid:%{REQUEST_LINE}").evaluate_rule_actions(&ra, &t);
+    assert_eq!(
+        t.m_variables.m_variable_user_id.borrow().evaluate(),
+        "GET /login HTTP/1.1"
+    );
+    assert_eq!(t.expand_macros("%{FULL_REQUEST_LENGTH}"), "21");
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn test_tracing_spans() {
+    use crate::modsecurity::{enums::Phases, RulesSetProperties};
+    use std::{
+        fmt::Debug,
+        sync::{Arc, Mutex},
+    };
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Event, Metadata, Subscriber,
+    };
+
+    struct Span {
+        name: &'static str,
+        parent: Option<u64>,
+        fields: Vec<(String, String)>,
+    }
+    struct Fields<'a>(&'a mut Vec<(String, String)>);
+    impl Visit for Fields<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.push((field.name().to_string(), value.to_string()));
+        }
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0
+                .push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+    #[derive(Default)]
+    struct Spans {
+        spans: Mutex<Vec<Span>>,
+        entered: Mutex<Vec<u64>>,
+    }
+    struct Recorder(Arc<Spans>);
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+            let parent = match attrs.parent() {
+                Some(parent) => Some(parent.into_u64()),
+                None if attrs.is_contextual() => self.0.entered.lock().unwrap().last().copied(),
+                None => None,
+            };
+            let mut fields = Vec::new();
+            attrs.record(&mut Fields(&mut fields));
+            let mut spans = self.0.spans.lock().unwrap();
+            spans.push(Span {
+                name: attrs.metadata().name(),
+                parent,
+                fields,
+            });
+            Id::from_u64(spans.len() as u64)
+        }
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let mut spans = self.0.spans.lock().unwrap();
+            let span = &mut spans[span.into_u64() as usize - 1];
+            values.record(&mut Fields(&mut span.fields));
+        }
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+        fn event(&self, _event: &Event<'_>) {}
+        fn enter(&self, span: &Id) {
+            self.0.entered.lock().unwrap().push(span.into_u64());
+        }
+        fn exit(&self, _span: &Id) {
+            self.0.entered.lock().unwrap().pop();
+        }
+    }
+
+    let recorded = Arc::new(Spans::default());
+    tracing::subscriber::with_default(Recorder(recorded.clone()), || {
+        let mut t = Transaction::new_with_id(Arc::new(RulesSetProperties::new()), "unique-8");
+        t.process_connection("10.0.0.1", 12345, "10.0.0.2", 80);
+        t.process_uri("/?a=1", "GET", "1.1");
+        {
+            let _phase = t.trace_phase(Phases::RequestHeadersPhase);
+            let rule = t.trace_rule(942100, "@rx");
+            rule.record_match("ARGS:a");
+        }
+        t.process_response_headers(403, "HTTP 1.1");
+    });
+
+    let spans = recorded.spans.lock().unwrap();
+    let field = |span: &Span, name: &str| {
+        span.fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+            .unwrap()
+    };
+    let transaction = &spans[0];
+    assert_eq!(transaction.name, "modsecurity.transaction");
+    assert_eq!(field(transaction, "unique_id"), "unique-8");
+    assert_eq!(field(transaction, "client_ip"), "10.0.0.1");
+    assert_eq!(field(transaction, "uri"), "/?a=1");
+    assert_eq!(field(transaction, "http_code"), "403");
+
+    let phases = spans
+        .iter()
+        .filter(|span| span.name == "modsecurity.phase")
+        .map(|span| (field(span, "phase"), span.parent))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        phases,
+        ["connection", "uri", "request_headers", "response_headers"]
+            .iter()
+            .map(|phase| (phase.to_string(), Some(1)))
+            .collect::<Vec<_>>()
+    );
+
+    let rule = spans
+        .iter()
+        .find(|span| span.name == "modsecurity.rule")
+        .unwrap();
+    assert_eq!(
+        field(&spans[rule.parent.unwrap() as usize - 1], "phase"),
+        "request_headers"
+    );
+    assert_eq!(field(rule, "rule_id"), "942100");
+    assert_eq!(field(rule, "operator"), "@rx");
+    assert_eq!(field(rule, "matched_var"), "ARGS:a");
+}
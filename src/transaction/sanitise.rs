@@ -0,0 +1,200 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Cow;
+
+use crate::ms_dbg;
+
+use super::Transaction;
+
+/// What the sanitise actions asked to mask in the audit log of a
+/// transaction. Names are resolved when the audit log is written, so that
+/// `sanitiseArg` also covers the arguments of a body processed later.
+#[derive(Debug, Default)]
+pub struct Sanitise {
+    /// sanitiseArg, names of ARGS
+    pub m_args: Vec<String>,
+    /// sanitiseRequestHeader
+    pub m_request_headers: Vec<String>,
+    /// sanitiseResponseHeader
+    pub m_response_headers: Vec<String>,
+    /// sanitiseMatchedBytes, (offset, length) in the request line and
+    /// headers, see `Transaction::add_request_header`
+    pub m_request_ranges: Vec<(usize, usize)>,
+    /// sanitiseMatchedBytes, (offset, length) in the raw request body
+    pub m_request_body_ranges: Vec<(usize, usize)>,
+}
+
+/// Replace with `*` the bytes of `buf`, found at `base`, covered by `ranges`.
+fn mask(buf: &mut [u8], base: usize, ranges: &[(usize, usize)]) {
+    for &(offset, length) in ranges {
+        let start = offset.max(base);
+        let end = (offset + length).min(base + buf.len());
+        if start < end {
+            buf[start - base..end - base].fill(b'*');
+        }
+    }
+}
+
+impl Transaction {
+    pub fn sanitise_arg(&self, name: &str) {
+        self.m_sanitise.borrow_mut().m_args.push(name.to_string());
+    }
+
+    pub fn sanitise_request_header(&self, name: &str) {
+        self.m_sanitise
+            .borrow_mut()
+            .m_request_headers
+            .push(name.to_string());
+    }
+
+    pub fn sanitise_response_header(&self, name: &str) {
+        self.m_sanitise
+            .borrow_mut()
+            .m_response_headers
+            .push(name.to_string());
+    }
+
+    /// Mask the variable in MATCHED_VAR_NAME: by name for the arguments and
+    /// the headers, by its origin otherwise.
+    pub fn sanitise_matched(&self) {
+        let name = self.m_variables.m_variable_matched_var_name.evaluate();
+        match name.split_once(':') {
+            Some(("ARGS" | "ARGS_GET" | "ARGS_POST", key)) => self.sanitise_arg(key),
+            Some(("REQUEST_HEADERS", key)) => self.sanitise_request_header(key),
+            Some(("RESPONSE_HEADERS", key)) => self.sanitise_response_header(key),
+            _ => self.sanitise_matched_bytes(0, 0),
+        }
+    }
+
+    /// Mask the bytes of MATCHED_VAR, located by its origin, but the first
+    /// `keep_start` and the last `keep_end`. The whole value is masked when
+    /// it is not longer than what should be kept.
+    pub fn sanitise_matched_bytes(&self, keep_start: usize, keep_end: usize) {
+        let vars = &self.m_variables;
+        let (offset, length) = match vars.m_variable_matched_var.get_origin() {
+            Some(origin) if origin.m_length > 0 => (origin.m_offset, origin.m_length),
+            _ => return,
+        };
+        let range = if length > keep_start + keep_end {
+            (offset + keep_start, length - keep_start - keep_end)
+        } else {
+            (offset, length)
+        };
+
+        let name = vars.m_variable_matched_var_name.evaluate();
+        let (collection, key) = name.split_once(':').unwrap_or((name, ""));
+        let in_body = match collection {
+            "ARGS_POST" | "REQUEST_BODY" => true,
+            // ARGS has the arguments of both the query string and the body
+            "ARGS" => vars.m_variable_args_post.borrow().values(key).any(|v| {
+                v.get_origin()
+                    .iter()
+                    .any(|o| o.m_offset == offset && o.m_length == length)
+            }),
+            c if c.starts_with("RESPONSE_") => {
+                ms_dbg!(
+                    self,
+                    4,
+                    "sanitiseMatchedBytes: {} can not be sanitised",
+                    name
+                );
+                return;
+            }
+            _ => false,
+        };
+
+        let mut sanitise = self.m_sanitise.borrow_mut();
+        if in_body {
+            sanitise.m_request_body_ranges.push(range);
+        } else {
+            sanitise.m_request_ranges.push(range);
+        }
+    }
+
+    /// Raw URI and request headers with the sanitised values masked.
+    pub fn sanitised_request_head(&self) -> (String, Vec<(String, String)>) {
+        let sanitise = self.m_sanitise.borrow();
+        let vars = &self.m_variables;
+
+        let mut ranges = sanitise.m_request_ranges.clone();
+        let args_get = vars.m_variable_args_get.borrow();
+        let names = sanitise.m_args.iter().map(|name| args_get.values(name));
+        let headers = sanitise
+            .m_request_headers
+            .iter()
+            .map(|name| vars.m_variable_request_headers.values(name));
+        for value in names.chain(headers).flatten() {
+            ranges.extend(value.get_origin().iter().map(|o| (o.m_offset, o.m_length)));
+        }
+        if ranges.is_empty() {
+            return (self.m_uri.clone(), self.m_request_headers.clone());
+        }
+
+        // "<method> <uri> HTTP/<version>\n<key>: <value>\n..."
+        let uri_offset = self.m_method.len() + 1;
+        let mut uri = self.m_uri.clone().into_bytes();
+        mask(&mut uri, uri_offset, &ranges);
+
+        let mut offset = uri_offset + uri.len() + " HTTP/".len() + self.m_http_version.len() + 1;
+        let mut request_headers = Vec::with_capacity(self.m_request_headers.len());
+        for (key, value) in self.m_request_headers.iter() {
+            let mut masked = value.clone().into_bytes();
+            mask(&mut masked, offset + key.len() + 2, &ranges);
+            offset += key.len() + value.len() + 3;
+            request_headers.push((key.clone(), String::from_utf8_lossy(&masked).into_owned()));
+        }
+
+        (String::from_utf8_lossy(&uri).into_owned(), request_headers)
+    }
+
+    /// Raw request body with the sanitised arguments and bytes masked.
+    pub fn sanitised_request_body(&self) -> Cow<'_, [u8]> {
+        let sanitise = self.m_sanitise.borrow();
+
+        let mut ranges = sanitise.m_request_body_ranges.clone();
+        let args_post = self.m_variables.m_variable_args_post.borrow();
+        for name in sanitise.m_args.iter() {
+            for value in args_post.values(name) {
+                ranges.extend(value.get_origin().iter().map(|o| (o.m_offset, o.m_length)));
+            }
+        }
+        if ranges.is_empty() {
+            return Cow::Borrowed(&self.m_request_body);
+        }
+
+        let mut body = self.m_request_body.clone();
+        mask(&mut body, 0, &ranges);
+        Cow::Owned(body)
+    }
+
+    /// Response headers with the values of the sanitised ones masked.
+    pub fn sanitised_response_headers(&self) -> Vec<(String, String)> {
+        let sanitise = self.m_sanitise.borrow();
+        self.m_response_headers
+            .iter()
+            .map(|(key, value)| {
+                let masked = sanitise
+                    .m_response_headers
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(key));
+                if masked {
+                    (key.clone(), "*".repeat(value.len()))
+                } else {
+                    (key.clone(), value.clone())
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,89 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::modsecurity::enums::Phases;
+
+use super::Transaction;
+
+/// A phase or rule span, entered until dropped. Without the `tracing`
+/// feature it does nothing.
+#[cfg(feature = "tracing")]
+pub struct TraceGuard(tracing::span::EnteredSpan);
+
+#[cfg(not(feature = "tracing"))]
+pub struct TraceGuard;
+
+impl TraceGuard {
+    /// Record the variable a rule matched, e.g. `ARGS:id`.
+    #[cfg(feature = "tracing")]
+    pub fn record_match(&self, variable: &str) {
+        self.0.record("matched_var", variable);
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub fn record_match(&self, _variable: &str) {}
+}
+
+/// `modsecurity.transaction` span of the transaction `id`, the parent of
+/// its phase spans; the connection, URI and response status are recorded
+/// as they are processed.
+#[cfg(feature = "tracing")]
+pub fn transaction_span(id: &str) -> tracing::Span {
+    use tracing::field::Empty;
+
+    tracing::info_span!(
+        "modsecurity.transaction",
+        unique_id = id,
+        client_ip = Empty,
+        method = Empty,
+        uri = Empty,
+        http_code = Empty,
+    )
+}
+
+#[cfg(feature = "tracing")]
+impl Transaction {
+    /// Enter the `modsecurity.phase` span of `phase`.
+    pub fn trace_phase(&self, phase: Phases) -> TraceGuard {
+        let span = tracing::info_span!(
+            parent: &self.m_span,
+            "modsecurity.phase",
+            phase = phase.as_str()
+        );
+        TraceGuard(span.entered())
+    }
+
+    /// Enter the `modsecurity.rule` span of the evaluation of the rule
+    /// `rule_id`, see `TraceGuard::record_match`.
+    pub fn trace_rule(&self, rule_id: i64, operator: &str) -> TraceGuard {
+        let span = tracing::debug_span!(
+            "modsecurity.rule",
+            rule_id,
+            operator,
+            matched_var = tracing::field::Empty
+        );
+        TraceGuard(span.entered())
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl Transaction {
+    pub fn trace_phase(&self, _phase: Phases) -> TraceGuard {
+        TraceGuard
+    }
+
+    pub fn trace_rule(&self, _rule_id: i64, _operator: &str) -> TraceGuard {
+        TraceGuard
+    }
+}
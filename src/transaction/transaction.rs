@@ -12,106 +12,823 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::rc::Rc;
+use std::{
+    borrow::Cow,
+    cell::{Cell, Ref, RefCell},
+    fs,
+    rc::Rc,
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use rand::Rng;
+
+use super::{Sanitise, TraceGuard};
 
-use crate::variables::anchored::{
-    AnchoredSetVariable, AnchoredSetVariableTranslationProxy, AnchoredVariable,
+use crate::{
+    audit_log::AuditLogStatus,
+    collection::Collections,
+    modsecurity::{
+        enums::Phases, modsecurity::modsecurity_who_am_i, Metrics, ModSecurityIntervention,
+        RuleProfiler, RuleTimer, RulesSetProperties, UploadKeepFiles,
+    },
+    ms_dbg,
+    request_body_processor::{charset, graphql::GraphQL, multipart::Multipart},
+    rules::RuleMessage,
+    utils::string::{uri_decode, url_decode},
+    variables::anchored::{
+        AnchoredSetVariable, AnchoredSetVariableTranslationProxy, AnchoredVariable,
+    },
 };
 
-pub struct Transaction {}
-
-pub struct TransactionAnchoredVariables<'t> {
-    m_variable_offset: i32,
-
-    m_variable_args_names: AnchoredSetVariableTranslationProxy<'t>,
-    m_variable_args_get_names: AnchoredSetVariableTranslationProxy<'t>,
-    m_variable_args_post_names: AnchoredSetVariableTranslationProxy<'t>,
-
-    m_variable_response_content_type: AnchoredVariable<'t>,
-    m_variable_arg_scombined_size: AnchoredVariable<'t>,
-    m_variable_auth_type: AnchoredVariable<'t>,
-    m_variable_files_combined_size: AnchoredVariable<'t>,
-    m_variable_full_request: AnchoredVariable<'t>,
-    m_variable_full_request_length: AnchoredVariable<'t>,
-    m_variable_inbound_data_error: AnchoredVariable<'t>,
-    m_variable_matched_var: AnchoredVariable<'t>,
-    m_variable_matched_var_name: AnchoredVariable<'t>,
-    m_variable_msc_pcre_error: AnchoredVariable<'t>,
-    m_variable_msc_pcre_limits_exceeded: AnchoredVariable<'t>,
-    m_variable_multipart_boundary_quoted: AnchoredVariable<'t>,
-    m_variable_multipart_boundary_white_space: AnchoredVariable<'t>,
-    m_variable_multipart_crlf_lf_lines: AnchoredVariable<'t>,
-    m_variable_multipart_data_after: AnchoredVariable<'t>,
-    m_variable_multipart_data_before: AnchoredVariable<'t>,
-    m_variable_multipart_file_limit_exceeded: AnchoredVariable<'t>,
-    m_variable_multipart_header_folding: AnchoredVariable<'t>,
-    m_variable_multipart_invalid_header_folding: AnchoredVariable<'t>,
-    m_variable_multipart_invalid_part: AnchoredVariable<'t>,
-    m_variable_multipart_invalid_quoting: AnchoredVariable<'t>,
-    m_variable_multipart_lf_line: AnchoredVariable<'t>,
-    m_variable_multipart_missing_semicolon: AnchoredVariable<'t>,
-    m_variable_multipart_strict_error: AnchoredVariable<'t>,
-    m_variable_multipart_unmatched_boundary: AnchoredVariable<'t>,
-    m_variable_outbound_data_error: AnchoredVariable<'t>,
-    m_variable_path_info: AnchoredVariable<'t>,
-    m_variable_query_string: AnchoredVariable<'t>,
-    m_variable_remote_addr: AnchoredVariable<'t>,
-    m_variable_remote_host: AnchoredVariable<'t>,
-    m_variable_remote_port: AnchoredVariable<'t>,
-    m_variable_reqbody_error: AnchoredVariable<'t>,
-    m_variable_reqbody_error_msg: AnchoredVariable<'t>,
-    m_variable_reqbody_processor_error: AnchoredVariable<'t>,
-    m_variable_reqbody_processor_error_msg: AnchoredVariable<'t>,
-    m_variable_reqbody_processor: AnchoredVariable<'t>,
-    m_variable_request_basename: AnchoredVariable<'t>,
-    m_variable_request_body: AnchoredVariable<'t>,
-    m_variable_request_body_length: AnchoredVariable<'t>,
-    m_variable_request_filename: AnchoredVariable<'t>,
-    m_variable_request_line: AnchoredVariable<'t>,
-    m_variable_request_method: AnchoredVariable<'t>,
-    m_variable_request_protocol: AnchoredVariable<'t>,
-    m_variable_request_uri: AnchoredVariable<'t>,
-    m_variable_request_uri_raw: AnchoredVariable<'t>,
-    m_variable_resource: AnchoredVariable<'t>,
-    m_variable_response_body: AnchoredVariable<'t>,
-    m_variable_response_content_length: AnchoredVariable<'t>,
-    m_variable_response_protocol: AnchoredVariable<'t>,
-    m_variable_response_status: AnchoredVariable<'t>,
-    m_variable_server_addr: AnchoredVariable<'t>,
-    m_variable_server_name: AnchoredVariable<'t>,
-    m_variable_server_port: AnchoredVariable<'t>,
-    m_variable_session_id: AnchoredVariable<'t>,
-    m_variable_unique_id: AnchoredVariable<'t>,
-    m_variable_url_encoded_error: AnchoredVariable<'t>,
-    m_variable_user_id: AnchoredVariable<'t>,
-
-    m_variable_args: Rc<AnchoredSetVariable<'t>>,
-    m_variable_args_get: Rc<AnchoredSetVariable<'t>>,
-    m_variable_args_post: Rc<AnchoredSetVariable<'t>>,
-    m_variable_request_headers_names: AnchoredSetVariable<'t>,
-    m_variable_response_headers_names: AnchoredSetVariable<'t>,
-    m_variable_files_sizes: AnchoredSetVariable<'t>,
-    m_variable_files_names: AnchoredSetVariable<'t>,
-    m_variable_files_tmp_content: AnchoredSetVariable<'t>,
-    m_variable_multipart_file_name: AnchoredSetVariable<'t>,
-    m_variable_multipart_name: AnchoredSetVariable<'t>,
-    m_variable_matched_vars_names: AnchoredSetVariable<'t>,
-    m_variable_matched_vars: AnchoredSetVariable<'t>,
-    m_variable_files: AnchoredSetVariable<'t>,
-    m_variable_request_cookies: AnchoredSetVariable<'t>,
-    m_variable_request_headers: AnchoredSetVariable<'t>,
-    m_variable_response_headers: AnchoredSetVariable<'t>,
-    m_variable_geo: AnchoredSetVariable<'t>,
-    m_variable_request_cookies_names: AnchoredSetVariable<'t>,
-    m_variable_files_tmp_names: AnchoredSetVariable<'t>,
-    m_variable_multipart_part_headers: AnchoredSetVariable<'t>,
+pub struct Transaction {
+    /// Holds the transaction unique id.
+    pub m_id: String,
+    /// Creation time of the transaction.
+    pub m_timestamp: SystemTime,
+    /// Used to measure the processing time of the transaction.
+    pub m_creation_instant: Instant,
+    /// Holds the client IP address.
+    pub m_client_ip_address: String,
+    /// Holds the server IP address.
+    pub m_server_ip_address: String,
+    /// Holds the client port.
+    pub m_client_port: u16,
+    /// Holds the server port.
+    pub m_server_port: u16,
+    /// Holds the request URI, as received by the connector.
+    pub m_uri: String,
+    /// Holds the request URI, percent-decoded.
+    pub m_uri_decoded: String,
+    /// Holds the HTTP method used by the request.
+    pub m_method: String,
+    /// Holds the HTTP version, e.g. "1.1".
+    pub m_http_version: String,
+    /// Request headers in the order they were added, used to reassemble
+    /// FULL_REQUEST.
+    pub m_request_headers: Vec<(String, String)>,
+    /// Response headers in the order they were added.
+    pub m_response_headers: Vec<(String, String)>,
+    /// HTTP status code of the response, see `process_response_headers`.
+    pub m_http_code_returned: i32,
+    /// Set by the disruptive actions, see `intervene`.
+    pub m_it: Cell<ModSecurityIntervention>,
+    /// Properties of the rule set this transaction is evaluated against.
+    pub m_rules: Arc<RulesSetProperties>,
+    /// Request body as received from the connector, raw bytes.
+    pub m_request_body: Vec<u8>,
+    /// Request body decoded to UTF-8, see SecBodyCharsetDecoding.
+    pub m_request_body_decoded: Option<String>,
+    /// Response body as received from the connector, raw bytes.
+    pub m_response_body: Vec<u8>,
+    /// Response body decoded to UTF-8, see SecBodyCharsetDecoding.
+    pub m_response_body_decoded: Option<String>,
+    pub m_variables: TransactionAnchoredVariables,
+    /// Messages of the rules that matched and were logged.
+    pub m_rules_messages: Vec<Rc<RuleMessage>>,
+    /// Files written to SecUploadDir, removed when the transaction is
+    /// dropped unless SecUploadKeepFiles says otherwise.
+    pub m_upload_tmp_files: Vec<String>,
+    /// Audit engine status set by ctl:auditEngine, overriding SecAuditEngine.
+    pub m_ctl_audit_engine: Cell<Option<AuditLogStatus>>,
+    /// Parts added (true) or removed (false) by ctl:auditLogParts, applied
+    /// in order to SecAuditLogParts.
+    pub m_audit_log_modifier: RefCell<Vec<(bool, i32)>>,
+    /// Values to mask in the audit log, see the sanitise actions.
+    pub m_sanitise: RefCell<Sanitise>,
+    /// ModSecurity and connector information of the instance that created
+    /// the transaction, the producer of the JSON audit log.
+    pub m_whoami: String,
+    pub m_connector: String,
+    /// Profiler of the instance that created the transaction.
+    pub m_profiler: Option<Arc<RuleProfiler>>,
+    /// Metrics of the instance that created the transaction.
+    pub m_metrics: Option<Arc<Metrics>>,
+    /// Persistent collections of the instance that created the
+    /// transaction, and TX.
+    pub m_collections: RefCell<Collections>,
+    /// `modsecurity.transaction` span, the parent of the phase spans.
+    #[cfg(feature = "tracing")]
+    pub m_span: tracing::Span,
+    /// REQUEST_LINE is assembled on first use, see `resolve_request_line`.
+    m_request_line_built: Cell<bool>,
+    /// FULL_REQUEST and FULL_REQUEST_LENGTH are assembled on first use, see
+    /// `resolve_full_request`.
+    m_full_request_built: Cell<bool>,
 }
 
-impl<'t> TransactionAnchoredVariables<'t> {
-    fn new(t: &Transaction) -> TransactionAnchoredVariables {
-        let m_variable_args = Rc::new(AnchoredSetVariable::new(t, "ARGS"));
-        let m_variable_args_get = Rc::new(AnchoredSetVariable::new(t, "ARGS_GET"));
-        let m_variable_args_post = Rc::new(AnchoredSetVariable::new(t, "ARGS_POST"));
+impl Transaction {
+    pub fn new(rules: Arc<RulesSetProperties>) -> Transaction {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Could not get system time");
+        let id = format!(
+            "{}{}.{}",
+            timestamp.as_secs(),
+            timestamp.subsec_micros(),
+            rand::thread_rng().gen_range(100000..1000000)
+        );
+
+        Transaction::new_with_id(rules, &id)
+    }
+
+    pub fn new_with_id(rules: Arc<RulesSetProperties>, id: &str) -> Transaction {
+        let mut variables = TransactionAnchoredVariables::new();
+        variables.m_variable_unique_id.set(id, 0);
+
+        Transaction {
+            m_id: id.to_string(),
+            m_timestamp: SystemTime::now(),
+            m_creation_instant: Instant::now(),
+            m_client_ip_address: String::new(),
+            m_server_ip_address: String::new(),
+            m_client_port: 0,
+            m_server_port: 0,
+            m_uri: String::new(),
+            m_uri_decoded: String::new(),
+            m_method: String::new(),
+            m_http_version: String::new(),
+            m_request_headers: Vec::new(),
+            m_response_headers: Vec::new(),
+            m_http_code_returned: 200,
+            m_it: Cell::new(ModSecurityIntervention::default()),
+            m_rules: rules,
+            m_request_body: Vec::new(),
+            m_request_body_decoded: None,
+            m_response_body: Vec::new(),
+            m_response_body_decoded: None,
+            m_variables: variables,
+            m_rules_messages: Vec::new(),
+            m_upload_tmp_files: Vec::new(),
+            m_ctl_audit_engine: Cell::new(None),
+            m_audit_log_modifier: RefCell::new(Vec::new()),
+            m_sanitise: RefCell::new(Sanitise::default()),
+            m_whoami: modsecurity_who_am_i(),
+            m_connector: String::new(),
+            m_profiler: None,
+            m_metrics: None,
+            m_collections: RefCell::new(Collections::new_in_memory()),
+            #[cfg(feature = "tracing")]
+            m_span: super::transaction_span(id),
+            m_request_line_built: Cell::new(false),
+            m_full_request_built: Cell::new(false),
+        }
+    }
+
+    /// Perform the analysis on the connection.
+    ///
+    /// This function should be called at very beginning of a request process,
+    /// it is expected to be executed prior to the virtual host resolution,
+    /// when the connection arrives on the server.
+    pub fn process_connection(&mut self, client: &str, cport: u16, server: &str, sport: u16) {
+        let _phase = self.start_phase(Phases::ConnectionPhase);
+        #[cfg(feature = "tracing")]
+        self.m_span.record("client_ip", client);
+        self.m_client_ip_address = client.to_string();
+        self.m_server_ip_address = server.to_string();
+        self.m_client_port = cport;
+        self.m_server_port = sport;
+
+        let vars = &mut self.m_variables;
+        vars.m_variable_remote_addr.set(client, 0);
+        vars.m_variable_remote_port.set(&cport.to_string(), 0);
+        vars.m_variable_server_addr.set(server, 0);
+        vars.m_variable_server_port.set(&sport.to_string(), 0);
+    }
+
+    /// Perform the analysis on the URI and all the query string variables.
+    ///
+    /// This function should be called at very beginning of a request process,
+    /// it is expected to be executed prior to the virtual host resolution,
+    /// when the connection arrives on the server.
+    ///
+    /// REQUEST_LINE and FULL_REQUEST are not built here, see
+    /// `resolve_request_line` and `resolve_full_request`.
+    pub fn process_uri(&mut self, uri: &str, method: &str, http_version: &str) {
+        let _phase = self.start_phase(Phases::UriPhase);
+        #[cfg(feature = "tracing")]
+        {
+            self.m_span.record("method", method);
+            self.m_span.record("uri", uri);
+        }
+        self.m_uri = uri.to_string();
+        self.m_uri_decoded = uri_decode(uri);
+        self.m_method = method.to_string();
+        self.m_http_version = http_version.to_string();
+        self.m_request_line_built.set(false);
+        self.m_full_request_built.set(false);
+
+        // offsets are relative to FULL_REQUEST: "<method> <uri> HTTP/<version>\n"
+        let uri_offset = method.len() + 1;
+        let protocol = format!("HTTP/{}", http_version);
+        let protocol_offset = uri_offset + uri.len() + 1;
+
+        let vars = &mut self.m_variables;
+        vars.m_variable_request_method.set(method, 0);
+        vars.m_variable_request_uri_raw.set(uri, uri_offset);
+        vars.m_variable_request_uri.set(uri, uri_offset);
+        vars.m_variable_request_protocol
+            .set(&protocol, protocol_offset);
+
+        let (path, query) = match uri.split_once('?') {
+            None => (uri, None),
+            Some((path, query)) => (path, Some(query)),
+        };
+        // REQUEST_FILENAME and REQUEST_BASENAME are decoded
+        let path_decoded = uri_decode(path);
+        vars.m_variable_request_filename
+            .set_with_length(&path_decoded, uri_offset, path.len());
+        let basename = path_decoded.rsplit('/').next().unwrap_or_default();
+        let basename_offset = path.rfind('/').map_or(0, |i| i + 1);
+        vars.m_variable_request_basename.set_with_length(
+            basename,
+            uri_offset + basename_offset,
+            path.len() - basename_offset,
+        );
+        if let Some(query) = query {
+            vars.m_variable_query_string
+                .set(query, uri_offset + path.len() + 1);
+        }
+
+        vars.m_variable_offset = (protocol_offset + protocol.len() + 1) as i32;
+
+        if let Some(query) = query {
+            self.extract_arguments("GET", query.as_bytes(), uri_offset + path.len() + 1, None);
+        }
+    }
+
+    /// Split `buf`, a query string or an application/x-www-form-urlencoded
+    /// body found at `offset`, into ARGS and ARGS_GET or ARGS_POST. Names and
+    /// values are decoded, from `charset` if any, their origins cover the raw
+    /// values: the offsets are counted in bytes of `buf`, whatever its
+    /// encoding.
+    fn extract_arguments(&mut self, orig: &str, buf: &[u8], offset: usize, charset: Option<&str>) {
+        let mut pair_offset = offset;
+        for pair in buf.split(|&b| b == b'&') {
+            let (key, value) = match pair.iter().position(|&b| b == b'=') {
+                Some(i) => (&pair[..i], &pair[i + 1..]),
+                None => (pair, &b""[..]),
+            };
+            if !key.is_empty() {
+                let value_offset = pair_offset + pair.len() - value.len();
+                let raw_length = value.len();
+                let key = charset::decode_argument(&url_decode(key), charset);
+                let value = charset::decode_argument(&url_decode(value), charset);
+                ms_dbg!(
+                    self,
+                    4,
+                    "Adding request argument ({}): name \"{}\", value \"{}\"",
+                    orig,
+                    key,
+                    value
+                );
+
+                let vars = &self.m_variables;
+                vars.m_variable_args.borrow_mut().set_with_length(
+                    &key,
+                    &value,
+                    value_offset,
+                    raw_length,
+                );
+                let args = match orig {
+                    "GET" => &vars.m_variable_args_get,
+                    _ => &vars.m_variable_args_post,
+                };
+                args.borrow_mut()
+                    .set_with_length(&key, &value, value_offset, raw_length);
+            }
+            pair_offset += pair.len() + 1;
+        }
+    }
+
+    /// Assemble REQUEST_LINE, if it was not already, and return it.
+    ///
+    /// REQUEST_LINE is only needed by the rules that reference it, so it is
+    /// not built by `process_uri` but when the variable is resolved.
+    pub fn resolve_request_line(&self) -> Ref<'_, str> {
+        if !self.m_request_line_built.get() {
+            let request_line = format!(
+                "{} {} HTTP/{}",
+                self.m_method, self.m_uri, self.m_http_version
+            );
+            self.m_variables
+                .m_variable_request_line
+                .borrow_mut()
+                .set(&request_line, 0);
+            self.m_request_line_built.set(true);
+        }
+
+        Ref::map(self.m_variables.m_variable_request_line.borrow(), |v| {
+            v.evaluate()
+        })
+    }
+
+    /// Assemble FULL_REQUEST and FULL_REQUEST_LENGTH, if they were not
+    /// already, and return FULL_REQUEST.
+    ///
+    /// FULL_REQUEST is the request line, followed by the request headers and
+    /// the request body received so far, the way they would appear on the
+    /// wire. Adding headers or body afterwards invalidates it.
+    pub fn resolve_full_request(&self) -> Ref<'_, str> {
+        if !self.m_full_request_built.get() {
+            let request_line = self.resolve_request_line().to_string();
+            let headers_len: usize = self
+                .m_request_headers
+                .iter()
+                .map(|(k, v)| k.len() + v.len() + 3)
+                .sum();
+
+            // the length of the request as received, the body bytes that are
+            // not UTF-8 being replaced in FULL_REQUEST
+            let length = request_line.len() + headers_len + self.m_request_body.len() + 2;
+
+            let mut full_request = String::with_capacity(length);
+            full_request.push_str(&request_line);
+            full_request.push('\n');
+            for (key, value) in self.m_request_headers.iter() {
+                full_request.push_str(key);
+                full_request.push_str(": ");
+                full_request.push_str(value);
+                full_request.push('\n');
+            }
+            full_request.push('\n');
+            full_request.push_str(&String::from_utf8_lossy(&self.m_request_body));
+
+            let vars = &self.m_variables;
+            vars.m_variable_full_request_length
+                .borrow_mut()
+                .set(&length.to_string(), 0);
+            vars.m_variable_full_request
+                .borrow_mut()
+                .set_with_length(&full_request, 0, length);
+            self.m_full_request_built.set(true);
+        }
+
+        Ref::map(self.m_variables.m_variable_full_request.borrow(), |v| {
+            v.evaluate()
+        })
+    }
+
+    /// Assemble FULL_REQUEST_LENGTH, if it was not already, and return it.
+    pub fn resolve_full_request_length(&self) -> Ref<'_, str> {
+        drop(self.resolve_full_request());
+        Ref::map(
+            self.m_variables.m_variable_full_request_length.borrow(),
+            |v| v.evaluate(),
+        )
+    }
+
+    /// Adds a request header.
+    ///
+    /// With this function it is possible to feed ModSecurity with a request
+    /// header.
+    pub fn add_request_header(&mut self, key: &str, value: &str) {
+        self.m_request_headers
+            .push((key.to_string(), value.to_string()));
+        self.m_full_request_built.set(false);
+
+        let vars = &mut self.m_variables;
+        let offset = vars.m_variable_offset as usize;
+        vars.m_variable_request_headers_names.set(key, key, offset);
+        vars.m_variable_request_headers
+            .set(key, value, offset + key.len() + 2);
+        // "<key>: <value>\n"
+        vars.m_variable_offset += (key.len() + value.len() + 3) as i32;
+
+        if key.eq_ignore_ascii_case("cookie") {
+            self.add_request_cookies(value, offset + key.len() + 2);
+        }
+    }
+
+    /// `Cookie: a=1; b=2`, found at `offset`, fills REQUEST_COOKIES and
+    /// REQUEST_COOKIES_NAMES.
+    fn add_request_cookies(&mut self, header: &str, offset: usize) {
+        let vars = &mut self.m_variables;
+        let mut start = 0;
+        for cookie in header.split(';') {
+            let trimmed = cookie.trim_start();
+            let cookie_offset = offset + start + cookie.len() - trimmed.len();
+            start += cookie.len() + 1;
+
+            let (name, value, value_offset) = match trimmed.find('=') {
+                Some(i) => (&trimmed[..i], &trimmed[i + 1..], cookie_offset + i + 1),
+                None => (trimmed, "", cookie_offset + trimmed.len()),
+            };
+            let name = name.trim_end();
+            if name.is_empty() {
+                continue;
+            }
+            vars.m_variable_request_cookies_names
+                .set(name, name, cookie_offset);
+            vars.m_variable_request_cookies
+                .set(name, value.trim_end(), value_offset);
+        }
+    }
+
+    /// Adds request body to be inspected.
+    ///
+    /// With this function it is possible to feed ModSecurity with data to be
+    /// inspected. The data may be given in chunks.
+    pub fn append_request_body(&mut self, buf: &[u8]) {
+        self.m_request_body.extend_from_slice(buf);
+        self.m_full_request_built.set(false);
+    }
+
+    /// Perform the analysis on the request body (if any).
+    ///
+    /// REQUEST_BODY always holds the raw bytes, the decoded body (see
+    /// SecBodyCharsetDecoding) is what the body processors get through
+    /// `request_body_for_processors`.
+    pub fn process_request_body(&mut self) {
+        let _phase = self.start_phase(Phases::RequestBodyPhase);
+        let content_type = self
+            .m_variables
+            .m_variable_request_headers
+            .resolve_first("Content-Type")
+            .unwrap_or_default()
+            .to_string();
+
+        if self.m_rules.m_body_charset_decoding {
+            match charset::decode_body(&self.m_request_body, &content_type) {
+                Ok(decoded) => {
+                    self.m_request_body_decoded = decoded.map(|d| d.into_owned());
+                }
+                Err(err) => {
+                    let vars = &mut self.m_variables;
+                    vars.m_variable_reqbody_charset_error.set("1", 0);
+                    vars.m_variable_reqbody_charset_error_msg.set(&err, 0);
+                }
+            }
+        }
+
+        if Multipart::is_multipart(&content_type) {
+            self.process_multipart(&content_type);
+        } else if self.m_rules.m_graphql_body_processor
+            && GraphQL::is_graphql(&content_type)
+            && self.process_graphql(&content_type)
+        {
+            // claimed by the GraphQL processor
+        } else if content_type
+            .to_lowercase()
+            .starts_with("application/x-www-form-urlencoded")
+        {
+            self.process_urlencoded(&content_type);
+        }
+
+        let body = String::from_utf8_lossy(&self.m_request_body).into_owned();
+        let vars = &mut self.m_variables;
+        vars.m_variable_request_body.set(&body, 0);
+        vars.m_variable_request_body_length
+            .set(&self.m_request_body.len().to_string(), 0);
+    }
+
+    /// Multipart bodies are binary, they are parsed from the raw bytes
+    /// whatever SecBodyCharsetDecoding says; with it, the text fields are
+    /// decoded from their charset, see `Multipart::process`.
+    fn process_multipart(&mut self, content_type: &str) {
+        self.m_variables
+            .m_variable_reqbody_processor
+            .set("MULTIPART", 0);
+
+        let body = std::mem::take(&mut self.m_request_body);
+        let result = Multipart::parse(content_type, &body).and_then(|m| m.process(self));
+        self.m_request_body = body;
+
+        if let Err(err) = result {
+            self.set_reqbody_processor_error(&err);
+        }
+    }
+
+    /// Url encoded bodies are parsed from the raw bytes, valid UTF-8 or
+    /// not, so that the origins of ARGS_POST are offsets in the raw body.
+    /// Once the body was decoded, see SecBodyCharsetDecoding, the names and
+    /// values are decoded from its charset.
+    fn process_urlencoded(&mut self, content_type: &str) {
+        self.m_variables
+            .m_variable_reqbody_processor
+            .set("URLENCODED", 0);
+
+        let charset = match self.m_request_body_decoded {
+            Some(_) => charset::charset_from_content_type(content_type),
+            None => None,
+        };
+        let body = std::mem::take(&mut self.m_request_body);
+        self.extract_arguments("POST", &body, 0, charset);
+        self.m_request_body = body;
+    }
+
+    /// GraphQL bodies are parsed from the decoded body, see
+    /// SecBodyCharsetDecoding. JSON bodies without a GraphQL query are not
+    /// claimed, false is returned and they are left to the other processors.
+    fn process_graphql(&mut self, content_type: &str) -> bool {
+        let processor = GraphQL {
+            m_max_depth: self.m_rules.m_graphql_max_depth,
+            m_max_aliases: self.m_rules.m_graphql_max_aliases,
+            m_max_fields: self.m_rules.m_graphql_max_fields,
+        };
+        let result = processor.process(self, content_type);
+        if let Ok(false) = result {
+            return false;
+        }
+
+        self.m_variables
+            .m_variable_reqbody_processor
+            .set("GRAPHQL", 0);
+        if let Err(err) = result {
+            self.set_reqbody_processor_error(&err);
+        }
+        true
+    }
+
+    fn set_reqbody_processor_error(&mut self, err: &str) {
+        let vars = &mut self.m_variables;
+        vars.m_variable_reqbody_error.set("1", 0);
+        vars.m_variable_reqbody_error_msg.set(err, 0);
+        vars.m_variable_reqbody_processor_error.set("1", 0);
+        vars.m_variable_reqbody_processor_error_msg.set(err, 0);
+
+        if let Some(metrics) = &self.m_metrics {
+            let processor = vars.m_variable_reqbody_processor.evaluate();
+            metrics.m_body_processor_errors.inc(&[processor]);
+        }
+    }
+
+    /// Request body as the body processors should see it: decoded to UTF-8
+    /// when SecBodyCharsetDecoding succeeded, the raw bytes otherwise.
+    pub fn request_body_for_processors(&self) -> Cow<'_, str> {
+        match &self.m_request_body_decoded {
+            Some(decoded) => Cow::Borrowed(decoded),
+            None => String::from_utf8_lossy(&self.m_request_body),
+        }
+    }
+
+    /// Record the intervention of a disruptive action, e.g. deny, returning
+    /// `status` to the client.
+    pub fn intervene(&self, status: i32) {
+        self.m_it.set(ModSecurityIntervention {
+            m_status: status,
+            m_disruptive: true,
+        });
+    }
+
+    /// A transaction is relevant when at least one rule logged a message.
+    pub fn is_relevant(&self) -> bool {
+        !self.m_rules_messages.is_empty()
+    }
+
+    /// Perform the analysis on the response headers.
+    ///
+    /// `code` is the HTTP status code and `protocol` the response protocol,
+    /// e.g. "HTTP 1.1".
+    pub fn process_response_headers(&mut self, code: i32, protocol: &str) {
+        let _phase = self.start_phase(Phases::ResponseHeadersPhase);
+        #[cfg(feature = "tracing")]
+        self.m_span.record("http_code", code);
+        self.m_http_code_returned = code;
+        let vars = &mut self.m_variables;
+        vars.m_variable_response_status.set(&code.to_string(), 0);
+        vars.m_variable_response_protocol.set(protocol, 0);
+    }
+
+    /// Adds a response header.
+    ///
+    /// With this function it is possible to feed ModSecurity with a response
+    /// header.
+    pub fn add_response_header(&mut self, key: &str, value: &str) {
+        self.m_response_headers
+            .push((key.to_string(), value.to_string()));
+
+        let vars = &mut self.m_variables;
+        vars.m_variable_response_headers_names.set(key, key, 0);
+        vars.m_variable_response_headers.set(key, value, 0);
+        if key.eq_ignore_ascii_case("content-type") {
+            vars.m_variable_response_content_type.set(value, 0);
+        }
+    }
+
+    /// Adds response body to be inspected.
+    ///
+    /// With this function it is possible to feed ModSecurity with data to be
+    /// inspected. The data may be given in chunks.
+    pub fn append_response_body(&mut self, buf: &[u8]) {
+        self.m_response_body.extend_from_slice(buf);
+    }
+
+    /// Perform the analysis on the response body (if any).
+    ///
+    /// As for the request, RESPONSE_BODY keeps the raw bytes while the
+    /// decoded body is available through `response_body_for_processors`.
+    pub fn process_response_body(&mut self) {
+        let _phase = self.start_phase(Phases::ResponseBodyPhase);
+        if self.m_rules.m_body_charset_decoding {
+            let content_type = self.m_variables.m_variable_response_content_type.evaluate();
+            match charset::decode_body(&self.m_response_body, content_type) {
+                Ok(decoded) => {
+                    self.m_response_body_decoded = decoded.map(|d| d.into_owned());
+                }
+                Err(err) => {
+                    let vars = &mut self.m_variables;
+                    vars.m_variable_resbody_charset_error.set("1", 0);
+                    vars.m_variable_resbody_charset_error_msg.set(&err, 0);
+                }
+            }
+        }
+
+        let body = String::from_utf8_lossy(&self.m_response_body).into_owned();
+        let vars = &mut self.m_variables;
+        vars.m_variable_response_body.set(&body, 0);
+        vars.m_variable_response_content_length
+            .set(&self.m_response_body.len().to_string(), 0);
+    }
+
+    /// Response body as the body processors should see it, see
+    /// `request_body_for_processors`.
+    pub fn response_body_for_processors(&self) -> Cow<'_, str> {
+        match &self.m_response_body_decoded {
+            Some(decoded) => Cow::Borrowed(decoded),
+            None => String::from_utf8_lossy(&self.m_response_body),
+        }
+    }
+
+    /// Logging all information relative to this transaction.
+    ///
+    /// At this point there is not need to hold the connection, the response
+    /// can be delivered prior to the execution of this function. The records
+    /// of the persistent collections written by the transaction are updated
+    /// first, see `Collections::update_records`.
+    pub fn process_logging(&mut self) -> Result<(), String> {
+        let _phase = self.start_phase(Phases::LoggingPhase);
+        self.m_collections.borrow_mut().update_records();
+
+        let audit_log = &self.m_rules.m_audit_log;
+        let mut parts = audit_log.m_parts;
+        for (add, modifier) in self.m_audit_log_modifier.borrow().iter() {
+            ms_dbg!(
+                self,
+                4,
+                "There was an audit log modifier for this transaction."
+            );
+            if *add {
+                parts |= modifier;
+            } else {
+                parts &= !modifier;
+            }
+        }
+
+        audit_log.save_if_relevant_with_parts(self, parts)
+    }
+
+    /// Timer of an evaluation of the rule `rule_id`, recorded to the
+    /// profiler when dropped.
+    pub fn profile_rule(&self, rule_id: i64) -> RuleTimer<'_> {
+        RuleTimer::new(self.m_profiler.as_deref(), rule_id)
+    }
+
+    /// Count the transaction in `phase` and open its span, closed when the
+    /// returned guard is dropped.
+    pub fn start_phase(&self, phase: Phases) -> TraceGuard {
+        if let Some(metrics) = &self.m_metrics {
+            metrics.m_transactions.inc(&[phase.as_str()]);
+        }
+        self.trace_phase(phase)
+    }
+
+    /// Account the messages logged by the rules, and the intervention of
+    /// the disruptive actions, labelled with its status.
+    fn record_metrics(&self) {
+        let Some(metrics) = &self.m_metrics else {
+            return;
+        };
+        for rm in self.m_rules_messages.iter() {
+            metrics.m_rule_hits.inc(&[&rm.m_rule_id.to_string()]);
+            for tag in rm.m_tags.iter() {
+                metrics.m_rule_tag_hits.inc(&[tag]);
+            }
+        }
+        let it = self.m_it.get();
+        if it.m_disruptive {
+            metrics.m_interventions.inc(&[&it.m_status.to_string()]);
+        }
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        self.record_metrics();
+
+        let keep = match self.m_rules.m_upload_keep_files {
+            UploadKeepFiles::OnUploadKeepFiles => true,
+            UploadKeepFiles::OffUploadKeepFiles => false,
+            UploadKeepFiles::RelevantOnlyUploadKeepFiles => self.is_relevant(),
+        };
+        if !keep {
+            for file in self.m_upload_tmp_files.drain(..) {
+                _ = fs::remove_file(file);
+            }
+        }
+    }
+}
+
+pub struct TransactionAnchoredVariables {
+    pub m_variable_offset: i32,
+
+    pub m_variable_args_names: AnchoredSetVariableTranslationProxy,
+    pub m_variable_args_get_names: AnchoredSetVariableTranslationProxy,
+    pub m_variable_args_post_names: AnchoredSetVariableTranslationProxy,
+
+    pub m_variable_response_content_type: AnchoredVariable,
+    pub m_variable_arg_scombined_size: AnchoredVariable,
+    pub m_variable_auth_type: AnchoredVariable,
+    pub m_variable_files_combined_size: AnchoredVariable,
+    /// Built when resolved, see `Transaction::resolve_full_request`.
+    pub m_variable_full_request: RefCell<AnchoredVariable>,
+    pub m_variable_full_request_length: RefCell<AnchoredVariable>,
+    pub m_variable_inbound_data_error: AnchoredVariable,
+    pub m_variable_matched_var: AnchoredVariable,
+    pub m_variable_matched_var_name: AnchoredVariable,
+    pub m_variable_msc_pcre_error: AnchoredVariable,
+    pub m_variable_msc_pcre_limits_exceeded: AnchoredVariable,
+    pub m_variable_multipart_boundary_quoted: AnchoredVariable,
+    pub m_variable_multipart_boundary_white_space: AnchoredVariable,
+    pub m_variable_multipart_crlf_lf_lines: AnchoredVariable,
+    pub m_variable_multipart_data_after: AnchoredVariable,
+    pub m_variable_multipart_data_before: AnchoredVariable,
+    pub m_variable_multipart_file_limit_exceeded: AnchoredVariable,
+    pub m_variable_multipart_header_folding: AnchoredVariable,
+    pub m_variable_multipart_invalid_header_folding: AnchoredVariable,
+    pub m_variable_multipart_invalid_part: AnchoredVariable,
+    pub m_variable_multipart_invalid_quoting: AnchoredVariable,
+    pub m_variable_multipart_lf_line: AnchoredVariable,
+    pub m_variable_multipart_missing_semicolon: AnchoredVariable,
+    pub m_variable_multipart_strict_error: AnchoredVariable,
+    pub m_variable_multipart_unmatched_boundary: AnchoredVariable,
+    pub m_variable_outbound_data_error: AnchoredVariable,
+    pub m_variable_path_info: AnchoredVariable,
+    pub m_variable_query_string: AnchoredVariable,
+    pub m_variable_remote_addr: AnchoredVariable,
+    pub m_variable_remote_host: AnchoredVariable,
+    pub m_variable_remote_port: AnchoredVariable,
+    pub m_variable_reqbody_error: AnchoredVariable,
+    pub m_variable_reqbody_error_msg: AnchoredVariable,
+    pub m_variable_reqbody_processor_error: AnchoredVariable,
+    pub m_variable_reqbody_processor_error_msg: AnchoredVariable,
+    pub m_variable_reqbody_processor: AnchoredVariable,
+    pub m_variable_reqbody_charset_error: AnchoredVariable,
+    pub m_variable_reqbody_charset_error_msg: AnchoredVariable,
+    pub m_variable_resbody_charset_error: AnchoredVariable,
+    pub m_variable_resbody_charset_error_msg: AnchoredVariable,
+    pub m_variable_request_basename: AnchoredVariable,
+    pub m_variable_request_body: AnchoredVariable,
+    pub m_variable_request_body_length: AnchoredVariable,
+    pub m_variable_request_filename: AnchoredVariable,
+    /// Built when resolved, see `Transaction::resolve_request_line`.
+    pub m_variable_request_line: RefCell<AnchoredVariable>,
+    pub m_variable_request_method: AnchoredVariable,
+    pub m_variable_request_protocol: AnchoredVariable,
+    pub m_variable_request_uri: AnchoredVariable,
+    pub m_variable_request_uri_raw: AnchoredVariable,
+    pub m_variable_resource: AnchoredVariable,
+    pub m_variable_response_body: AnchoredVariable,
+    pub m_variable_response_content_length: AnchoredVariable,
+    pub m_variable_response_protocol: AnchoredVariable,
+    pub m_variable_response_status: AnchoredVariable,
+    pub m_variable_server_addr: AnchoredVariable,
+    pub m_variable_server_name: AnchoredVariable,
+    pub m_variable_server_port: AnchoredVariable,
+    /// Set by setsid, an action given a shared transaction.
+    pub m_variable_session_id: RefCell<AnchoredVariable>,
+    pub m_variable_unique_id: AnchoredVariable,
+    pub m_variable_url_encoded_error: AnchoredVariable,
+    /// Set by setuid, see SESSIONID.
+    pub m_variable_user_id: RefCell<AnchoredVariable>,
+
+    pub m_variable_args: Rc<RefCell<AnchoredSetVariable>>,
+    pub m_variable_args_get: Rc<RefCell<AnchoredSetVariable>>,
+    pub m_variable_args_post: Rc<RefCell<AnchoredSetVariable>>,
+    pub m_variable_request_headers_names: AnchoredSetVariable,
+    pub m_variable_response_headers_names: AnchoredSetVariable,
+    pub m_variable_files_sizes: AnchoredSetVariable,
+    pub m_variable_files_names: AnchoredSetVariable,
+    pub m_variable_files_tmp_content: AnchoredSetVariable,
+    pub m_variable_multipart_file_name: AnchoredSetVariable,
+    pub m_variable_multipart_name: AnchoredSetVariable,
+    pub m_variable_matched_vars_names: AnchoredSetVariable,
+    pub m_variable_matched_vars: AnchoredSetVariable,
+    pub m_variable_files: AnchoredSetVariable,
+    pub m_variable_request_cookies: AnchoredSetVariable,
+    pub m_variable_request_headers: AnchoredSetVariable,
+    pub m_variable_response_headers: AnchoredSetVariable,
+    pub m_variable_geo: AnchoredSetVariable,
+    pub m_variable_request_cookies_names: AnchoredSetVariable,
+    pub m_variable_files_tmp_names: AnchoredSetVariable,
+    pub m_variable_multipart_part_headers: AnchoredSetVariable,
+}
+
+impl TransactionAnchoredVariables {
+    pub fn new() -> TransactionAnchoredVariables {
+        let m_variable_args = Rc::new(RefCell::new(AnchoredSetVariable::new("ARGS")));
+        let m_variable_args_get = Rc::new(RefCell::new(AnchoredSetVariable::new("ARGS_GET")));
+        let m_variable_args_post = Rc::new(RefCell::new(AnchoredSetVariable::new("ARGS_POST")));
 
         TransactionAnchoredVariables {
             m_variable_offset: 0,
@@ -128,120 +845,116 @@ impl<'t> TransactionAnchoredVariables<'t> {
                 m_variable_args_post.clone(),
             ),
 
-            m_variable_response_content_type: AnchoredVariable::new(t, "RESPONSE_CONTENT_TYPE"),
-            m_variable_arg_scombined_size: AnchoredVariable::new(t, "ARGS_COMBINED_SIZE"),
-            m_variable_auth_type: AnchoredVariable::new(t, "AUTH_TYPE"),
-            m_variable_files_combined_size: AnchoredVariable::new(t, "FILES_COMBINED_SIZE"),
-            m_variable_full_request: AnchoredVariable::new(t, "FULL_REQUEST"),
-            m_variable_full_request_length: AnchoredVariable::new(t, "FULL_REQUEST_LENGTH"),
-            m_variable_inbound_data_error: AnchoredVariable::new(t, "INBOUND_DATA_ERROR"),
-            m_variable_matched_var: AnchoredVariable::new(t, "MATCHED_VAR"),
-            m_variable_matched_var_name: AnchoredVariable::new(t, "MATCHED_VAR_NAME"),
-            m_variable_msc_pcre_error: AnchoredVariable::new(t, "MSC_PCRE_ERROR"),
-            m_variable_msc_pcre_limits_exceeded: AnchoredVariable::new(
-                t,
-                "MSC_PCRE_LIMITS_EXCEEDED",
-            ),
+            m_variable_response_content_type: AnchoredVariable::new("RESPONSE_CONTENT_TYPE"),
+            m_variable_arg_scombined_size: AnchoredVariable::new("ARGS_COMBINED_SIZE"),
+            m_variable_auth_type: AnchoredVariable::new("AUTH_TYPE"),
+            m_variable_files_combined_size: AnchoredVariable::new("FILES_COMBINED_SIZE"),
+            m_variable_full_request: RefCell::new(AnchoredVariable::new("FULL_REQUEST")),
+            m_variable_full_request_length: RefCell::new(AnchoredVariable::new(
+                "FULL_REQUEST_LENGTH",
+            )),
+            m_variable_inbound_data_error: AnchoredVariable::new("INBOUND_DATA_ERROR"),
+            m_variable_matched_var: AnchoredVariable::new("MATCHED_VAR"),
+            m_variable_matched_var_name: AnchoredVariable::new("MATCHED_VAR_NAME"),
+            m_variable_msc_pcre_error: AnchoredVariable::new("MSC_PCRE_ERROR"),
+            m_variable_msc_pcre_limits_exceeded: AnchoredVariable::new("MSC_PCRE_LIMITS_EXCEEDED"),
             m_variable_multipart_boundary_quoted: AnchoredVariable::new(
-                t,
                 "MULTIPART_BOUNDARY_QUOTED",
             ),
             m_variable_multipart_boundary_white_space: AnchoredVariable::new(
-                t,
                 "MULTIPART_BOUNDARY_WHITESPACE",
             ),
-            m_variable_multipart_crlf_lf_lines: AnchoredVariable::new(t, "MULTIPART_CRLF_LF_LINES"),
-            m_variable_multipart_data_after: AnchoredVariable::new(t, "MULTIPART_DATA_AFTER"),
-            m_variable_multipart_data_before: AnchoredVariable::new(t, "MULTIPART_DATA_BEFORE"),
+            m_variable_multipart_crlf_lf_lines: AnchoredVariable::new("MULTIPART_CRLF_LF_LINES"),
+            m_variable_multipart_data_after: AnchoredVariable::new("MULTIPART_DATA_AFTER"),
+            m_variable_multipart_data_before: AnchoredVariable::new("MULTIPART_DATA_BEFORE"),
             m_variable_multipart_file_limit_exceeded: AnchoredVariable::new(
-                t,
                 "MULTIPART_FILE_LIMIT_EXCEEDED",
             ),
-            m_variable_multipart_header_folding: AnchoredVariable::new(
-                t,
-                "MULTIPART_HEADER_FOLDING",
-            ),
+            m_variable_multipart_header_folding: AnchoredVariable::new("MULTIPART_HEADER_FOLDING"),
             m_variable_multipart_invalid_header_folding: AnchoredVariable::new(
-                t,
                 "MULTIPART_INVALID_HEADER_FOLDING",
             ),
-            m_variable_multipart_invalid_part: AnchoredVariable::new(t, "MULTIPART_INVALID_PART"),
+            m_variable_multipart_invalid_part: AnchoredVariable::new("MULTIPART_INVALID_PART"),
             m_variable_multipart_invalid_quoting: AnchoredVariable::new(
-                t,
                 "MULTIPART_INVALID_QUOTING",
             ),
-            m_variable_multipart_lf_line: AnchoredVariable::new(t, "MULTIPART_LF_LINE"),
+            m_variable_multipart_lf_line: AnchoredVariable::new("MULTIPART_LF_LINE"),
             m_variable_multipart_missing_semicolon: AnchoredVariable::new(
-                t,
                 "MULTIPART_MISSING_SEMICOLON",
             ),
-            m_variable_multipart_strict_error: AnchoredVariable::new(t, "MULTIPART_STRICT_ERROR"),
+            m_variable_multipart_strict_error: AnchoredVariable::new("MULTIPART_STRICT_ERROR"),
             m_variable_multipart_unmatched_boundary: AnchoredVariable::new(
-                t,
                 "MULTIPART_UNMATCHED_BOUNDARY",
             ),
-            m_variable_outbound_data_error: AnchoredVariable::new(t, "OUTBOUND_DATA_ERROR"),
-            m_variable_path_info: AnchoredVariable::new(t, "PATH_INFO"),
-            m_variable_query_string: AnchoredVariable::new(t, "QUERY_STRING"),
-            m_variable_remote_addr: AnchoredVariable::new(t, "REMOTE_ADDR"),
-            m_variable_remote_host: AnchoredVariable::new(t, "REMOTE_HOST"),
-            m_variable_remote_port: AnchoredVariable::new(t, "REMOTE_PORT"),
-            m_variable_reqbody_error: AnchoredVariable::new(t, "REQBODY_ERROR"),
-            m_variable_reqbody_error_msg: AnchoredVariable::new(t, "REQBODY_ERROR_MSG"),
-            m_variable_reqbody_processor_error: AnchoredVariable::new(t, "REQBODY_PROCESSOR_ERROR"),
+            m_variable_outbound_data_error: AnchoredVariable::new("OUTBOUND_DATA_ERROR"),
+            m_variable_path_info: AnchoredVariable::new("PATH_INFO"),
+            m_variable_query_string: AnchoredVariable::new("QUERY_STRING"),
+            m_variable_remote_addr: AnchoredVariable::new("REMOTE_ADDR"),
+            m_variable_remote_host: AnchoredVariable::new("REMOTE_HOST"),
+            m_variable_remote_port: AnchoredVariable::new("REMOTE_PORT"),
+            m_variable_reqbody_error: AnchoredVariable::new("REQBODY_ERROR"),
+            m_variable_reqbody_error_msg: AnchoredVariable::new("REQBODY_ERROR_MSG"),
+            m_variable_reqbody_processor_error: AnchoredVariable::new("REQBODY_PROCESSOR_ERROR"),
             m_variable_reqbody_processor_error_msg: AnchoredVariable::new(
-                t,
                 "REQBODY_PROCESSOR_ERROR_MSG",
             ),
-            m_variable_reqbody_processor: AnchoredVariable::new(t, "REQBODY_PROCESSOR"),
-            m_variable_request_basename: AnchoredVariable::new(t, "REQUEST_BASENAME"),
-            m_variable_request_body: AnchoredVariable::new(t, "REQUEST_BODY"),
-            m_variable_request_body_length: AnchoredVariable::new(t, "REQUEST_BODY_LENGTH"),
-            m_variable_request_filename: AnchoredVariable::new(t, "REQUEST_FILENAME"),
-            m_variable_request_line: AnchoredVariable::new(t, "REQUEST_LINE"),
-            m_variable_request_method: AnchoredVariable::new(t, "REQUEST_METHOD"),
-            m_variable_request_protocol: AnchoredVariable::new(t, "REQUEST_PROTOCOL"),
-            m_variable_request_uri: AnchoredVariable::new(t, "REQUEST_URI"),
-            m_variable_request_uri_raw: AnchoredVariable::new(t, "REQUEST_URI_RAW"),
-            m_variable_resource: AnchoredVariable::new(t, "RESOURCE"),
-            m_variable_response_body: AnchoredVariable::new(t, "RESPONSE_BODY"),
-            m_variable_response_content_length: AnchoredVariable::new(t, "RESPONSE_CONTENT_LENGTH"),
-            m_variable_response_protocol: AnchoredVariable::new(t, "RESPONSE_PROTOCOL"),
-            m_variable_response_status: AnchoredVariable::new(t, "RESPONSE_STATUS"),
-            m_variable_server_addr: AnchoredVariable::new(t, "SERVER_ADDR"),
-            m_variable_server_name: AnchoredVariable::new(t, "SERVER_NAME"),
-            m_variable_server_port: AnchoredVariable::new(t, "SERVER_PORT"),
-            m_variable_session_id: AnchoredVariable::new(t, "SESSIONID"),
-            m_variable_unique_id: AnchoredVariable::new(t, "UNIQUE_ID"),
-            m_variable_url_encoded_error: AnchoredVariable::new(t, "URLENCODED_ERROR"),
-            m_variable_user_id: AnchoredVariable::new(t, "USERID"),
+            m_variable_reqbody_processor: AnchoredVariable::new("REQBODY_PROCESSOR"),
+            m_variable_reqbody_charset_error: AnchoredVariable::new("REQBODY_CHARSET_ERROR"),
+            m_variable_reqbody_charset_error_msg: AnchoredVariable::new(
+                "REQBODY_CHARSET_ERROR_MSG",
+            ),
+            m_variable_resbody_charset_error: AnchoredVariable::new("RESBODY_CHARSET_ERROR"),
+            m_variable_resbody_charset_error_msg: AnchoredVariable::new(
+                "RESBODY_CHARSET_ERROR_MSG",
+            ),
+            m_variable_request_basename: AnchoredVariable::new("REQUEST_BASENAME"),
+            m_variable_request_body: AnchoredVariable::new("REQUEST_BODY"),
+            m_variable_request_body_length: AnchoredVariable::new("REQUEST_BODY_LENGTH"),
+            m_variable_request_filename: AnchoredVariable::new("REQUEST_FILENAME"),
+            m_variable_request_line: RefCell::new(AnchoredVariable::new("REQUEST_LINE")),
+            m_variable_request_method: AnchoredVariable::new("REQUEST_METHOD"),
+            m_variable_request_protocol: AnchoredVariable::new("REQUEST_PROTOCOL"),
+            m_variable_request_uri: AnchoredVariable::new("REQUEST_URI"),
+            m_variable_request_uri_raw: AnchoredVariable::new("REQUEST_URI_RAW"),
+            m_variable_resource: AnchoredVariable::new("RESOURCE"),
+            m_variable_response_body: AnchoredVariable::new("RESPONSE_BODY"),
+            m_variable_response_content_length: AnchoredVariable::new("RESPONSE_CONTENT_LENGTH"),
+            m_variable_response_protocol: AnchoredVariable::new("RESPONSE_PROTOCOL"),
+            m_variable_response_status: AnchoredVariable::new("RESPONSE_STATUS"),
+            m_variable_server_addr: AnchoredVariable::new("SERVER_ADDR"),
+            m_variable_server_name: AnchoredVariable::new("SERVER_NAME"),
+            m_variable_server_port: AnchoredVariable::new("SERVER_PORT"),
+            m_variable_session_id: RefCell::new(AnchoredVariable::new("SESSIONID")),
+            m_variable_unique_id: AnchoredVariable::new("UNIQUE_ID"),
+            m_variable_url_encoded_error: AnchoredVariable::new("URLENCODED_ERROR"),
+            m_variable_user_id: RefCell::new(AnchoredVariable::new("USERID")),
 
             m_variable_args: m_variable_args.clone(),
             m_variable_args_get: m_variable_args_get.clone(),
             m_variable_args_post: m_variable_args_post.clone(),
-            m_variable_request_headers_names: AnchoredSetVariable::new(t, "REQUEST_HEADERS_NAMES"),
-            m_variable_response_headers_names: AnchoredSetVariable::new(
-                t,
-                "RESPONSE_HEADERS_NAMES",
-            ),
-            m_variable_files_sizes: AnchoredSetVariable::new(t, "FILES_SIZES"),
-            m_variable_files_names: AnchoredSetVariable::new(t, "FILES_NAMES"),
-            m_variable_files_tmp_content: AnchoredSetVariable::new(t, "FILES_TMP_CONTENT"),
-            m_variable_multipart_file_name: AnchoredSetVariable::new(t, "MULTIPART_FILENAME"),
-            m_variable_multipart_name: AnchoredSetVariable::new(t, "MULTIPART_NAME"),
-            m_variable_matched_vars_names: AnchoredSetVariable::new(t, "MATCHED_VARS_NAMES"),
-            m_variable_matched_vars: AnchoredSetVariable::new(t, "MATCHED_VARS"),
-            m_variable_files: AnchoredSetVariable::new(t, "FILES"),
-            m_variable_request_cookies: AnchoredSetVariable::new(t, "REQUEST_COOKIES"),
-            m_variable_request_headers: AnchoredSetVariable::new(t, "REQUEST_HEADERS"),
-            m_variable_response_headers: AnchoredSetVariable::new(t, "RESPONSE_HEADERS"),
-            m_variable_geo: AnchoredSetVariable::new(t, "GEO"),
-            m_variable_request_cookies_names: AnchoredSetVariable::new(t, "REQUEST_COOKIES_NAMES"),
-            m_variable_files_tmp_names: AnchoredSetVariable::new(t, "FILES_TMPNAMES"),
-            m_variable_multipart_part_headers: AnchoredSetVariable::new(
-                t,
-                "MULTIPART_PART_HEADERS",
-            ),
+            m_variable_request_headers_names: AnchoredSetVariable::new("REQUEST_HEADERS_NAMES"),
+            m_variable_response_headers_names: AnchoredSetVariable::new("RESPONSE_HEADERS_NAMES"),
+            m_variable_files_sizes: AnchoredSetVariable::new("FILES_SIZES"),
+            m_variable_files_names: AnchoredSetVariable::new("FILES_NAMES"),
+            m_variable_files_tmp_content: AnchoredSetVariable::new("FILES_TMP_CONTENT"),
+            m_variable_multipart_file_name: AnchoredSetVariable::new("MULTIPART_FILENAME"),
+            m_variable_multipart_name: AnchoredSetVariable::new("MULTIPART_NAME"),
+            m_variable_matched_vars_names: AnchoredSetVariable::new("MATCHED_VARS_NAMES"),
+            m_variable_matched_vars: AnchoredSetVariable::new("MATCHED_VARS"),
+            m_variable_files: AnchoredSetVariable::new("FILES"),
+            m_variable_request_cookies: AnchoredSetVariable::new("REQUEST_COOKIES"),
+            m_variable_request_headers: AnchoredSetVariable::new("REQUEST_HEADERS"),
+            m_variable_response_headers: AnchoredSetVariable::new("RESPONSE_HEADERS"),
+            m_variable_geo: AnchoredSetVariable::new("GEO"),
+            m_variable_request_cookies_names: AnchoredSetVariable::new("REQUEST_COOKIES_NAMES"),
+            m_variable_files_tmp_names: AnchoredSetVariable::new("FILES_TMPNAMES"),
+            m_variable_multipart_part_headers: AnchoredSetVariable::new("MULTIPART_PART_HEADERS"),
         }
     }
 }
+
+impl Default for TransactionAnchoredVariables {
+    fn default() -> Self {
+        TransactionAnchoredVariables::new()
+    }
+}
@@ -0,0 +1,164 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Escape the bytes that are not printable ASCII as `\xHH`; with
+/// `escape_spec` the double quote and the backslash are escaped too.
+pub fn to_hex_if_needed(bytes: &[u8], escape_spec: bool) -> String {
+    let mut res = String::with_capacity(bytes.len());
+    for &c in bytes {
+        if !(32..=126).contains(&c) || (escape_spec && (c == b'"' || c == b'\\')) {
+            res.push_str(&format!("\\x{:02x}", c));
+        } else {
+            res.push(c as char);
+        }
+    }
+    res
+}
+
+/// Reverse of `to_hex_if_needed`, `\xHH` sequences back to bytes.
+pub fn from_hex_if_needed(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && bytes.get(i + 1) == Some(&b'x') {
+            let hex = bytes
+                .get(i + 2..i + 4)
+                .filter(|h| h.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|h| std::str::from_utf8(h).ok());
+            if let Some(c) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                res.push(c);
+                i += 4;
+                continue;
+            }
+        }
+        res.push(bytes[i]);
+        i += 1;
+    }
+    res
+}
+
+/// Escape `s` for a quoted field of a log line, as ModSecurity 2 does: the
+/// double quote and the backslash as `\"` and `\\`, the bytes that are not
+/// printable ASCII as `\xHH`.
+pub fn log_escape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for &c in s.as_bytes() {
+        match c {
+            b'"' => res.push_str("\\\""),
+            b'\\' => res.push_str("\\\\"),
+            32..=126 => res.push(c as char),
+            _ => res.push_str(&format!("\\x{:02x}", c)),
+        }
+    }
+    res
+}
+
+/// Reverse of `log_escape`.
+pub fn log_unescape(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            match bytes.get(i + 1) {
+                Some(&c @ (b'"' | b'\\')) => {
+                    res.push(c);
+                    i += 2;
+                    continue;
+                }
+                Some(b'x') => {
+                    let hex = bytes
+                        .get(i + 2..i + 4)
+                        .filter(|h| h.iter().all(u8::is_ascii_hexdigit))
+                        .and_then(|h| std::str::from_utf8(h).ok());
+                    if let Some(c) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                        res.push(c);
+                        i += 4;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+        }
+        res.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&res).into_owned()
+}
+
+/// Quote and escape `s` as a JSON string.
+pub fn to_json_string(s: &str) -> String {
+    serde_json::Value::from(s).to_string()
+}
+
+/// Join already serialized JSON values into an object, keeping the order of
+/// `fields` and any repeated key, e.g. HTTP headers.
+pub fn to_json_object(fields: &[(&str, String)]) -> String {
+    let members = fields
+        .iter()
+        .map(|(key, value)| format!("{}:{}", to_json_string(key), value))
+        .collect::<Vec<String>>()
+        .join(",");
+    format!("{{{}}}", members)
+}
+
+/// Truncate `s` to `amount` bytes, telling how many were omitted.
+pub fn limit_to(amount: usize, s: &str) -> String {
+    if s.len() <= amount {
+        return s.to_string();
+    }
+    let mut end = amount;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{} ({} characters omitted)", &s[..end], s.len() - end)
+}
+
+/// Percent-decode an URI, `+` is left untouched.
+pub fn uri_decode(uri: &str) -> String {
+    String::from_utf8_lossy(&percent_decode(uri.as_bytes())).into_owned()
+}
+
+fn percent_decode(bytes: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && bytes[i + 1].is_ascii_hexdigit()
+            && bytes[i + 2].is_ascii_hexdigit()
+        {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            decoded.push(u8::from_str_radix(hex, 16).unwrap_or_default());
+            i += 3;
+            continue;
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    decoded
+}
+
+/// `uri_decode` for the arguments of a query string or of an
+/// application/x-www-form-urlencoded body, where `+` is a space. The bytes
+/// are returned as decoded, in the charset of the body, see
+/// `charset::decode_argument`.
+pub fn url_decode(s: &[u8]) -> Vec<u8> {
+    let plus_as_space = s
+        .iter()
+        .map(|&b| if b == b'+' { b' ' } else { b })
+        .collect::<Vec<u8>>();
+    percent_decode(&plus_as_space)
+}
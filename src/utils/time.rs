@@ -0,0 +1,110 @@
+// Copyright 2023 young2j
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Broken down UTC time, enough to build the file and directory names and
+/// the timestamps ModSecurity writes in its logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UtcTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    /// Day of the week, 0 is Sunday.
+    pub weekday: u32,
+}
+
+pub const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+pub const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+impl UtcTime {
+    pub fn now() -> UtcTime {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Could not get system time")
+            .as_secs();
+        UtcTime::from_unix(secs)
+    }
+
+    /// Convert seconds since the epoch, see Howard Hinnant's civil_from_days.
+    pub fn from_unix(secs: u64) -> UtcTime {
+        let days = (secs / 86400) as i64;
+        let rem = secs % 86400;
+
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        UtcTime {
+            year,
+            month,
+            day,
+            hour: (rem / 3600) as u32,
+            minute: (rem % 3600 / 60) as u32,
+            second: (rem % 60) as u32,
+            // 1970-01-01 was a Thursday
+            weekday: (days + 4).rem_euclid(7) as u32,
+        }
+    }
+
+    /// e.g. 20231231
+    pub fn ymd(&self) -> String {
+        format!("{:04}{:02}{:02}", self.year, self.month, self.day)
+    }
+
+    /// e.g. 20231231-235959
+    pub fn ymd_hms(&self) -> String {
+        format!(
+            "{}-{:02}{:02}{:02}",
+            self.ymd(),
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+
+    pub fn month_abbr(&self) -> &'static str {
+        MONTHS[(self.month - 1) as usize]
+    }
+    pub fn weekday_abbr(&self) -> &'static str {
+        WEEKDAYS[self.weekday as usize]
+    }
+
+    /// Same layout as asctime(3), without the trailing newline, e.g.
+    /// `Sun Dec 31 23:59:59 2023`.
+    pub fn asctime(&self) -> String {
+        format!(
+            "{} {} {:2} {:02}:{:02}:{:02} {}",
+            self.weekday_abbr(),
+            self.month_abbr(),
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+            self.year
+        )
+    }
+}
@@ -12,25 +12,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{
-    transaction::Transaction,
-    variables::{VariableOrigin, VariableValue},
-};
+use crate::variables::{VariableOrigin, VariableValue};
 
-pub struct AnchoredVariable<'a> {
-    m_transaction: &'a Transaction,
+pub struct AnchoredVariable {
     m_offset: usize,
-    m_name: &'a str,
+    m_name: String,
     m_value: String,
     m_var: VariableValue,
 }
 
-impl<'a> AnchoredVariable<'a> {
-    pub fn new<'t: 'a>(t: &'t Transaction, name: &'a str) -> AnchoredVariable<'a> {
+impl AnchoredVariable {
+    pub fn new(name: &str) -> AnchoredVariable {
         AnchoredVariable {
-            m_transaction: t,
             m_offset: 0,
-            m_name: name,
+            m_name: name.to_string(),
             m_value: String::new(),
             m_var: VariableValue::new(name, None),
         }
@@ -40,7 +35,7 @@ impl<'a> AnchoredVariable<'a> {
         self.m_value.clear();
     }
 
-    pub fn set(&mut self, value: &'a str, offset: usize) {
+    pub fn set(&mut self, value: &str, offset: usize) {
         self.m_value = value.to_string();
         self.m_offset = offset;
 
@@ -48,7 +43,7 @@ impl<'a> AnchoredVariable<'a> {
         self.m_var.add_origin(origin);
     }
 
-    pub fn set_with_length(&mut self, value: &'a str, offset: usize, length: usize) {
+    pub fn set_with_length(&mut self, value: &str, offset: usize, length: usize) {
         self.m_value = value.to_string();
         self.m_offset = offset;
 
@@ -56,7 +51,7 @@ impl<'a> AnchoredVariable<'a> {
         self.m_var.add_origin(origin);
     }
 
-    pub fn append(&mut self, value: &'a str, offset: usize, space_separator: bool) {
+    pub fn append(&mut self, value: &str, offset: usize, space_separator: bool) {
         if space_separator && value.len() > 0 {
             self.m_value.push(' ');
             self.m_value.push_str(value);
@@ -88,18 +83,22 @@ impl<'a> AnchoredVariable<'a> {
         self.m_var.add_origin(origin);
     }
 
+    /// Origin of the current value, the last one set.
+    pub fn get_origin(&self) -> Option<&VariableOrigin> {
+        self.m_var.get_origin().back()
+    }
+
     pub fn evaluate(&self) -> &str {
         &self.m_value
     }
 
-    pub fn evaluate_variable_values(&mut self, mut l: Vec<VariableValue>) {
+    pub fn evaluate_variable_values(&self, l: &mut Vec<VariableValue>) {
         if self.m_name.is_empty() {
             return;
         }
-        self.m_var.set_value(&self.m_value);
-
-        let new_var = VariableValue::new_from(&self.m_var);
-        l.push(new_var);
+        let mut var = VariableValue::new_from(&self.m_var);
+        var.set_value(&self.m_value);
+        l.push(var);
     }
 
     pub fn resolve_first(&self) -> String {
@@ -15,22 +15,23 @@
 use std::collections::{HashMap, LinkedList};
 
 use crate::{
+    ms_dbg,
     transaction::Transaction,
+    utils::regex_cache::cached_regex,
     variables::{KeyExclusions, VariableOrigin, VariableValue},
 };
 
-pub struct AnchoredSetVariable<'a> {
-    m_transaction: &'a Transaction,
-    m_name: &'a str,
-    m_set: HashMap<&'a str, LinkedList<VariableValue>>,
+/// Keys are case insensitive, as in ModSecurity: they are stored lowercased
+/// while the `VariableValue`s keep the original spelling.
+pub struct AnchoredSetVariable {
+    m_name: String,
+    m_set: HashMap<String, LinkedList<VariableValue>>,
 }
 
-impl<'a> AnchoredSetVariable<'a> {
-    pub fn new<'t: 'a>(t: &'t Transaction, name: &'a str) -> AnchoredSetVariable<'a> {
+impl AnchoredSetVariable {
+    pub fn new(name: &str) -> AnchoredSetVariable {
         AnchoredSetVariable {
-            m_transaction: t,
-            m_name: name,
-            // todo: whether key is case insensitive?
+            m_name: name.to_string(),
             // todo: Whether concurrency safety is required?
             m_set: HashMap::with_capacity(1000),
         }
@@ -40,94 +41,102 @@ impl<'a> AnchoredSetVariable<'a> {
         self.m_set.clear()
     }
 
-    pub fn set(&mut self, key: &'a str, value: &str, offset: usize) {
+    pub fn set(&mut self, key: &str, value: &str, offset: usize) {
         let origin = VariableOrigin::new_with_props(value.len(), offset);
-        let mut var = VariableValue::new_with_collection(self.m_name, key, value);
+        let mut var = VariableValue::new_with_collection(&self.m_name, key, value);
         var.add_origin(origin);
 
         self.m_set
-            .entry(key)
+            .entry(key.to_lowercase())
             .or_insert_with(LinkedList::new)
             .push_back(var);
     }
 
-    pub fn set_with_length(&mut self, key: &'a str, value: &str, offset: usize, length: usize) {
+    pub fn set_with_length(&mut self, key: &str, value: &str, offset: usize, length: usize) {
         let origin = VariableOrigin::new_with_props(length, offset);
-        let mut var = VariableValue::new_with_collection(self.m_name, key, value);
+        let mut var = VariableValue::new_with_collection(&self.m_name, key, value);
         var.add_origin(origin);
 
         self.m_set
-            .entry(key)
+            .entry(key.to_lowercase())
             .or_insert_with(LinkedList::new)
             .push_back(var);
     }
 
-    pub fn resolve(&self, mut l: Vec<VariableValue>) {
+    /// The `resolve*` methods append the matching values to `l`, borrowed
+    /// for as long as the set is.
+    pub fn resolve<'a>(&'a self, l: &mut Vec<&'a VariableValue>) {
         self.m_set.values().for_each(|ll| {
-            ll.iter().for_each(|vv| {
-                let new_vv = VariableValue::new_from(vv);
-                l.insert(0, new_vv);
-            })
+            l.extend(ll.iter());
         });
     }
 
-    pub fn resolve_with_exclusions(&self, mut l: Vec<VariableValue>, ke: KeyExclusions) {
+    pub fn resolve_with_exclusions<'a>(
+        &'a self,
+        transaction: &Transaction,
+        l: &mut Vec<&'a VariableValue>,
+        ke: &KeyExclusions,
+    ) {
         self.m_set.iter().for_each(|(key, ll)| {
             if !ke.to_omit(key) {
-                ll.iter().for_each(|vv| {
-                    let new_vv = VariableValue::new_from(vv);
-                    l.insert(0, new_vv);
-                })
+                l.extend(ll.iter());
             } else {
-                // todo: debug info
+                ms_dbg!(transaction, 7, "Excluding key: {} from target value.", key);
             }
         });
     }
 
-    pub fn resolve_by_key(&self, key: &str, mut l: Vec<VariableValue>) {
-        if let Some(ll) = self.m_set.get(key) {
-            ll.iter().for_each(|vv| {
-                let new_vv = VariableValue::new_from(vv);
-                l.push(new_vv);
-            })
+    pub fn resolve_by_key<'a>(&'a self, key: &str, l: &mut Vec<&'a VariableValue>) {
+        if let Some(ll) = self.m_set.get(&key.to_lowercase()) {
+            l.extend(ll.iter());
         }
     }
 
+    /// Values set for `key`, in the order they were set.
+    pub fn values(&self, key: &str) -> impl Iterator<Item = &VariableValue> {
+        self.m_set.get(&key.to_lowercase()).into_iter().flatten()
+    }
+
     pub fn resolve_first(&self, key: &str) -> Option<&str> {
-        match self.m_set.get(key) {
+        match self.m_set.get(&key.to_lowercase()) {
             None => None,
             Some(ll) => ll.front().and_then(|first| Some(first.get_value())),
         }
     }
 
-    pub fn resolve_regular_expression(&self, regex: &str, mut l: Vec<VariableValue>) {
+    pub fn resolve_regular_expression<'a>(&'a self, regex: &str, l: &mut Vec<&'a VariableValue>) {
+        let Ok(re) = cached_regex(regex) else {
+            return;
+        };
         self.m_set.iter().for_each(|(key, ll)| {
-            // todo: Regex
-            // if regex_search(regex, key) {
-            ll.iter().for_each(|vv| {
-                let new_vv = VariableValue::new_from(vv);
-                l.insert(0, new_vv);
-            })
-            // }
+            if re.is_match(key) {
+                l.extend(ll.iter());
+            }
         })
     }
 
-    pub fn resolve_regular_expression_with_exlusions(
-        &self,
+    pub fn resolve_regular_expression_with_exlusions<'a>(
+        &'a self,
+        transaction: &Transaction,
         regex: &str,
-        mut l: Vec<VariableValue>,
-        ke: KeyExclusions,
+        l: &mut Vec<&'a VariableValue>,
+        ke: &KeyExclusions,
     ) {
+        let re = match cached_regex(regex) {
+            Ok(re) => re,
+            Err(err) => {
+                ms_dbg!(transaction, 4, "{}", err);
+                return;
+            }
+        };
         self.m_set.iter().for_each(|(key, ll)| {
-            // todo: Regex
-            // if regex_search(regex, key) &&
+            if !re.is_match(key) {
+                return;
+            }
             if !ke.to_omit(key) {
-                ll.iter().for_each(|vv| {
-                    let new_vv = VariableValue::new_from(vv);
-                    l.insert(0, new_vv);
-                })
+                l.extend(ll.iter());
             } else {
-                // todo: debug info
+                ms_dbg!(transaction, 7, "Excluding key: {} from target value.", key);
             }
         })
     }
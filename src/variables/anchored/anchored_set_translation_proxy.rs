@@ -12,26 +12,26 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::rc::Rc;
+use std::{cell::RefCell, rc::Rc};
 
 use crate::variables::VariableValue;
 
 use super::AnchoredSetVariable;
 
-pub struct AnchoredSetVariableTranslationProxy<'a> {
-    m_name: &'a str,
-    m_fount: Rc<AnchoredSetVariable<'a>>,
-    m_translate: fn(&'a str, Vec<VariableValue>),
+pub struct AnchoredSetVariableTranslationProxy {
+    m_name: String,
+    m_fount: Rc<RefCell<AnchoredSetVariable>>,
+    m_translate: fn(&str, &mut Vec<VariableValue>),
 }
 
-impl<'a> AnchoredSetVariableTranslationProxy<'a> {
+impl AnchoredSetVariableTranslationProxy {
     pub fn new(
-        name: &'a str,
-        fount: Rc<AnchoredSetVariable<'a>>,
-    ) -> AnchoredSetVariableTranslationProxy<'a> {
-        let m_translate = |name: &str, mut l: Vec<VariableValue>| {};
+        name: &str,
+        fount: Rc<RefCell<AnchoredSetVariable>>,
+    ) -> AnchoredSetVariableTranslationProxy {
+        let m_translate = |name: &str, l: &mut Vec<VariableValue>| {};
         AnchoredSetVariableTranslationProxy {
-            m_name: name,
+            m_name: name.to_string(),
             m_fount: fount,
             m_translate,
         }
@@ -1,24 +1,48 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, sync::Arc};
+
+use regex::Regex;
+
+use crate::utils::regex_cache::cached_regex;
 
 pub trait KeyExclusion {
     fn m_match(&self, key: &str) -> bool;
 }
 
-//
-pub struct KeyExclusionRegex {}
+// !ARGS:/^pass/
+pub struct KeyExclusionRegex {
+    m_re: Arc<Regex>,
+}
+
+impl KeyExclusionRegex {
+    pub fn new(re: &str) -> Result<KeyExclusionRegex, String> {
+        Ok(KeyExclusionRegex {
+            m_re: cached_regex(re)?,
+        })
+    }
+}
 
 impl KeyExclusion for KeyExclusionRegex {
     fn m_match(&self, key: &str) -> bool {
-        todo!()
+        self.m_re.is_match(key)
     }
 }
 
-//
-pub struct KeyExclusionString {}
+// !ARGS:password
+pub struct KeyExclusionString {
+    m_key: String,
+}
+
+impl KeyExclusionString {
+    pub fn new(key: &str) -> KeyExclusionString {
+        KeyExclusionString {
+            m_key: key.to_string(),
+        }
+    }
+}
 
 impl KeyExclusion for KeyExclusionString {
     fn m_match(&self, key: &str) -> bool {
-        todo!()
+        self.m_key.eq_ignore_ascii_case(key)
     }
 }
 
@@ -27,6 +51,9 @@ impl KeyExclusions {
     pub fn new() -> KeyExclusions {
         KeyExclusions(VecDeque::new())
     }
+    pub fn push(&mut self, ke: Box<dyn KeyExclusion>) {
+        self.0.push_back(ke);
+    }
     pub fn to_omit(&self, key: &str) -> bool {
         self.0.iter().any(|ke| ke.m_match(key))
     }